@@ -16,6 +16,24 @@ fn tick_benchmark(c: &mut Criterion) {
     }));
 }
 
+#[allow(dead_code)]
+fn kernel_benchmark(c: &mut Criterion) {
+    let (width, height) = (1000, 1000);
+    let mut group = c.benchmark_group("Tick Kernels");
+
+    for kernel in [game_of_life::Kernel::ActiveCells, game_of_life::Kernel::BitSliced] {
+        let mut universe = game_of_life::Universe::new_oscillators(width, height, 10);
+        universe.set_kernel(kernel);
+
+        group.bench_function(
+            BenchmarkId::new("tick", format!("{:?}", kernel)),
+            |b| b.iter(|| {
+                universe.tick();
+        }));
+    }
+    group.finish();
+}
+
 #[allow(dead_code)]
 fn live_neighbours_benchmark(c: &mut Criterion) {
     let (width, height) = (200, 200);
@@ -97,5 +115,28 @@ fn bench_get_neighbours(c: &mut Criterion) {
     }
 }
 
-criterion_group!(benches, tick_benchmark);
+// Tick with the default kernel on a pool of each size, run with
+// --features parallel
+#[cfg(feature = "parallel")]
+fn thread_scaling_benchmark(c: &mut Criterion) {
+    let (width, height) = (2000, 2000);
+    let mut group = c.benchmark_group("Thread Scaling");
+
+    for threads in [1, 2, 4, 8] {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+        let mut universe = game_of_life::Universe::new_oscillators(width, height, 10);
+
+        group.bench_function(
+            BenchmarkId::new("tick", threads),
+            |b| b.iter(|| {
+                pool.install(|| universe.tick());
+        }));
+    }
+    group.finish();
+}
+
+#[cfg(not(feature = "parallel"))]
+criterion_group!(benches, tick_benchmark, kernel_benchmark);
+#[cfg(feature = "parallel")]
+criterion_group!(benches, tick_benchmark, kernel_benchmark, thread_scaling_benchmark);
 criterion_main!(benches);
\ No newline at end of file
@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+use crate::{Pattern, RuleError, SparseUniverse};
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PatternKind {
+    StillLife,
+    Oscillator,
+    Spaceship,
+    // Dies out, grows, settles into something else, or doesn't repeat
+    // within the generations looked at
+    Other,
+}
+
+// What running a pattern on its own showed. The period, bounding box,
+// population range and heat are over one period of the cycle the pattern
+// is in, or over every generation looked at for other patterns.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PatternAnalysis {
+    kind: PatternKind,
+    period: u32,
+    dx: i32,
+    dy: i32,
+    width: u32,
+    height: u32,
+    min_population: u32,
+    max_population: u32,
+    heat: f64,
+}
+
+#[wasm_bindgen]
+impl PatternAnalysis {
+    pub fn kind(&self) -> PatternKind {
+        self.kind
+    }
+
+    // Generations until the pattern repeats, or 0 if it doesn't
+    pub fn period(&self) -> u32 {
+        self.period
+    }
+
+    // Columns a spaceship moves right each period
+    pub fn dx(&self) -> i32 {
+        self.dx
+    }
+
+    // Rows a spaceship moves down each period
+    pub fn dy(&self) -> i32 {
+        self.dy
+    }
+
+    // Largest width of any phase
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    // Largest height of any phase
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn min_population(&self) -> u32 {
+        self.min_population
+    }
+
+    pub fn max_population(&self) -> u32 {
+        self.max_population
+    }
+
+    // Average number of cells that change each generation
+    pub fn heat(&self) -> f64 {
+        self.heat
+    }
+
+    // A spaceship's speed in the usual notation, e.g. "c/4 diagonal" for
+    // the glider or "(2,1)c/6" for an oblique ship, or an empty string for
+    // anything else
+    pub fn speed(&self) -> String {
+        if self.kind != PatternKind::Spaceship {
+            return String::new();
+        }
+        let (dx, dy) = (self.dx.unsigned_abs(), self.dy.unsigned_abs());
+        // Larger displacement first, as in "(2,1)c/6"
+        let (major, minor) = (dx.max(dy), dx.min(dy));
+        if minor != 0 && major != minor {
+            let divisor = gcd(gcd(major, minor), self.period);
+            return format!("({},{})c/{}", major / divisor, minor / divisor, self.period / divisor);
+        }
+
+        let divisor = gcd(major, self.period);
+        let (distance, period) = (major / divisor, self.period / divisor);
+        let direction = if minor == 0 { "orthogonal" } else { "diagonal" };
+        match distance {
+            1 => format!("c/{} {}", period, direction),
+            _ => format!("{}c/{} {}", distance, period, direction),
+        }
+    }
+}
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+// One generation's live cells, sorted, with the top left of their
+// bounding box
+pub(crate) struct Phase {
+    pub cells: Vec<(i64, i64)>,
+    pub origin: (i64, i64),
+    pub size: (u32, u32),
+}
+
+impl Phase {
+    fn of(universe: &SparseUniverse) -> Phase {
+        let mut cells: Vec<(i64, i64)> = universe.live_cells().collect();
+        cells.sort_unstable();
+        let top = cells.first().map_or(0, |cell| cell.0);
+        let bottom = cells.last().map_or(-1, |cell| cell.0);
+        let left = cells.iter().map(|cell| cell.1).min().unwrap_or(0);
+        let right = cells.iter().map(|cell| cell.1).max().unwrap_or(-1);
+        Phase { cells, origin: (top, left), size: ((right - left + 1) as u32, (bottom - top + 1) as u32) }
+    }
+
+    // The cells relative to the bounding box, the same for any translation
+    pub fn shape(&self) -> Vec<(i64, i64)> {
+        self.cells.iter().map(|&(row, col)| (row - self.origin.0, col - self.origin.1)).collect()
+    }
+
+    // Number of cells alive in one phase and not the other
+    fn changes(&self, other: &Phase) -> usize {
+        let (mut i, mut j, mut same) = (0, 0, 0);
+        while i < self.cells.len() && j < other.cells.len() {
+            match self.cells[i].cmp(&other.cells[j]) {
+                std::cmp::Ordering::Less => i += 1,
+                std::cmp::Ordering::Greater => j += 1,
+                std::cmp::Ordering::Equal => {
+                    same += 1;
+                    i += 1;
+                    j += 1;
+                },
+            }
+        }
+        self.cells.len() + other.cells.len() - 2 * same
+    }
+}
+
+// Run a pattern on its own in an unbounded universe under its rule for up
+// to max_generations, and classify it by whether and how it repeats. Only
+// a pattern that's back to its starting shape counts as a still life,
+// oscillator or spaceship, not one that turns into one later.
+#[wasm_bindgen]
+pub fn analyse_pattern(pattern: &Pattern, max_generations: u32) -> Result<PatternAnalysis, RuleError> {
+    let universe = SparseUniverse::from_universe(pattern)?;
+    Ok(analyse_sparse(universe, max_generations).0)
+}
+
+// Analyse whatever is in a sparse universe, also returning the phases the
+// analysis covers: one period of a cycle, or every generation looked at
+pub(crate) fn analyse_sparse(mut universe: SparseUniverse, max_generations: u32) -> (PatternAnalysis, Vec<Phase>) {
+    let mut phases = vec![Phase::of(&universe)];
+    let mut shapes = HashMap::new();
+    shapes.insert(phases[0].shape(), 0);
+    let mut repeat = None;
+
+    for generation in 1..=max_generations as usize {
+        universe.tick();
+        let phase = Phase::of(&universe);
+        let shape = phase.shape();
+        phases.push(phase);
+        if phases[generation].cells.is_empty() {
+            break;
+        }
+        if let Some(&start) = shapes.get(&shape) {
+            repeat = Some((start, generation));
+            break;
+        }
+        shapes.insert(shape, generation);
+    }
+
+    let (kind, period, (dx, dy)) = match repeat {
+        Some((0, end)) => {
+            let (first, last) = (&phases[0], &phases[end]);
+            let displacement = ((last.origin.1 - first.origin.1) as i32, (last.origin.0 - first.origin.0) as i32);
+            let kind = match (displacement, end) {
+                ((0, 0), 1) => PatternKind::StillLife,
+                ((0, 0), _) => PatternKind::Oscillator,
+                _ => PatternKind::Spaceship,
+            };
+            (kind, end as u32, displacement)
+        },
+        _ => (PatternKind::Other, 0, (0, 0)),
+    };
+
+    let changes: usize = phases.windows(2).map(|pair| pair[0].changes(&pair[1])).sum();
+    let heat = changes as f64 / (phases.len() - 1).max(1) as f64;
+    // A cycle's last phase is its first one again
+    if kind != PatternKind::Other {
+        phases.truncate(period as usize);
+    }
+    let analysis = PatternAnalysis {
+        kind,
+        period,
+        dx,
+        dy,
+        width: phases.iter().map(|phase| phase.size.0).max().unwrap_or(0),
+        height: phases.iter().map(|phase| phase.size.1).max().unwrap_or(0),
+        min_population: phases.iter().map(|phase| phase.cells.len() as u32).min().unwrap_or(0),
+        max_population: phases.iter().map(|phase| phase.cells.len() as u32).max().unwrap_or(0),
+        heat,
+    };
+    (analysis, phases)
+}
+
+
+//                      Testing
+// ======================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn analyse(pattern: Pattern) -> PatternAnalysis {
+        analyse_pattern(&pattern, 500).unwrap()
+    }
+
+    #[test]
+    fn test_still_lifes_and_oscillators() {
+        let mut block = Pattern::new(4, 4);
+        block.set_cells(&[(1, 1), (1, 2), (2, 1), (2, 2)]);
+        let report = analyse(block);
+        assert_eq!((report.kind(), report.period(), report.heat()), (PatternKind::StillLife, 1, 0.0));
+        assert_eq!((report.width(), report.height(), report.min_population()), (2, 2, 4));
+
+        let report = analyse(Pattern::blinker());
+        assert_eq!((report.kind(), report.period(), report.heat()), (PatternKind::Oscillator, 2, 4.0));
+        assert_eq!((report.width(), report.height()), (3, 3));
+
+        let report = analyse(Pattern::pulsar());
+        assert_eq!((report.kind(), report.period()), (PatternKind::Oscillator, 3));
+        assert_eq!((report.min_population(), report.max_population()), (48, 72));
+        assert_eq!(analyse(Pattern::pentadecathlon()).period(), 15);
+        assert_eq!(report.speed(), "");
+    }
+
+    #[test]
+    fn test_spaceships() {
+        let report = analyse(Pattern::glider());
+        assert_eq!((report.kind(), report.period()), (PatternKind::Spaceship, 4));
+        assert_eq!((report.dx().abs(), report.dy().abs()), (1, 1));
+        assert_eq!(report.speed(), "c/4 diagonal");
+        assert_eq!((report.min_population(), report.max_population()), (5, 5));
+
+        for ship in [Pattern::lightweight_spaceship(), Pattern::midweight_spaceship(), Pattern::heavyweight_spaceship()] {
+            let report = analyse(ship);
+            assert_eq!((report.kind(), report.period()), (PatternKind::Spaceship, 4));
+            assert_eq!(report.speed(), "c/2 orthogonal");
+        }
+    }
+
+    #[test]
+    fn test_speeds() {
+        let ship = |period, dx, dy| PatternAnalysis {
+            kind: PatternKind::Spaceship,
+            period,
+            dx,
+            dy,
+            width: 0,
+            height: 0,
+            min_population: 0,
+            max_population: 0,
+            heat: 0.0,
+        };
+        assert_eq!(ship(5, 0, -2).speed(), "2c/5 orthogonal");
+        assert_eq!(ship(8, -2, 2).speed(), "c/4 diagonal");
+        // Oblique ships give both displacements, like Sir Robin's
+        assert_eq!(ship(6, 1, -2).speed(), "(2,1)c/6");
+        assert_eq!(ship(12, -4, -2).speed(), "(2,1)c/6");
+        assert_eq!(ship(7, 3, 1).speed(), "(3,1)c/7");
+    }
+
+    #[test]
+    fn test_other_patterns() {
+        // The R-pentomino takes over a thousand generations to settle
+        let report = analyse(Pattern::r_pentomino());
+        assert_eq!((report.kind(), report.period()), (PatternKind::Other, 0));
+        assert!(report.max_population() > 100);
+
+        // Diehard dies out
+        let report = analyse_pattern(&Pattern::diehard(), 200).unwrap();
+        assert_eq!(report.kind(), PatternKind::Other);
+        assert_eq!(report.min_population(), 0);
+    }
+}
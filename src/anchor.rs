@@ -0,0 +1,65 @@
+use std::{error::Error, fmt};
+use wasm_bindgen::prelude::*;
+
+// Point of a universe that stays put when it's resized, so cells keep
+// their place relative to it
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Centre,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    // How far cells move down and right when a universe goes from the old
+    // size to the new one
+    pub(crate) fn offsets(&self, old: (usize, usize), new: (usize, usize)) -> (isize, isize) {
+        let (old_width, old_height) = (old.0 as isize, old.1 as isize);
+        let (new_width, new_height) = (new.0 as isize, new.1 as isize);
+        // 0 keeps the start of an axis in place, 1 the middle, 2 the end
+        let (vertical, horizontal) = match self {
+            Anchor::TopLeft => (0, 0),
+            Anchor::Top => (0, 1),
+            Anchor::TopRight => (0, 2),
+            Anchor::Left => (1, 0),
+            Anchor::Centre => (1, 1),
+            Anchor::Right => (1, 2),
+            Anchor::BottomLeft => (2, 0),
+            Anchor::Bottom => (2, 1),
+            Anchor::BottomRight => (2, 2),
+        };
+        let offset = |position: isize, old: isize, new: isize| (new - old) * position / 2;
+        (offset(vertical, old_height, new_height), offset(horizontal, old_width, new_width))
+    }
+}
+
+// A size or topology a universe can't take
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GeometryError {
+    // Spheres join the top edge to the left one, so need as many rows as
+    // columns
+    NonSquareSphere { width: u32, height: u32 },
+}
+
+impl fmt::Display for GeometryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GeometryError::NonSquareSphere { width, height } => write!(f, "a sphere must be square, not {}x{}", width, height),
+        }
+    }
+}
+
+impl Error for GeometryError {}
+
+impl From<GeometryError> for JsValue {
+    fn from(error: GeometryError) -> JsValue {
+        js_sys::Error::new(&error.to_string()).into()
+    }
+}
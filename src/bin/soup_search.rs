@@ -0,0 +1,365 @@
+// Headless soup search. Runs seeded random soups until they settle, takes
+// a census of each and writes the object totals, with the seeds of the
+// rarer objects, to a file. For example
+//
+//     cargo run --release --bin soup_search -- --soups 10000 --output rare.txt
+//
+// Soup n's seed is the string "<prefix><n>", so any soup in the summary
+// can be recreated with Universe::fill_soup_region and seed_from_str.
+
+use std::{
+    collections::BTreeMap,
+    env,
+    fs::File,
+    io::{self, BufWriter, Write},
+    process,
+};
+
+use game_of_life::{seed_from_str, Census, Universe};
+
+// Longest period looked for while waiting for a soup to settle, more than
+// any oscillator common in ash
+const MAX_ASH_PERIOD: u32 = 240;
+
+// Ships that escape the soup end up as debris against the edges of the
+// plane, so objects this close to an edge are left out of the census
+const EDGE_MARGIN: u32 = 6;
+
+const USAGE: &str = "\
+Usage: soup_search [options]
+
+Options:
+  --soups N            number of soups to run (default 1000)
+  --prefix TEXT        seeds are TEXT followed by the soup number (default \"soup_\")
+  --size WxH           size of the universe, a bounded plane (default 128x128)
+  --soup-size WxH      size of the random soup in its centre (default 16x16)
+  --density D          chance each soup cell starts alive (default 0.5)
+  --rule RULE          rulestring to run the soups under (default B3/S23)
+  --max-generations N  give up on soups still changing after N (default 20000)
+  --rare N             list the seeds of objects seen in at most N soups (default 3)
+  --output FILE        where to write the summary (default soup_search.txt)
+  --help               show this message";
+
+#[derive(Clone, Debug, PartialEq)]
+struct Options {
+    soups: u32,
+    prefix: String,
+    size: (u32, u32),
+    soup_size: (u32, u32),
+    density: f64,
+    rule: String,
+    max_generations: u32,
+    rare: usize,
+    output: String,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            soups: 1000,
+            prefix: "soup_".to_string(),
+            size: (128, 128),
+            soup_size: (16, 16),
+            density: 0.5,
+            rule: "B3/S23".to_string(),
+            max_generations: 20000,
+            rare: 3,
+            output: "soup_search.txt".to_string(),
+        }
+    }
+}
+
+impl Options {
+    // Options from the command line arguments, after the program name
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+        let mut options = Options::default();
+        while let Some(arg) = args.next() {
+            if arg == "--help" {
+                return Err(USAGE.to_string());
+            }
+            let value = args.next().ok_or(format!("{} needs a value", arg))?;
+            match arg.as_str() {
+                "--soups" => options.soups = parse_number(&arg, &value)?,
+                "--prefix" => options.prefix = value,
+                "--size" => options.size = parse_size(&arg, &value)?,
+                "--soup-size" => options.soup_size = parse_size(&arg, &value)?,
+                "--density" => options.density = parse_number(&arg, &value)?,
+                "--rule" => options.rule = value,
+                "--max-generations" => options.max_generations = parse_number(&arg, &value)?,
+                "--rare" => options.rare = parse_number(&arg, &value)?,
+                "--output" => options.output = value,
+                _ => return Err(format!("unknown option {}\n\n{}", arg, USAGE)),
+            }
+        }
+
+        let inside = (options.size.0.saturating_sub(2 * EDGE_MARGIN), options.size.1.saturating_sub(2 * EDGE_MARGIN));
+        if options.soup_size.0 > inside.0 || options.soup_size.1 > inside.1 {
+            return Err(format!("the soup must fit in the universe, at least {} cells from the edges", EDGE_MARGIN));
+        }
+        if options.rule.contains(':') {
+            return Err("--rule can't have a bounded grid, soups run on a plane the size of the universe".to_string());
+        }
+        if !(0.0..=1.0).contains(&options.density) {
+            return Err("the density must be between 0 and 1".to_string());
+        }
+        Ok(options)
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("{} can't be {:?}", option, value))
+}
+
+fn parse_size(option: &str, value: &str) -> Result<(u32, u32), String> {
+    let (width, height) = value.split_once('x').ok_or(format!("{} should look like 64x64", option))?;
+    let size = (parse_number(option, width)?, parse_number(option, height)?);
+    if size.0 == 0 || size.1 == 0 {
+        return Err(format!("{} can't be empty", option));
+    }
+    Ok(size)
+}
+
+// Run one soup until it settles into a cycle, and take its census, or
+// None if it's still changing after the most generations allowed. The
+// universe is a bounded plane, so ships that escape don't wrap round and
+// crash into the ash.
+fn run_soup(options: &Options, seed: &str) -> Result<Option<Census>, String> {
+    let (width, height) = options.size;
+    let (soup_width, soup_height) = options.soup_size;
+    let mut universe = Universe::new(width, height);
+    universe.set_rulestring(&format!("{}:P{},{}", options.rule, width, height)).map_err(|err| err.to_string())?;
+    universe.fill_soup_region(
+        seed_from_str(seed),
+        options.density,
+        (height - soup_height) / 2,
+        (width - soup_width) / 2,
+        soup_width,
+        soup_height,
+    );
+
+    universe.enable_cycle_detection(MAX_ASH_PERIOD);
+    while universe.cycle_report().is_none() {
+        if universe.generation() >= options.max_generations as u64 {
+            return Ok(None);
+        }
+        universe.tick();
+    }
+    clear_edge_debris(&mut universe);
+    universe.census().map(Some).map_err(|err| err.to_string())
+}
+
+// Kill every object with a cell within EDGE_MARGIN of an edge. Cells
+// within two of each other count as one object, as in the census, so
+// nothing is left half removed.
+fn clear_edge_debris(universe: &mut Universe) {
+    let (width, height) = (universe.width() as usize, universe.height() as usize);
+    let margin = EDGE_MARGIN as usize;
+    let cells = universe.get_cells();
+    let mut debris: Vec<usize> = cells.ones()
+        .filter(|idx| {
+            let (row, col) = (idx / width, idx % width);
+            row < margin || col < margin || row + margin >= height || col + margin >= width
+        })
+        .collect();
+    let mut remaining = cells.clone();
+    for &idx in debris.iter() {
+        remaining.set(idx, false);
+    }
+
+    let mut next = 0;
+    while next < debris.len() {
+        let (row, col) = (debris[next] / width, debris[next] % width);
+        next += 1;
+        for r in row.saturating_sub(2)..(row + 3).min(height) {
+            for c in col.saturating_sub(2)..(col + 3).min(width) {
+                let idx = r * width + c;
+                if remaining[idx] {
+                    remaining.set(idx, false);
+                    debris.push(idx);
+                }
+            }
+        }
+    }
+    for idx in debris {
+        universe.toggle_cell((idx / width) as u32, (idx % width) as u32);
+    }
+}
+
+// One kind of object over the whole search. Seeds are only kept while
+// the object is rare enough to list them.
+#[derive(Clone, Debug, Default)]
+struct Tally {
+    name: Option<&'static str>,
+    count: u64,
+    soups: usize,
+    seeds: Vec<String>,
+}
+
+// Totals of every object found, and the soups that never settled
+#[derive(Clone, Debug, Default)]
+struct Summary {
+    soups: u32,
+    objects: BTreeMap<String, Tally>,
+    unsettled: Vec<String>,
+}
+
+impl Summary {
+    fn add(&mut self, seed: &str, census: Option<Census>, rare: usize) {
+        self.soups += 1;
+        let census = match census {
+            Some(census) => census,
+            None => {
+                self.unsettled.push(seed.to_string());
+                return;
+            },
+        };
+        for entry in census.entries() {
+            let tally = self.objects.entry(entry.apgcode.clone()).or_default();
+            tally.name = entry.name;
+            tally.count += entry.count as u64;
+            tally.soups += 1;
+            if tally.soups <= rare {
+                tally.seeds.push(seed.to_string());
+            }
+            else {
+                tally.seeds.clear();
+            }
+        }
+    }
+
+    fn write<W: Write>(&self, out: &mut W, options: &Options) -> io::Result<()> {
+        writeln!(out, "# {} soups of {}x{} at density {} on a {}x{} plane under {}",
+            self.soups, options.soup_size.0, options.soup_size.1, options.density,
+            options.size.0, options.size.1, options.rule)?;
+        writeln!(out, "# Seeds are \"{}\" followed by the soup number, hashed with seed_from_str", options.prefix)?;
+
+        // Most common first
+        let mut objects: Vec<(&String, &Tally)> = self.objects.iter().collect();
+        objects.sort_by(|a, b| b.1.count.cmp(&a.1.count).then_with(|| a.0.cmp(b.0)));
+        writeln!(out, "\n## Census")?;
+        for (apgcode, tally) in objects.iter() {
+            match tally.name {
+                Some(name) => writeln!(out, "{} {} {}", tally.count, apgcode, name)?,
+                None => writeln!(out, "{} {}", tally.count, apgcode)?,
+            }
+        }
+
+        writeln!(out, "\n## Rare objects, seen in at most {} soups", options.rare)?;
+        for (apgcode, tally) in objects.iter().rev().filter(|(_, tally)| tally.soups <= options.rare) {
+            writeln!(out, "{}: {}", apgcode, tally.seeds.join(" "))?;
+        }
+
+        writeln!(out, "\n## Soups still changing after {} generations", options.max_generations)?;
+        for seed in self.unsettled.iter() {
+            writeln!(out, "{}", seed)?;
+        }
+        Ok(())
+    }
+}
+
+fn search(options: &Options) -> Result<Summary, String> {
+    let mut summary = Summary::default();
+    for n in 0..options.soups {
+        let seed = format!("{}{}", options.prefix, n);
+        summary.add(&seed, run_soup(options, &seed)?, options.rare);
+        if (n + 1) % 100 == 0 {
+            eprintln!("{} of {} soups done", n + 1, options.soups);
+        }
+    }
+    Ok(summary)
+}
+
+fn main() {
+    let options = match Options::parse(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            process::exit(2);
+        },
+    };
+
+    let summary = search(&options).unwrap_or_else(|message| {
+        eprintln!("{}", message);
+        process::exit(1);
+    });
+    let written = File::create(&options.output).and_then(|file| {
+        let mut out = BufWriter::new(file);
+        summary.write(&mut out, &options)?;
+        out.flush()
+    });
+    match written {
+        Ok(()) => eprintln!("Wrote the summary of {} soups to {}", summary.soups, options.output),
+        Err(err) => {
+            eprintln!("Couldn't write {}: {}", options.output, err);
+            process::exit(1);
+        },
+    }
+}
+
+
+//                      Testing
+// ======================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse_options() {
+        assert_eq!(parse(&[]).unwrap(), Options::default());
+        let options = parse(&["--soups", "50", "--size", "64x32", "--density", "0.3", "--output", "out.txt"]).unwrap();
+        assert_eq!((options.soups, options.size, options.density), (50, (64, 32), 0.3));
+        assert_eq!(options.output, "out.txt");
+
+        assert!(parse(&["--soups"]).is_err());
+        assert!(parse(&["--size", "64"]).is_err());
+        assert!(parse(&["--density", "2"]).is_err());
+        assert!(parse(&["--soup-size", "100x100", "--size", "64x64"]).is_err());
+        assert!(parse(&["--colour", "red"]).is_err());
+        assert!(parse(&["--soup-size", "60x60", "--size", "64x64"]).is_err());
+        assert!(parse(&["--rule", "B3/S23:T64,64"]).is_err());
+    }
+
+    #[test]
+    fn test_search() {
+        let options = parse(&["--soups", "4", "--size", "64x64", "--rare", "1"]).unwrap();
+        // A seed always gives the same soup and the same ash
+        let census = run_soup(&options, "soup_1").unwrap().unwrap();
+        assert_eq!(census.summary(), "4 blocks, 3 beehives, 2 blinkers, 2 boats");
+
+        let summary = search(&options).unwrap();
+        assert_eq!(summary.soups, 4);
+        assert!(summary.unsettled.is_empty());
+        let tally = |apgcode: &str| {
+            let tally = &summary.objects[apgcode];
+            (tally.count, tally.soups, tally.seeds.clone())
+        };
+        // Only rare objects keep their seeds
+        assert_eq!(tally("xp2_7"), (13, 4, vec![]));
+        assert_eq!(tally("xs4_33"), (13, 3, vec![]));
+        assert_eq!(tally("xp2_318c"), (1, 1, vec!["soup_0".to_string()]));
+
+        let mut out = Vec::new();
+        summary.write(&mut out, &options).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.starts_with("# 4 soups of 16x16 at density 0.5 on a 64x64 plane under B3/S23"));
+        assert!(text.contains("## Rare objects, seen in at most 1 soups\nxp2_318c: soup_0\n"));
+
+        let bad_rule = parse(&["--soups", "1", "--rule", "B3/S23/X"]).unwrap();
+        assert!(search(&bad_rule).is_err());
+    }
+
+    #[test]
+    fn test_edge_debris_is_cleared() {
+        // A block against the edge goes, and so does the blinker close
+        // enough to it to count as the same object, but not the one in the
+        // middle
+        let mut universe = Universe::new(30, 30);
+        universe.set_cells(&[(0, 10), (0, 11), (1, 10), (1, 11), (3, 10), (3, 11), (3, 12), (15, 14), (15, 15), (15, 16)]);
+        clear_edge_debris(&mut universe);
+        assert_eq!(universe.get_cells().ones().collect::<Vec<_>>(), vec![15 * 30 + 14, 15 * 30 + 15, 15 * 30 + 16]);
+    }
+}
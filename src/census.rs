@@ -0,0 +1,429 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use fixedbitset::FixedBitSet;
+use wasm_bindgen::prelude::*;
+
+use crate::analysis::{self, Phase};
+use crate::{PatternAnalysis, PatternKind, Rule, RuleError, SparseUniverse, Universe};
+
+// Generations an object is run on its own for to find its period
+const MAX_PERIOD: u32 = 1000;
+
+// Largest bounding box apgcodes are encoded for, as in apgsearch
+const MAX_ENCODED_SIZE: u32 = 40;
+
+// Digits of the extended Wechsler format apgcodes are written in
+const DIGITS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+// Common objects in Conway's Life by apgcode, with their names
+const CATALOGUE: &[(&str, &str, &str)] = &[
+    ("xs4_33", "block", "blocks"),
+    ("xs6_696", "beehive", "beehives"),
+    ("xs7_2596", "loaf", "loaves"),
+    ("xs5_253", "boat", "boats"),
+    ("xs6_356", "ship", "ships"),
+    ("xs4_252", "tub", "tubs"),
+    ("xs8_6996", "pond", "ponds"),
+    ("xs6_25a4", "barge", "barges"),
+    ("xs7_25ac", "long boat", "long boats"),
+    ("xs7_178c", "eater 1", "eater 1s"),
+    ("xp2_7", "blinker", "blinkers"),
+    ("xp2_7e", "toad", "toads"),
+    ("xp2_318c", "beacon", "beacons"),
+    ("xp15_4r4z4r4", "pentadecathlon", "pentadecathlons"),
+    ("xq4_153", "glider", "gliders"),
+    ("xq4_6frc", "lightweight spaceship", "lightweight spaceships"),
+    ("xq4_27dee6", "middleweight spaceship", "middleweight spaceships"),
+    ("xq4_27deee6", "heavyweight spaceship", "heavyweight spaceships"),
+];
+
+// How many of one kind of object a census found
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CensusEntry {
+    pub apgcode: String,
+    // Common name, for objects in the catalogue under Conway's Life
+    pub name: Option<&'static str>,
+    plural: Option<&'static str>,
+    pub count: u32,
+}
+
+// The objects a universe's live cells make up, most common first
+#[wasm_bindgen]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Census {
+    entries: Vec<CensusEntry>,
+}
+
+#[wasm_bindgen]
+impl Census {
+    // Number of different kinds of object
+    pub fn len(&self) -> u32 {
+        self.entries.len() as u32
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn apgcode(&self, index: u32) -> String {
+        self.entries.get(index as usize).map_or(String::new(), |entry| entry.apgcode.clone())
+    }
+
+    // The common name of a kind of object, or its apgcode if it hasn't got one
+    pub fn name(&self, index: u32) -> String {
+        self.entries.get(index as usize)
+            .map_or(String::new(), |entry| entry.name.unwrap_or(&entry.apgcode).to_string())
+    }
+
+    pub fn count(&self, index: u32) -> u32 {
+        self.entries.get(index as usize).map_or(0, |entry| entry.count)
+    }
+
+    // How many objects there are with a common name or apgcode
+    pub fn count_of(&self, name: &str) -> u32 {
+        self.entries.iter()
+            .find(|entry| entry.apgcode == name || entry.name == Some(name))
+            .map_or(0, |entry| entry.count)
+    }
+
+    // Number of objects of every kind together
+    pub fn total(&self) -> u32 {
+        self.entries.iter().map(|entry| entry.count).sum()
+    }
+
+    // A line like "42 blocks, 17 blinkers, 3 gliders"
+    pub fn summary(&self) -> String {
+        let parts: Vec<String> = self.entries.iter().map(|entry| {
+            let name = match entry.count {
+                1 => entry.name,
+                _ => entry.plural,
+            };
+            format!("{} {}", entry.count, name.unwrap_or(&entry.apgcode))
+        }).collect();
+        parts.join(", ")
+    }
+}
+
+// Public methods not exposed to JavaScript
+impl Census {
+    pub fn entries(&self) -> &[CensusEntry] {
+        &self.entries
+    }
+}
+
+// Separate a universe's live cells into objects and count each kind, the
+// way apgsearch takes a census of a settled soup. Cells within two of each
+// other are grouped, since only those can affect each other next
+// generation, then each group is run on its own under the universe's rule
+// to find the period and canonical form that identify it.
+pub(crate) fn take(universe: &Universe) -> Result<Census, RuleError> {
+    if universe.block_rule.is_some() {
+        return Err(RuleError::Unsupported("censuses under block rules"));
+    }
+    // Objects are run on an unbounded plane, which not every rule can use
+    SparseUniverse::check_rule(&universe.rule)?;
+
+    let mut counts: HashMap<String, u32> = HashMap::new();
+    for cluster in clusters(universe) {
+        for apgcode in identify(&cluster, &universe.rule) {
+            *counts.entry(apgcode).or_insert(0) += 1;
+        }
+    }
+
+    let conway = universe.rule == Rule::conway();
+    let mut entries: Vec<CensusEntry> = counts.into_iter().map(|(apgcode, count)| {
+        let known = CATALOGUE.iter().find(|&&(code, _, _)| conway && code == apgcode);
+        CensusEntry { name: known.map(|known| known.1), plural: known.map(|known| known.2), apgcode, count }
+    }).collect();
+    entries.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.apgcode.cmp(&b.apgcode)));
+    Ok(Census { entries })
+}
+
+// Group the live cells within two cells of each other. Positions are
+// followed across the edges, so an object crossing an edge keeps its
+// shape rather than being split in two.
+fn clusters(universe: &Universe) -> Vec<Vec<(i64, i64)>> {
+    let (width, height) = (universe.width, universe.height);
+    let cells = universe.get_cells();
+    let mut seen = FixedBitSet::with_capacity(width * height);
+    let mut clusters = Vec::new();
+
+    for start in cells.ones() {
+        if seen[start] {
+            continue;
+        }
+        seen.insert(start);
+        let mut cluster = Vec::new();
+        let mut queue = VecDeque::new();
+        queue.push_back((start, ((start / width) as i64, (start % width) as i64)));
+        while let Some((idx, (row, col))) = queue.pop_front() {
+            cluster.push((row, col));
+            let (grid_row, grid_col) = ((idx / width) as isize, (idx % width) as isize);
+            for dr in -2..=2 {
+                for dc in -2..=2 {
+                    if let Some((r, c)) = universe.topology.wrap(grid_row + dr, grid_col + dc, width, height) {
+                        let neighbour = r * width + c;
+                        if cells[neighbour] && !seen[neighbour] {
+                            seen.insert(neighbour);
+                            queue.push_back((neighbour, (row + dr as i64, col + dc as i64)));
+                        }
+                    }
+                }
+            }
+        }
+        clusters.push(cluster);
+    }
+    clusters
+}
+
+// Split cells into the groups connected through neighbouring cells
+fn pieces(cells: &[(i64, i64)]) -> Vec<Vec<(i64, i64)>> {
+    let mut unseen: HashSet<(i64, i64)> = cells.iter().cloned().collect();
+    let mut pieces = Vec::new();
+    for &start in cells {
+        if !unseen.remove(&start) {
+            continue;
+        }
+        let mut piece = vec![start];
+        let mut next = 0;
+        while next < piece.len() {
+            let (row, col) = piece[next];
+            next += 1;
+            for dr in -1..=1 {
+                for dc in -1..=1 {
+                    if unseen.remove(&(row + dr, col + dc)) {
+                        piece.push((row + dr, col + dc));
+                    }
+                }
+            }
+        }
+        pieces.push(piece);
+    }
+    pieces
+}
+
+fn sparse(cells: &[(i64, i64)], rule: &Rule) -> SparseUniverse {
+    let mut universe = SparseUniverse::new();
+    universe.set_rule(rule).expect("rule checked when the census started");
+    for &(row, col) in cells {
+        universe.set_cell(row, col, true);
+    }
+    universe
+}
+
+// The apgcodes of the objects in a group of cells. Pieces of a group that
+// each repeat and don't interact are separate objects, so a pseudo still
+// life such as two blocks side by side counts as two blocks.
+fn identify(cluster: &[(i64, i64)], rule: &Rule) -> Vec<String> {
+    let pieces = pieces(cluster);
+    if pieces.len() > 1 {
+        let analysed: Vec<_> = pieces.iter()
+            .map(|piece| analysis::analyse_sparse(sparse(piece, rule), MAX_PERIOD))
+            .collect();
+        if analysed.iter().all(|(analysis, _)| analysis.kind() != PatternKind::Other) {
+            let generations = analysed.iter()
+                .fold(1u64, |periods, (analysis, _)| lcm(periods, analysis.period() as u64))
+                .min(MAX_PERIOD as u64);
+            if independent(cluster, &pieces, rule, generations as u32) {
+                return analysed.iter().map(|(analysis, phases)| apgcode(analysis, phases)).collect();
+            }
+        }
+    }
+    let (analysis, phases) = analysis::analyse_sparse(sparse(cluster, rule), MAX_PERIOD);
+    vec![apgcode(&analysis, &phases)]
+}
+
+fn lcm(a: u64, b: u64) -> u64 {
+    let (mut x, mut y) = (a, b);
+    while y != 0 {
+        (x, y) = (y, x % y);
+    }
+    a / x * b
+}
+
+// Whether pieces run on their own always add up to the whole group run
+// together, i.e. never interact
+fn independent(cluster: &[(i64, i64)], pieces: &[Vec<(i64, i64)>], rule: &Rule, generations: u32) -> bool {
+    let mut whole = sparse(cluster, rule);
+    let mut parts: Vec<SparseUniverse> = pieces.iter().map(|piece| sparse(piece, rule)).collect();
+    for _ in 0..generations {
+        whole.tick();
+        parts.iter_mut().for_each(SparseUniverse::tick);
+        let mut together: Vec<_> = whole.live_cells().collect();
+        let mut apart: Vec<_> = parts.iter().flat_map(|part| part.live_cells()).collect();
+        together.sort_unstable();
+        apart.sort_unstable();
+        if together != apart {
+            return false;
+        }
+    }
+    true
+}
+
+// An object's apgcode: its kind with its population or period, then the
+// shortest (and of those, first) encoding of any phase in any orientation
+fn apgcode(analysis: &PatternAnalysis, phases: &[Phase]) -> String {
+    let prefix = match analysis.kind() {
+        PatternKind::StillLife => format!("xs{}", analysis.min_population()),
+        PatternKind::Oscillator => format!("xp{}", analysis.period()),
+        PatternKind::Spaceship => format!("xq{}", analysis.period()),
+        PatternKind::Other => return "PATHOLOGICAL".to_string(),
+    };
+    if analysis.width() > MAX_ENCODED_SIZE || analysis.height() > MAX_ENCODED_SIZE {
+        // apgsearch's names for objects too big to encode
+        return format!("ov_{}", &prefix[1..]);
+    }
+    let code = phases.iter()
+        .flat_map(encodings)
+        .min_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)))
+        .unwrap_or_default();
+    format!("{}_{}", prefix, code)
+}
+
+// Encodings of a phase in each of its eight orientations
+fn encodings(phase: &Phase) -> Vec<String> {
+    let (width, height) = (phase.size.0 as usize, phase.size.1 as usize);
+    let shape = phase.shape();
+    (0..8).map(|orientation| {
+        let transposed = orientation >= 4;
+        let (w, h) = if transposed { (height, width) } else { (width, height) };
+        let mut grid = vec![false; w * h];
+        for &(row, col) in shape.iter() {
+            let (mut row, mut col) = (row as usize, col as usize);
+            if transposed {
+                std::mem::swap(&mut row, &mut col);
+            }
+            if orientation & 1 != 0 {
+                col = w - 1 - col;
+            }
+            if orientation & 2 != 0 {
+                row = h - 1 - row;
+            }
+            grid[row * w + col] = true;
+        }
+        encode(&grid, w, h)
+    }).collect()
+}
+
+// Extended Wechsler format: strips five rows high, separated by 'z', with
+// each column of a strip a digit whose lowest bit is the top row. Runs of
+// blank columns are shortened, and blank columns at the end left out.
+fn encode(grid: &[bool], width: usize, height: usize) -> String {
+    let mut code = String::new();
+    for strip in 0..(height + 4) / 5 {
+        if strip > 0 {
+            code.push('z');
+        }
+        let mut blanks = 0;
+        for col in 0..width {
+            let digit = (0..5)
+                .filter(|&bit| strip * 5 + bit < height && grid[(strip * 5 + bit) * width + col])
+                .fold(0, |digit, bit| digit | 1 << bit);
+            if digit == 0 {
+                blanks += 1;
+                continue;
+            }
+            match blanks {
+                0 => {},
+                1 => code.push('0'),
+                2 => code.push('w'),
+                3 => code.push('x'),
+                _ => {
+                    code.push('y');
+                    code.push(DIGITS[blanks - 4] as char);
+                },
+            }
+            blanks = 0;
+            code.push(DIGITS[digit] as char);
+        }
+    }
+    code
+}
+
+
+//                      Testing
+// ======================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Pattern;
+
+    fn census_of(cells: &[(usize, usize)]) -> Census {
+        let mut universe = Universe::new(30, 30);
+        universe.set_cells(cells);
+        universe.census().unwrap()
+    }
+
+    #[test]
+    fn test_apgcodes() {
+        let expected = [
+            (Pattern::blinker(), "xp2_7"),
+            (Pattern::toad(), "xp2_7e"),
+            (Pattern::beacon(), "xp2_318c"),
+            (Pattern::pentadecathlon(), "xp15_4r4z4r4"),
+            (Pattern::glider(), "xq4_153"),
+            (Pattern::lightweight_spaceship(), "xq4_6frc"),
+            (Pattern::midweight_spaceship(), "xq4_27dee6"),
+            (Pattern::heavyweight_spaceship(), "xq4_27deee6"),
+            (Pattern::eater_one(), "xs7_178c"),
+        ];
+        for (pattern, apgcode) in IntoIterator::into_iter(expected) {
+            let mut universe = Universe::new(40, 40);
+            universe.insert_pattern(&pattern, 10, 10, 0);
+            let census = universe.census().unwrap();
+            assert_eq!((census.len(), census.apgcode(0), census.count(0)), (1, apgcode.to_string(), 1));
+        }
+
+        let still_lifes: [(&[(usize, usize)], &str); 8] = [
+            (&[(1, 1), (1, 2), (2, 1), (2, 2)], "block"),
+            (&[(1, 2), (1, 3), (2, 1), (2, 4), (3, 2), (3, 3)], "beehive"),
+            (&[(1, 2), (1, 3), (2, 1), (2, 4), (3, 2), (3, 4), (4, 3)], "loaf"),
+            (&[(1, 1), (1, 2), (2, 1), (2, 3), (3, 2)], "boat"),
+            (&[(1, 1), (1, 2), (2, 1), (2, 3), (3, 2), (3, 3)], "ship"),
+            (&[(1, 2), (2, 1), (2, 3), (3, 2)], "tub"),
+            (&[(1, 2), (1, 3), (2, 1), (2, 4), (3, 1), (3, 4), (4, 2), (4, 3)], "pond"),
+            (&[(1, 2), (2, 1), (2, 3), (3, 2), (3, 4), (4, 3)], "barge"),
+        ];
+        for (cells, name) in IntoIterator::into_iter(still_lifes) {
+            assert_eq!(census_of(cells).name(0), name);
+        }
+        assert_eq!(census_of(&[(1, 2), (2, 1), (2, 3), (3, 2), (3, 4), (4, 3), (4, 4)]).name(0), "long boat");
+    }
+
+    #[test]
+    fn test_separating_objects() {
+        // Two blocks two cells apart and a blinker on its own
+        let census = census_of(&[(1, 1), (1, 2), (2, 1), (2, 2), (1, 4), (1, 5), (2, 4), (2, 5), (10, 10), (10, 11), (10, 12)]);
+        assert_eq!(census.summary(), "2 blocks, 1 blinker");
+        assert_eq!((census.count_of("block"), census.count_of("xp2_7"), census.total()), (2, 1, 3));
+
+        // A beacon's two halves don't repeat on their own, so it stays whole
+        let mut universe = Universe::new(30, 30);
+        universe.insert_pattern(&Pattern::beacon(), 5, 5, 0);
+        universe.tick();
+        assert_eq!(universe.census().unwrap().summary(), "1 beacon");
+    }
+
+    #[test]
+    fn test_objects_across_edges() {
+        // A block split over all four corners of a torus
+        let census = census_of(&[(0, 0), (0, 29), (29, 0), (29, 29)]);
+        assert_eq!(census.summary(), "1 block");
+
+        let mut universe = Universe::new(20, 20);
+        universe.insert_pattern(&Pattern::glider(), 14, 14, 0);
+        universe.tick_n(12);
+        assert_eq!(universe.census().unwrap().summary(), "1 glider");
+    }
+
+    #[test]
+    fn test_settled_soup() {
+        let mut universe = Universe::new_soup(64, 64, 1, 0.35);
+        universe.tick_n(3000);
+        let census = universe.census().unwrap();
+        assert!(census.count_of("block") > 0);
+        assert_eq!(census.count_of("PATHOLOGICAL"), 0);
+        // Other rules get apgcodes without names
+        universe.set_rulestring("B36/S23").unwrap();
+        assert!(universe.census().unwrap().entries().iter().all(|entry| entry.name.is_none()));
+    }
+}
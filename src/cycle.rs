@@ -0,0 +1,297 @@
+use std::collections::VecDeque;
+use fixedbitset::FixedBitSet;
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CycleKind {
+    // Every cell stays the same
+    Static,
+    // The whole universe repeats in place
+    Periodic,
+    // The pattern repeats moved somewhere else, like a spaceship. Only
+    // seen when everything alive moves together, as the whole universe
+    // is compared: a spaceship next to a still life is found when it has
+    // gone round the torus and the universe repeats in place.
+    Translated,
+}
+
+// What a cycle detector found: the period, the generation the cycle
+// started from, and for translated repeats how far the pattern moves each
+// period
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CycleReport {
+    kind: CycleKind,
+    period: u32,
+    start_generation: u64,
+    dx: i32,
+    dy: i32,
+}
+
+#[wasm_bindgen]
+impl CycleReport {
+    pub fn kind(&self) -> CycleKind {
+        self.kind
+    }
+
+    pub fn period(&self) -> u32 {
+        self.period
+    }
+
+    pub fn start_generation(&self) -> u64 {
+        self.start_generation
+    }
+
+    // Columns moved right each period
+    pub fn dx(&self) -> i32 {
+        self.dx
+    }
+
+    // Rows moved down each period
+    pub fn dy(&self) -> i32 {
+        self.dy
+    }
+}
+
+// Hashes of one generation: of the exact cells, and of the cells
+// relative to their bounding box so translated copies hash the same
+#[derive(Clone, Copy, Debug)]
+struct Entry {
+    generation: u64,
+    hash: u64,
+    shape_hash: u64,
+    origin: (usize, usize),
+}
+
+// Watches the last max_period generations for one coming round again.
+// States are compared by 64 bit hashes, so a collision could in principle
+// report a cycle that isn't there.
+#[derive(Clone, Debug)]
+pub(crate) struct CycleDetector {
+    max_period: usize,
+    entries: VecDeque<Entry>,
+    report: Option<CycleReport>,
+}
+
+impl CycleDetector {
+    pub fn new(max_period: usize) -> CycleDetector {
+        CycleDetector { max_period: max_period.max(1), entries: VecDeque::new(), report: None }
+    }
+
+    pub fn report(&self) -> Option<CycleReport> {
+        self.report
+    }
+
+    // Start again, for when the universe changes other than by ticking
+    pub fn reset(&mut self) {
+        self.entries.clear();
+        self.report = None;
+    }
+
+    // Look at the next generation. One that doesn't come after the last
+    // seen, as after stepping backwards, starts detection again.
+    pub fn observe(&mut self, cells: &FixedBitSet, width: usize, generation: u64) {
+        if self.entries.back().map_or(false, |last| generation <= last.generation) {
+            self.reset();
+        }
+        if self.report.is_some() {
+            return;
+        }
+        let height = if width == 0 { 0 } else { cells.len() / width };
+        let (shape_hash, origin) = shape_hash(cells, width, height);
+        let entry = Entry { generation, hash: state_hash(cells), shape_hash, origin };
+
+        // The most recent match gives the shortest period
+        for previous in self.entries.iter().rev() {
+            if previous.shape_hash != entry.shape_hash {
+                continue;
+            }
+            let period = (generation - previous.generation) as u32;
+            let dy = wrapped_offset(entry.origin.0 as i64 - previous.origin.0 as i64, height);
+            let dx = wrapped_offset(entry.origin.1 as i64 - previous.origin.1 as i64, width);
+            let kind = if previous.hash != entry.hash {
+                CycleKind::Translated
+            }
+            else if period == 1 {
+                CycleKind::Static
+            }
+            else {
+                CycleKind::Periodic
+            };
+            let (dx, dy) = if kind == CycleKind::Translated { (dx, dy) } else { (0, 0) };
+            self.report = Some(CycleReport { kind, period, start_generation: previous.generation, dx, dy });
+            return;
+        }
+
+        self.entries.push_back(entry);
+        if self.entries.len() > self.max_period {
+            self.entries.pop_front();
+        }
+    }
+}
+
+fn mix(hash: u64, value: u64) -> u64 {
+    (hash ^ value).wrapping_mul(0x100_0000_01b3).rotate_left(29)
+}
+
+fn finish(hash: u64) -> u64 {
+    let mut z = hash;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// Hash of exactly which cells are alive
+pub(crate) fn state_hash(cells: &FixedBitSet) -> u64 {
+    finish(cells.as_slice().iter().fold(0xcbf2_9ce4_8422_2325, |hash, &block| mix(hash, block as u64)))
+}
+
+// Where the box around the lines (rows or columns) with live cells starts
+// on a torus: just after the longest run of empty lines, which can wrap
+// round the edge. A pattern straddling the edge then gets a box as small
+// as anywhere else, rather than one the size of the universe.
+fn wrapped_start(occupied: &[bool]) -> usize {
+    let first = match occupied.iter().position(|&line| line) {
+        Some(first) => first,
+        None => return 0,
+    };
+    // Once round from the first occupied line and back to it
+    let (mut start, mut longest_gap, mut gap) = (first, 0, 0);
+    for step in 1..=occupied.len() {
+        let line = (first + step) % occupied.len();
+        if !occupied[line] {
+            gap += 1;
+            continue;
+        }
+        if gap > longest_gap {
+            start = line;
+            longest_gap = gap;
+        }
+        gap = 0;
+    }
+    start
+}
+
+// An offset between two positions on an axis that wraps round, as the
+// shortest way there
+fn wrapped_offset(offset: i64, size: usize) -> i32 {
+    let size = size.max(1) as i64;
+    let offset = offset.rem_euclid(size);
+    (if offset > size / 2 { offset - size } else { offset }) as i32
+}
+
+// Hash of the live cells relative to the top left of their bounding box,
+// and where that corner is. The box is around every live cell in the
+// universe, not each object, and is measured round the torus.
+fn shape_hash(cells: &FixedBitSet, width: usize, height: usize) -> (u64, (usize, usize)) {
+    let (mut rows, mut columns) = (vec![false; height], vec![false; width]);
+    for idx in cells.ones() {
+        rows[idx / width] = true;
+        columns[idx % width] = true;
+    }
+    let (top, left) = (wrapped_start(&rows), wrapped_start(&columns));
+    // Cells past the edge come first by index, so each is hashed on its own
+    // and the hashes summed, which doesn't depend on the order
+    let hash = cells.ones().fold(0u64, |hash, idx| {
+        let (row, col) = ((idx / width + height - top) % height, (idx % width + width - left) % width);
+        hash.wrapping_add(finish(mix(0xcbf2_9ce4_8422_2325, (row as u64) << 32 | col as u64)))
+    });
+    (finish(hash), (top, left))
+}
+
+
+//                      Testing
+// ======================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockRule, Pattern, Universe};
+
+    fn run_until_report(universe: &mut Universe, limit: usize) -> CycleReport {
+        for _ in 0..limit {
+            universe.tick();
+            if let Some(report) = universe.cycle_report() {
+                return report;
+            }
+        }
+        panic!("no cycle found in {} generations", limit);
+    }
+
+    #[test]
+    fn test_static_and_periodic() {
+        let mut universe = Universe::new(20, 20);
+        universe.insert_pattern(&Pattern::blinker(), 5, 5, 0);
+        universe.enable_cycle_detection(100);
+        // The state detection was enabled in counts, so the cycle starts there
+        let report = run_until_report(&mut universe, 10);
+        assert_eq!((report.kind(), report.period(), report.start_generation()), (CycleKind::Periodic, 2, 0));
+
+        let mut universe = Universe::new(20, 20);
+        universe.set_cells(&[(3, 3), (3, 4), (4, 3), (4, 4), (10, 10)]);
+        universe.enable_cycle_detection(100);
+        let report = run_until_report(&mut universe, 10);
+        assert_eq!((report.kind(), report.period(), report.start_generation()), (CycleKind::Static, 1, 1));
+    }
+
+    #[test]
+    fn test_spaceship() {
+        let mut universe = Universe::new(40, 40);
+        universe.insert_pattern(&Pattern::glider(), 5, 5, 0);
+        universe.enable_cycle_detection(100);
+        let report = run_until_report(&mut universe, 20);
+        assert_eq!(report.kind(), CycleKind::Translated);
+        assert_eq!(report.period(), 4);
+        assert_eq!((report.dx().abs(), report.dy().abs()), (1, 1));
+    }
+
+    #[test]
+    fn test_spaceship_crossing_the_edge() {
+        // A glider heading down and right from the bottom right corner,
+        // which straddles both edges within a period
+        let mut universe = Universe::new(20, 16);
+        universe.set_cells(&[(13, 18), (14, 19), (15, 17), (15, 18), (15, 19)]);
+        universe.enable_cycle_detection(100);
+        let report = run_until_report(&mut universe, 20);
+        assert_eq!((report.kind(), report.period(), report.start_generation()), (CycleKind::Translated, 4, 0));
+        assert_eq!((report.dx(), report.dy()), (1, 1));
+
+        assert_eq!(wrapped_start(&[true, false, false, false, true, true]), 4);
+        assert_eq!(wrapped_start(&[false, true, true, false, false, false]), 1);
+        assert_eq!(wrapped_start(&[false; 4]), 0);
+        assert_eq!((wrapped_offset(-19, 20), wrapped_offset(3, 20), wrapped_offset(-3, 20)), (1, 3, -3));
+    }
+
+    #[test]
+    fn test_edits_restart_detection() {
+        let mut universe = Universe::new(20, 20);
+        universe.insert_pattern(&Pattern::blinker(), 5, 5, 0);
+        universe.enable_cycle_detection(100);
+        run_until_report(&mut universe, 10);
+        universe.toggle_cell(15, 15);
+        assert_eq!(universe.cycle_report(), None);
+        // A state hash only depends on the cells
+        let hash = universe.state_hash();
+        universe.tick();
+        universe.tick();
+        assert_ne!(universe.state_hash(), hash);
+        universe.toggle_cell(15, 15);
+        assert_eq!(universe.state_hash(), hash);
+    }
+
+    #[test]
+    fn test_reverse_steps_restart_detection() {
+        let mut universe = Universe::new(16, 12);
+        universe.insert_pattern(&Pattern::r_pentomino(), 3, 4, 0);
+        universe.set_block_rule(&BlockRule::critters());
+        universe.enable_cycle_detection(100);
+        for _ in 0..10 {
+            universe.tick();
+        }
+        // Going back gives states the detector has seen, which aren't cycles
+        for _ in 0..10 {
+            universe.tick_reverse().unwrap();
+            assert_eq!(universe.cycle_report(), None);
+        }
+    }
+}
@@ -0,0 +1,253 @@
+use std::fmt;
+use fixedbitset::FixedBitSet;
+use wasm_bindgen::prelude::*;
+
+use crate::{random::Random, utils};
+
+// Wolfram's elementary one-dimensional automata, rules 0 to 255. Bit n of
+// the rule number is the next state of a cell whose left, own and right
+// states read as the binary number n. Each tick computes a new row from
+// the bottom one and scrolls the rows above up, so the universe shows the
+// last height generations as a space-time diagram, oldest at the top.
+#[wasm_bindgen]
+pub struct ElementaryUniverse {
+    width: usize,
+    height: usize,
+    buffers: [FixedBitSet; 2],
+    curr_index: usize,
+    rule: u8,
+    // Whether the row wraps around, otherwise cells past the edges are
+    // always dead
+    wrapping: bool,
+    generation: u64,
+}
+
+// Public methods, exposed to JavaScript via bindgen
+#[wasm_bindgen]
+impl ElementaryUniverse {
+    // The universe is at least one cell wide and tall, so there's always a
+    // bottom row to seed and grow from
+    pub fn new(width: u32, height: u32, rule: u8) -> ElementaryUniverse {
+        // Enable logging for panics
+        utils::set_panic_hook();
+        let (width, height) = (width.max(1) as usize, height.max(1) as usize);
+        let size = width * height;
+
+        ElementaryUniverse {
+            width,
+            height,
+            buffers: [FixedBitSet::with_capacity(size), FixedBitSet::with_capacity(size)],
+            curr_index: 0,
+            rule,
+            wrapping: true,
+            generation: 0,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width as u32
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height as u32
+    }
+
+    pub fn cells(&self) -> *const usize {
+        self.buffers[self.curr_index].as_slice().as_ptr()
+    }
+
+    pub fn rule(&self) -> u8 {
+        self.rule
+    }
+
+    pub fn set_rule(&mut self, rule: u8) {
+        self.rule = rule;
+    }
+
+    pub fn wrapping(&self) -> bool {
+        self.wrapping
+    }
+
+    pub fn set_wrapping(&mut self, wrapping: bool) {
+        self.wrapping = wrapping;
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    // Clear the history and start from a single live cell in the middle
+    // of the bottom row
+    pub fn seed_single(&mut self) {
+        self.clear();
+        let idx = self.get_index(self.height - 1, self.width / 2);
+        self.buffers[self.curr_index].insert(idx);
+    }
+
+    // Clear the history and start from a bottom row where each cell is
+    // alive with the given probability, drawn from a seed
+    pub fn seed_random(&mut self, seed: u64, density: f64) {
+        self.clear();
+        let mut random = Random::new(seed);
+        for col in 0..self.width {
+            let idx = self.get_index(self.height - 1, col);
+            self.buffers[self.curr_index].set(idx, random.chance(density));
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.buffers[self.curr_index].clear();
+        self.generation = 0;
+    }
+
+    pub fn tick(&mut self) {
+        let next_index = 1 - self.curr_index;
+        let (width, height, wrapping, rule) = (self.width, self.height, self.wrapping, self.rule);
+        let (current, next) = {
+            let (first, second) = self.buffers.split_at_mut(1);
+            if self.curr_index == 0 { (&first[0], &mut second[0]) } else { (&second[0], &mut first[0]) }
+        };
+        next.clear();
+
+        // Rows are stored one after another, so scrolling up is moving
+        // every cell back by a row
+        for idx in current.ones().filter(|&idx| idx >= width) {
+            next.insert(idx - width);
+        }
+
+        let last = (height - 1) * width;
+        let cell = |col: isize| -> usize {
+            if (0..width as isize).contains(&col) {
+                current[last + col as usize] as usize
+            }
+            else if wrapping {
+                current[last + col.rem_euclid(width as isize) as usize] as usize
+            }
+            else {
+                0
+            }
+        };
+        for col in 0..width as isize {
+            let pattern = cell(col - 1) << 2 | cell(col) << 1 | cell(col + 1);
+            next.set(last + col as usize, rule >> pattern & 1 != 0);
+        }
+
+        self.curr_index = next_index;
+        self.generation += 1;
+    }
+
+    pub fn get_cell(&self, row: u32, column: u32) -> bool {
+        self.buffers[self.curr_index][self.get_index(row as usize, column as usize)]
+    }
+
+    // Toggle a cell of the bottom row, which the next tick grows from
+    pub fn toggle_cell(&mut self, column: u32) {
+        let idx = self.get_index(self.height - 1, column as usize);
+        self.buffers[self.curr_index].toggle(idx);
+    }
+
+    pub fn render(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl ElementaryUniverse {
+    #[inline(always)]
+    fn get_index(&self, row: usize, column: usize) -> usize {
+        row * self.width + column
+    }
+
+    // Get all the cells in the universe
+    pub fn get_cells(&self) -> &FixedBitSet {
+        &self.buffers[self.curr_index]
+    }
+}
+
+impl fmt::Display for ElementaryUniverse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in 0..self.height {
+            for col in 0..self.width {
+                let symbol = if self.buffers[self.curr_index][self.get_index(row, col)] { '◼' } else { '◻' };
+                write!(f, "{}", symbol)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+
+//                      Testing
+// ======================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(universe: &ElementaryUniverse, row: u32) -> String {
+        (0..universe.width()).map(|col| if universe.get_cell(row, col) { '#' } else { '.' }).collect()
+    }
+
+    #[test]
+    fn test_rule_90_draws_sierpinski_triangle() {
+        let mut universe = ElementaryUniverse::new(9, 4, 90);
+        universe.seed_single();
+        for _ in 0..3 {
+            universe.tick();
+        }
+        assert_eq!(universe.generation(), 3);
+        assert_eq!(row(&universe, 0), "....#....");
+        assert_eq!(row(&universe, 1), "...#.#...");
+        assert_eq!(row(&universe, 2), "..#...#..");
+        assert_eq!(row(&universe, 3), ".#.#.#.#.");
+    }
+
+    #[test]
+    fn test_history_scrolls_off_the_top() {
+        let mut universe = ElementaryUniverse::new(5, 2, 204);
+        universe.seed_single();
+        for _ in 0..10 {
+            universe.tick();
+        }
+        // Rule 204 is the identity, so only the seed's column stays alive
+        assert_eq!(universe.get_cells().count_ones(..), 2);
+        assert_eq!(row(&universe, 0), "..#..");
+    }
+
+    #[test]
+    fn test_boundaries() {
+        // Rule 2 moves a lone cell left, so it wraps or falls off the edge
+        let mut universe = ElementaryUniverse::new(4, 1, 2);
+        universe.toggle_cell(0);
+        universe.tick();
+        assert_eq!(row(&universe, 0), "...#");
+
+        universe.set_wrapping(false);
+        universe.clear();
+        universe.toggle_cell(0);
+        universe.tick();
+        assert_eq!(row(&universe, 0), "....");
+
+        // Rule 1 turns all-dead neighbourhoods alive, including at the
+        // fixed edges
+        universe.set_rule(1);
+        universe.tick();
+        assert_eq!(row(&universe, 0), "####");
+        universe.tick();
+        assert_eq!(row(&universe, 0), "....");
+    }
+
+    #[test]
+    fn test_empty_sizes_have_a_row() {
+        let mut universe = ElementaryUniverse::new(0, 0, 30);
+        assert_eq!((universe.width(), universe.height()), (1, 1));
+        universe.seed_single();
+        universe.seed_random(7, 0.5);
+        universe.toggle_cell(0);
+        universe.tick();
+
+        let mut universe = ElementaryUniverse::new(6, 0, 30);
+        assert_eq!(universe.height(), 1);
+        universe.seed_single();
+        assert_eq!(row(&universe, 0), "...#..");
+    }
+}
@@ -0,0 +1,257 @@
+use std::fmt;
+use wasm_bindgen::prelude::*;
+
+use super::{column_of, fits, pattern_from_cells, PatternError, MAX_CELLS};
+use crate::{Pattern, Rule, Universe};
+
+// Longest row Life 1.05 allows, wider patterns are split into blocks
+const MAX_LIFE_105_WIDTH: usize = 80;
+
+// A rule in the S/B order Life 1.05 uses, e.g. "23/36" for HighLife
+struct SurvivalBirth<'a>(&'a Rule);
+
+impl fmt::Display for SurvivalBirth<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.write_survival(f)?;
+        write!(f, "/")?;
+        self.0.write_birth(f)?;
+        match self.0.neighbourhood().suffix() {
+            Some(suffix) => write!(f, "{}", suffix),
+            None => Ok(()),
+        }
+    }
+}
+
+// Line number and text of the lines after a "#Life 1.0x" header, which
+// has to come first
+fn body<'a>(text: &'a str, version: &str) -> Result<impl Iterator<Item = (usize, &'a str)>, PatternError> {
+    let mut lines = text.lines().enumerate().map(|(i, line)| (i + 1, line.trim()));
+    match lines.by_ref().find(|(_, line)| !line.is_empty()) {
+        Some((_, line)) if line.starts_with(version) => Ok(lines),
+        Some((line_number, _)) => Err(PatternError::new(line_number, 1, format!("expected a \"{}\" header", version))),
+        None => Err(PatternError::new(0, 0, format!("no \"{}\" header", version))),
+    }
+}
+
+// A pattern of the box around cells with signed positions, moved so the
+// box starts at 0. The box can be anywhere an i64 reaches, but no bigger
+// than a pattern can be.
+fn pattern_around(cells: &[(i64, i64)], corners: &[(i64, i64)]) -> Result<Pattern, PatternError> {
+    let top = corners.iter().map(|corner| corner.0).min().unwrap_or(0);
+    let left = corners.iter().map(|corner| corner.1).min().unwrap_or(0);
+    let bottom = corners.iter().map(|corner| corner.0).max().unwrap_or(0);
+    let right = corners.iter().map(|corner| corner.1).max().unwrap_or(0);
+    // An i128 holds the span of any two i64s
+    let span = |first: i64, last: i64| (last as i128 - first as i128 + 1) as u128;
+    let (width, height) = (span(left, right), span(top, bottom));
+    if width > u64::MAX as u128 || height > u64::MAX as u128 || !fits(width as u64, height as u64) {
+        return Err(PatternError::new(0, 0, format!("the cells span {} by {}, more than the {} cells a pattern can have", width, height, MAX_CELLS)));
+    }
+    // Both fit in the box, so the differences are small
+    let cells: Vec<_> = cells.iter().map(|&(row, col)| (row.wrapping_sub(top) as usize, col.wrapping_sub(left) as usize)).collect();
+    Ok(pattern_from_cells(width as usize, height as usize, &cells))
+}
+
+#[wasm_bindgen]
+impl Pattern {
+    // Read a pattern in Life 1.05 format: a "#Life 1.05" header, "#D"
+    // description lines, "#N" for Conway's Life or "#R 23/3" for another
+    // rule in S/B order, and blocks of rows of '.' and '*' each starting
+    // with a "#P x y" line giving the position of its top left cell
+    pub fn from_life_105(text: &str) -> Result<Pattern, PatternError> {
+        let mut rule = Rule::conway();
+        let (mut position, mut row) = ((0, 0), 0);
+        let mut cells = Vec::new();
+        // Ends of every row, so dead cells count towards the size too
+        let mut corners = Vec::new();
+
+        for (line_number, line) in body(text, "#Life 1.05")? {
+            let error = |part: &str, message: String| PatternError::new(line_number, column_of(line, part), message);
+            if let Some(rest) = line.strip_prefix("#R") {
+                let rulestring = rest.trim();
+                rule = Rule::parse(rulestring).map_err(|err| error(rulestring, format!("invalid rule: {}", err)))?;
+            }
+            else if let Some(rest) = line.strip_prefix("#P") {
+                let numbers: Vec<&str> = rest.split_whitespace().collect();
+                if numbers.len() != 2 {
+                    return Err(PatternError::new(line_number, 1, "expected \"#P x y\""));
+                }
+                let mut coordinates = numbers.iter()
+                    .map(|number| number.parse::<i64>().map_err(|_| error(number, format!("'{}' isn't a whole number", number))));
+                let x = coordinates.next().unwrap()?;
+                position = (coordinates.next().unwrap()?, x);
+                row = 0;
+            }
+            else if line.starts_with('#') || line.is_empty() {
+                // Descriptions, "#N", and other lines that don't change the cells
+                continue;
+            }
+            else {
+                let too_far = |col: usize| PatternError::new(line_number, col + 1, "cells go past the largest position");
+                let y = position.0.checked_add(row).ok_or_else(|| too_far(0))?;
+                for (col, c) in line.chars().enumerate() {
+                    match c {
+                        '.' => {},
+                        '*' | 'O' => cells.push((y, position.1.checked_add(col as i64).ok_or_else(|| too_far(col))?)),
+                        c => return Err(PatternError::new(line_number, col + 1, format!("unexpected character '{}'", c))),
+                    }
+                }
+                let last = line.chars().count() - 1;
+                corners.push((y, position.1));
+                corners.push((y, position.1.checked_add(last as i64).ok_or_else(|| too_far(last))?));
+                row += 1;
+            }
+        }
+
+        let mut pattern = pattern_around(&cells, &corners)?;
+        pattern.set_rule(&rule);
+        Ok(pattern)
+    }
+
+    // Read a pattern in Life 1.06 format: a "#Life 1.06" header, then the
+    // x and y position of each live cell on a line of its own
+    pub fn from_life_106(text: &str) -> Result<Pattern, PatternError> {
+        // Lists of positions are sometimes pasted without the header
+        let lines: Box<dyn Iterator<Item = (usize, &str)>> = if text.trim_start().starts_with('#') {
+            Box::new(body(text, "#Life 1.06")?)
+        }
+        else {
+            Box::new(text.lines().enumerate().map(|(i, line)| (i + 1, line.trim())))
+        };
+
+        let mut cells = Vec::new();
+        for (line_number, line) in lines {
+            if line.starts_with('#') || line.is_empty() {
+                continue;
+            }
+            let numbers: Vec<&str> = line.split_whitespace().collect();
+            if numbers.len() != 2 {
+                return Err(PatternError::new(line_number, 1, "expected the x and y position of a cell"));
+            }
+            let mut coordinates = numbers.iter().map(|number| number.parse::<i64>().map_err(|_| {
+                PatternError::new(line_number, column_of(line, number), format!("'{}' isn't a whole number", number))
+            }));
+            let x = coordinates.next().unwrap()?;
+            cells.push((coordinates.next().unwrap()?, x));
+        }
+
+        pattern_around(&cells, &cells)
+    }
+}
+
+#[wasm_bindgen]
+impl Universe {
+    // The whole universe in Life 1.05 format, in blocks at most 80 cells
+    // wide with every cell written out so the size is kept
+    pub fn to_life_105(&self) -> String {
+        let mut text = String::from("#Life 1.05\n");
+        if self.rule == Rule::conway() {
+            text.push_str("#N\n");
+        }
+        else {
+            text.push_str(&format!("#R {}\n", SurvivalBirth(&self.rule)));
+        }
+
+        let cells = self.get_cells();
+        for left in (0..self.width).step_by(MAX_LIFE_105_WIDTH) {
+            let right = (left + MAX_LIFE_105_WIDTH).min(self.width);
+            text.push_str(&format!("#P {} 0\n", left));
+            for row in 0..self.height {
+                let start = Universe::get_index(self.width, row, 0);
+                text.extend((start + left..start + right).map(|idx| if cells[idx] { '*' } else { '.' }));
+                text.push('\n');
+            }
+        }
+        text
+    }
+
+    // The live cells in Life 1.06 format, as x and y positions from the top
+    // left. The format has no way to give the size or the rule.
+    pub fn to_life_106(&self) -> String {
+        let mut text = String::from("#Life 1.06\n");
+        for idx in self.get_cells().ones() {
+            text.push_str(&format!("{} {}\n", idx % self.width, idx / self.width));
+        }
+        text
+    }
+}
+
+
+//                      Testing
+// ======================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn live_cells(universe: &Universe) -> Vec<(usize, usize)> {
+        let width = universe.width() as usize;
+        universe.get_cells().ones().map(|idx| (idx / width, idx % width)).collect()
+    }
+
+    #[test]
+    fn test_life_105() {
+        let text = "#Life 1.05\n#D Two gliders\n#R 23/36\n#P -1 -1\n.*\n..*\n***\n#P 4 2\n***\n*\n.*\n";
+        let pattern = Pattern::from_life_105(text).unwrap();
+        assert_eq!((pattern.width(), pattern.height()), (8, 6));
+        assert_eq!(pattern.rule(), Rule::parse("B36/S23").unwrap());
+        assert_eq!(live_cells(&pattern), vec![(0, 1), (1, 2), (2, 0), (2, 1), (2, 2), (3, 5), (3, 6), (3, 7), (4, 5), (5, 6)]);
+
+        let written = pattern.to_life_105();
+        assert!(written.starts_with("#Life 1.05\n#R 23/36\n#P 0 0\n.*......\n"));
+        assert_eq!(live_cells(&Pattern::from_life_105(&written).unwrap()), live_cells(&pattern));
+
+        // Wide universes are written in blocks
+        let universe = Universe::new_soup(200, 10, 9, 0.5);
+        let written = universe.to_life_105();
+        assert!(written.starts_with("#Life 1.05\n#N\n"));
+        assert!(written.lines().all(|line| line.len() <= MAX_LIFE_105_WIDTH));
+        assert_eq!(Pattern::from_life_105(&written).unwrap().get_cells(), universe.get_cells());
+    }
+
+    #[test]
+    fn test_life_106() {
+        let glider = Pattern::from_life_106("#Life 1.06\n0 -1\n1 0\n-1 1\n0 1\n1 1\n").unwrap();
+        assert_eq!((glider.width(), glider.height()), (3, 3));
+        assert_eq!(live_cells(&glider), vec![(0, 1), (1, 2), (2, 0), (2, 1), (2, 2)]);
+        assert_eq!(glider.to_life_106(), "#Life 1.06\n1 0\n2 1\n0 2\n1 2\n2 2\n");
+        assert_eq!(live_cells(&Pattern::from_life_106(&glider.to_life_106()).unwrap()), live_cells(&glider));
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |result: Result<Pattern, PatternError>| {
+            let error = result.err().unwrap();
+            (error.line(), error.column())
+        };
+        assert_eq!(error(Pattern::from_life_105("#Life 1.06\n*\n")), (1, 1));
+        assert_eq!(error(Pattern::from_life_105("#Life 1.05\n#R 23/9\n")), (2, 4));
+        assert_eq!(error(Pattern::from_life_105("#Life 1.05\n#P 0 zero\n")), (2, 6));
+        assert_eq!(error(Pattern::from_life_105("#Life 1.05\n#P 0 0\n.*.\n.x\n")), (4, 2));
+        assert_eq!(error(Pattern::from_life_106("#Life 1.06\n1 2\n3\n")), (3, 1));
+        assert_eq!(error(Pattern::from_life_106("#Life 1.06\n1  two\n")), (2, 4));
+    }
+
+    #[test]
+    fn test_extreme_positions() {
+        // Far from the origin is fine as long as the cells are close together
+        let pattern = Pattern::from_life_106("#Life 1.06\n9223372036854775807 9223372036854775806\n9223372036854775806 9223372036854775807\n").unwrap();
+        assert_eq!((pattern.width(), pattern.height()), (2, 2));
+        assert_eq!(live_cells(&pattern), vec![(0, 1), (1, 0)]);
+        let pattern = Pattern::from_life_105("#Life 1.05\n#P -9223372036854775808 -9223372036854775808\n*.*\n").unwrap();
+        assert_eq!(live_cells(&pattern), vec![(0, 0), (0, 2)]);
+
+        // Cells far apart would need too big a pattern
+        let error = Pattern::from_life_106("#Life 1.06\n-9223372036854775808 0\n9223372036854775807 0\n").err().unwrap();
+        assert_eq!(error.line(), 0);
+        assert!(Pattern::from_life_106("0 0\n100000 100000\n").is_err());
+        assert!(Pattern::from_life_106("0 0\n0 5000\n").is_ok());
+
+        // Rows and columns past the largest position
+        let error = |text: &str| {
+            let error = Pattern::from_life_105(text).err().unwrap();
+            (error.line(), error.column())
+        };
+        assert_eq!(error("#Life 1.05\n#P 9223372036854775806 0\n.**\n"), (3, 3));
+        assert_eq!(error("#Life 1.05\n#P 0 9223372036854775807\n*\n*\n"), (4, 1));
+    }
+}
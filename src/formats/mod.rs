@@ -0,0 +1,157 @@
+use std::{error::Error, fmt};
+use wasm_bindgen::prelude::*;
+
+use crate::Pattern;
+
+// Reading and writing patterns as text, in the formats LifeWiki and Golly
+// use
+mod rle;
+mod plaintext;
+mod life;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PatternError {
+    // Line and column of the text the error is at, counting from 1, or 0
+    // if it's about a whole line or the whole text
+    line: usize,
+    column: usize,
+    message: String,
+}
+
+impl PatternError {
+    pub(crate) fn new(line: usize, column: usize, message: impl Into<String>) -> PatternError {
+        PatternError { line, column, message: message.into() }
+    }
+
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn column(&self) -> usize {
+        self.column
+    }
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.line, self.column) {
+            (0, _) => write!(f, "{}", self.message),
+            (line, 0) => write!(f, "line {}: {}", line, self.message),
+            (line, column) => write!(f, "line {}, column {}: {}", line, column, self.message),
+        }
+    }
+}
+
+impl Error for PatternError {}
+
+impl From<PatternError> for JsValue {
+    fn from(error: PatternError) -> JsValue {
+        js_sys::Error::new(&error.to_string()).into()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Rle,
+    Plaintext,
+    Life105,
+    Life106,
+}
+
+// Tell a pattern's format from its first lines. Life 1.05 and 1.06 files
+// say which they are, RLE has its "x = .." header, and lines of two numbers
+// are Life 1.06 cells without the header. Anything else is read as
+// plaintext, which at least reports where the text stops making sense.
+fn detect(text: &str) -> Format {
+    let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+    match lines.next() {
+        Some(line) if line.starts_with("#Life 1.05") => return Format::Life105,
+        Some(line) if line.starts_with("#Life 1.06") => return Format::Life106,
+        _ => {},
+    }
+
+    let first_row = text.lines().map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with('!'));
+    match first_row {
+        Some(line) if line.starts_with('x') && line.contains('=') => Format::Rle,
+        Some(line) if line.split_whitespace().count() == 2
+            && line.split_whitespace().all(|number| number.parse::<i64>().is_ok()) => Format::Life106,
+        _ => Format::Plaintext,
+    }
+}
+
+#[wasm_bindgen]
+impl Pattern {
+    // Read a pattern in RLE, plaintext, Life 1.05 or Life 1.06 format,
+    // whichever the text is in
+    pub fn from_text(text: &str) -> Result<Pattern, PatternError> {
+        match detect(text) {
+            Format::Rle => Pattern::from_rle(text),
+            Format::Plaintext => Pattern::from_plaintext(text),
+            Format::Life105 => Pattern::from_life_105(text),
+            Format::Life106 => Pattern::from_life_106(text),
+        }
+    }
+}
+
+// Most cells a pattern read from text can have, so a mistyped or hostile
+// size can't ask for more memory than a browser tab has
+pub(crate) const MAX_CELLS: u64 = 1 << 26;
+
+// Whether a pattern this size is small enough to make
+pub(crate) fn fits(width: u64, height: u64) -> bool {
+    width.checked_mul(height).map_or(false, |cells| cells <= MAX_CELLS)
+}
+
+// A pattern with live cells at the given rows and columns
+pub(crate) fn pattern_from_cells(width: usize, height: usize, cells: &[(usize, usize)]) -> Pattern {
+    let mut pattern = Pattern::new(width.max(1) as u32, height.max(1) as u32);
+    for &(row, col) in cells {
+        pattern.buffers[pattern.curr_index].insert(row * pattern.width + col);
+    }
+    pattern.mark_all_active();
+    pattern
+}
+
+// Column of a slice of a line, counting from 1
+pub(crate) fn column_of(line: &str, part: &str) -> usize {
+    line[..part.as_ptr() as usize - line.as_ptr() as usize].chars().count() + 1
+}
+
+
+//                      Testing
+// ======================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_formats() {
+        assert_eq!(detect("#N Glider\nx = 3, y = 3, rule = B3/S23\nbo$2bo$3o!"), Format::Rle);
+        assert_eq!(detect("!Name: Glider\n.O\n..O\nOOO\n"), Format::Plaintext);
+        assert_eq!(detect("\n#Life 1.05\n#P -1 -1\n.*\n..*\n***\n"), Format::Life105);
+        assert_eq!(detect("#Life 1.06\n0 -1\n1 0\n-1 1\n0 1\n1 1\n"), Format::Life106);
+        assert_eq!(detect("0 -1\n1 0\n"), Format::Life106);
+        assert_eq!(detect("OO\nOO"), Format::Plaintext);
+    }
+
+    #[test]
+    fn test_read_any_format() {
+        let glider = [(0, 1), (1, 2), (2, 0), (2, 1), (2, 2)];
+        let texts = [
+            "x = 3, y = 3\nbo$2bo$3o!",
+            "!Name: Glider\n!\n.O\n..O\nOOO\n",
+            "#Life 1.05\n#D Glider\n#N\n#P -1 -1\n.*\n..*\n***\n",
+            "#Life 1.06\n0 -1\n1 0\n-1 1\n0 1\n1 1\n",
+        ];
+        for text in IntoIterator::into_iter(texts) {
+            let pattern = Pattern::from_text(text).unwrap();
+            let cells: Vec<_> = pattern.get_cells().ones().map(|idx| (idx / 3, idx % 3)).collect();
+            assert_eq!((pattern.width(), pattern.height()), (3, 3), "{}", text);
+            assert_eq!(cells, glider, "{}", text);
+        }
+
+        let error = Pattern::from_text("!Name: Nonsense\n.O\nO?\n").err().unwrap();
+        assert_eq!((error.line(), error.column()), (3, 2));
+    }
+}
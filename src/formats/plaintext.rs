@@ -0,0 +1,89 @@
+use wasm_bindgen::prelude::*;
+
+use super::{fits, pattern_from_cells, PatternError, MAX_CELLS};
+use crate::{Pattern, Universe};
+
+#[wasm_bindgen]
+impl Pattern {
+    // Read a pattern in plaintext (.cells) format: '!' comment lines, then
+    // a line per row with '.' for a dead cell and 'O' for a live one. Rows
+    // can stop at their last live cell, the pattern is as wide as its
+    // longest row.
+    pub fn from_plaintext(text: &str) -> Result<Pattern, PatternError> {
+        let mut rows: Vec<(usize, &str)> = text.lines().enumerate()
+            .filter(|(_, line)| !line.starts_with('!'))
+            .map(|(i, line)| (i + 1, line.trim_end()))
+            .skip_while(|(_, row)| row.is_empty())
+            .collect();
+        while rows.last().map_or(false, |(_, row)| row.is_empty()) {
+            rows.pop();
+        }
+        if rows.is_empty() {
+            return Err(PatternError::new(0, 0, "no rows of cells"));
+        }
+
+        let mut cells = Vec::new();
+        for (row, &(line_number, line)) in rows.iter().enumerate() {
+            for (col, c) in line.chars().enumerate() {
+                match c {
+                    '.' => {},
+                    // Some older collections use '*' for live cells
+                    'O' | 'o' | '*' => cells.push((row, col)),
+                    c => return Err(PatternError::new(line_number, col + 1, format!("unexpected character '{}'", c))),
+                }
+            }
+        }
+        let width = rows.iter().map(|(_, row)| row.chars().count()).max().unwrap_or(0);
+        if !fits(width as u64, rows.len() as u64) {
+            return Err(PatternError::new(0, 0, format!("{} by {} is more than the {} cells a pattern can have", width, rows.len(), MAX_CELLS)));
+        }
+        Ok(pattern_from_cells(width, rows.len(), &cells))
+    }
+}
+
+#[wasm_bindgen]
+impl Universe {
+    // The whole universe in plaintext (.cells) format, every cell written
+    // out so the size is kept. The format has no way to give the rule.
+    pub fn to_plaintext(&self) -> String {
+        let cells = self.get_cells();
+        let mut text = String::with_capacity((self.width + 1) * self.height);
+        for row in 0..self.height {
+            let start = Universe::get_index(self.width, row, 0);
+            text.extend((start..start + self.width).map(|idx| if cells[idx] { 'O' } else { '.' }));
+            text.push('\n');
+        }
+        text
+    }
+}
+
+
+//                      Testing
+// ======================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_and_write() {
+        let text = "!Name: Glider\n!\n.O\n..O\nOOO\n";
+        let glider = Pattern::from_plaintext(text).unwrap();
+        assert_eq!((glider.width(), glider.height()), (3, 3));
+        assert_eq!(glider.to_plaintext(), ".O.\n..O\nOOO\n");
+
+        // Blank rows inside the pattern are kept, ones around it aren't
+        let pattern = Pattern::from_plaintext("!Two blocks\n\nOO\nOO\n\n..OO\n..OO\n\n").unwrap();
+        assert_eq!((pattern.width(), pattern.height(), pattern.population()), (4, 5, 8));
+
+        let universe = Universe::new_soup(40, 20, 5, 0.5);
+        let read = Pattern::from_plaintext(&universe.to_plaintext()).unwrap();
+        assert_eq!(read.get_cells(), universe.get_cells());
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(Pattern::from_plaintext("!Only a comment\n\n").err().unwrap().line(), 0);
+        let error = Pattern::from_plaintext("!Name: Nonsense\n.O.\nOxO\n").err().unwrap();
+        assert_eq!(error.to_string(), "line 3, column 2: unexpected character 'x'");
+    }
+}
@@ -0,0 +1,284 @@
+use wasm_bindgen::prelude::*;
+
+use super::{column_of, fits, PatternError, MAX_CELLS};
+use crate::{Pattern, Topology, Universe};
+
+// Longest line written, as Golly and LifeWiki keep to
+const MAX_LINE_LENGTH: usize = 70;
+
+#[wasm_bindgen]
+impl Pattern {
+    // Read a pattern in run length encoded format: '#' comment lines, a
+    // header like "x = 3, y = 3, rule = B3/S23", then rows of runs of dead
+    // 'b' and live 'o' cells ending in '$', up to a '!'. A rule with a
+    // bounded grid suffix, e.g. "B3/S23:P100,80", also sets the size of
+    // the pattern, with its cells at the top left.
+    pub fn from_rle(text: &str) -> Result<Pattern, PatternError> {
+        let mut lines = text.lines().enumerate()
+            .map(|(i, line)| (i + 1, line))
+            .filter(|(_, line)| !line.trim_start().starts_with('#'));
+
+        let (header_line, header) = lines.by_ref().find(|(_, line)| !line.trim().is_empty())
+            .ok_or_else(|| PatternError::new(0, 0, "no \"x = .., y = ..\" header line"))?;
+        let Header { width, height, rule } = parse_header(header_line, header)?;
+        if !fits(width as u64, height as u64) {
+            return Err(PatternError::new(header_line, 1, format!("{} by {} is more than the {} cells a pattern can have", width, height, MAX_CELLS)));
+        }
+        if let Some((rule, column)) = rule {
+            // A bounded grid in the rule sets the size too
+            let topology = rule.split_once(':').and_then(|(_, suffix)| Topology::parse(suffix).ok());
+            if let Some(topology) = topology.filter(|topology| !fits(topology.width() as u64, topology.height() as u64)) {
+                return Err(PatternError::new(header_line, column, format!("a {} by {} grid is more than the {} cells a pattern can have",
+                    topology.width(), topology.height(), MAX_CELLS)));
+            }
+        }
+        let mut pattern = Pattern::new(width, height);
+        if let Some((rule, column)) = rule {
+            pattern.set_rulestring(rule)
+                .map_err(|err| PatternError::new(header_line, column, format!("invalid rule: {}", err)))?;
+            if pattern.width < width as usize || pattern.height < height as usize {
+                return Err(PatternError::new(header_line, column, "the pattern is bigger than the rule's bounded grid"));
+            }
+        }
+
+        let (mut row, mut col) = (0usize, 0usize);
+        let mut count: Option<usize> = None;
+        'body: for (line_number, line) in lines {
+            for (i, c) in line.chars().enumerate() {
+                let error = |message: String| PatternError::new(line_number, i + 1, message);
+                if let Some(digit) = c.to_digit(10) {
+                    count = Some(count.unwrap_or(0).checked_mul(10)
+                        .and_then(|count| count.checked_add(digit as usize))
+                        .ok_or_else(|| error("run count is too large".to_string()))?);
+                    continue;
+                }
+                let run = match c {
+                    c if c.is_whitespace() => continue,
+                    '!' => break 'body,
+                    _ => count.take().unwrap_or(1),
+                };
+                let past_box = || error(format!("cells go past the {} by {} box", width, height));
+                match c {
+                    '$' => {
+                        row = row.checked_add(run).ok_or_else(past_box)?;
+                        col = 0;
+                    },
+                    'b' | '.' => col = col.checked_add(run).ok_or_else(past_box)?,
+                    'B'..='X' | 'p'..='y' => return Err(error(format!("'{}' is a cell state above 1, which patterns can't have", c))),
+                    // Golly reads any other letter as a live cell
+                    c if c.is_ascii_alphabetic() => {
+                        if row >= pattern.height || col.checked_add(run).map_or(true, |end| end > pattern.width) {
+                            return Err(past_box());
+                        }
+                        let start = Universe::get_index(pattern.width, row, col);
+                        pattern.buffers[pattern.curr_index].insert_range(start..start + run);
+                        col += run;
+                    },
+                    c => return Err(error(format!("unexpected character '{}'", c))),
+                }
+            }
+        }
+        pattern.mark_all_active();
+        Ok(pattern)
+    }
+}
+
+#[wasm_bindgen]
+impl Universe {
+    // The whole universe in run length encoded format, with its rule and,
+    // unless it's the usual torus, its bounded grid
+    pub fn to_rle(&self) -> String {
+        let rule = if self.topology.is_plain_torus() { self.rule.to_string() } else { self.rulestring() };
+        let mut rle = format!("x = {}, y = {}, rule = {}\n", self.width, self.height, rule);
+        let mut body = Body::default();
+        let cells = self.get_cells();
+
+        // Dead cells at the end of a row and empty rows at the end are left
+        // out, and row ends are put off until a row with live cells
+        let mut row_ends = 0;
+        for row in 0..self.height {
+            let start = Universe::get_index(self.width, row, 0);
+            let mut col = 0;
+            while let Some(first_live) = (col..self.width).find(|&c| cells[start + c]) {
+                if row_ends > 0 {
+                    body.push(row_ends, '$');
+                    row_ends = 0;
+                }
+                if first_live > col {
+                    body.push(first_live - col, 'b');
+                }
+                col = (first_live..self.width).find(|&c| !cells[start + c]).unwrap_or(self.width);
+                body.push(col - first_live, 'o');
+            }
+            row_ends += 1;
+        }
+        body.push(1, '!');
+        rle.push_str(&body.text);
+        rle.push('\n');
+        rle
+    }
+}
+
+// Runs written out in lines no longer than MAX_LINE_LENGTH, only breaking
+// between runs
+#[derive(Default)]
+struct Body {
+    text: String,
+    line_length: usize,
+}
+
+impl Body {
+    fn push(&mut self, count: usize, tag: char) {
+        let run = match count {
+            1 => tag.to_string(),
+            _ => format!("{}{}", count, tag),
+        };
+        if self.line_length + run.len() > MAX_LINE_LENGTH {
+            self.text.push('\n');
+            self.line_length = 0;
+        }
+        self.text.push_str(&run);
+        self.line_length += run.len();
+    }
+}
+
+// Size and rule from a header line like "x = 36, y = 9, rule = B3/S23"
+struct Header<'a> {
+    width: u32,
+    height: u32,
+    // The rule and the column it starts at
+    rule: Option<(&'a str, usize)>,
+}
+
+// The rule is the rest of the line, as a bounded grid suffix has a comma
+// in it
+fn parse_header(line_number: usize, line: &str) -> Result<Header, PatternError> {
+    let (mut width, mut height, mut rule) = (None, None, None);
+    for part in line.split(',') {
+        let (key, value) = match part.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => return Err(PatternError::new(line_number, column_of(line, part.trim_start()),
+                format!("expected \"key = value\", found '{}'", part.trim()))),
+        };
+        let column = column_of(line, value);
+        let size = || match value.parse::<u32>() {
+            Ok(size) if size > 0 => Ok(size),
+            _ => Err(PatternError::new(line_number, column, format!("{} must be a whole number above 0", key))),
+        };
+        match key {
+            "x" => width = Some(size()?),
+            "y" => height = Some(size()?),
+            "rule" => {
+                let start = value.as_ptr() as usize - line.as_ptr() as usize;
+                rule = Some((line[start..].trim_end(), column));
+                break;
+            },
+            // Other settings don't change the pattern
+            _ => {},
+        }
+    }
+    match (width, height) {
+        (Some(width), Some(height)) => Ok(Header { width, height, rule }),
+        _ => Err(PatternError::new(line_number, 0, "the header needs both x and y")),
+    }
+}
+
+
+//                      Testing
+// ======================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Boundary, Rule};
+
+    fn live_cells(universe: &Universe) -> Vec<(usize, usize)> {
+        let width = universe.width() as usize;
+        universe.get_cells().ones().map(|idx| (idx / width, idx % width)).collect()
+    }
+
+    #[test]
+    fn test_read_glider() {
+        let glider = Pattern::from_rle("#N Glider\n#C A comment\nx = 3, y = 3, rule = B3/S23\nbo$2bo$3o!").unwrap();
+        assert_eq!((glider.width(), glider.height()), (3, 3));
+        assert_eq!(live_cells(&glider), vec![(0, 1), (1, 2), (2, 0), (2, 1), (2, 2)]);
+        assert_eq!(glider.to_rle(), "x = 3, y = 3, rule = B3/S23\nbo$2bo$3o!\n");
+        assert_eq!(glider.active_cell_count(), 9);
+    }
+
+    #[test]
+    fn test_read_gosper_glider_gun() {
+        let gun = Pattern::from_rle("\
+#N Gosper glider gun
+#C This was the first gun discovered.
+x = 36, y = 9, rule = B3/S23
+24bo$22bobo$12b2o6b2o12b2o$11bo3bo4b2o12b2o$2o8bo5bo3b2o$2o8bo3bob2o4b
+obo$10bo5bo7bo$11bo3bo$12b2o!").unwrap();
+        // The built in gun has a border of one dead cell
+        let mut universe = Universe::new(38, 11);
+        universe.insert_pattern(&gun, 1, 1, 0);
+        assert_eq!(live_cells(&universe), live_cells(&Pattern::gosper_glider_gun()));
+    }
+
+    #[test]
+    fn test_rules_and_grids() {
+        let pattern = Pattern::from_rle("x = 2, y = 2, rule = B36/S23\n2o$2o!").unwrap();
+        assert_eq!(pattern.rule(), Rule::parse("B36/S23").unwrap());
+        assert!(pattern.to_rle().starts_with("x = 2, y = 2, rule = B36/S23\n"));
+
+        let pattern = Pattern::from_rle("x = 3, y = 1, rule = B3/S23:P10,8\n3o!").unwrap();
+        assert_eq!((pattern.width(), pattern.height(), pattern.topology().boundary()), (10, 8, Boundary::Plane));
+        assert_eq!(pattern.to_rle(), "x = 10, y = 8, rule = B3/S23:P10,8\n3o!\n");
+
+        // Without a rule the pattern is Conway's Life
+        let pattern = Pattern::from_rle("x = 1, y = 5\no3$o!").unwrap();
+        assert_eq!(pattern.rule(), Rule::conway());
+        assert_eq!(live_cells(&pattern), vec![(0, 0), (3, 0)]);
+        assert_eq!(pattern.to_rle(), "x = 1, y = 5, rule = B3/S23\no3$o!\n");
+    }
+
+    #[test]
+    fn test_write_long_lines() {
+        let universe = Universe::new_soup(200, 30, 3, 0.4);
+        let rle = universe.to_rle();
+        assert!(rle.lines().all(|line| line.len() <= MAX_LINE_LENGTH));
+        let read = Pattern::from_rle(&rle).unwrap();
+        assert_eq!((read.width(), read.height()), (200, 30));
+        assert_eq!(read.get_cells(), universe.get_cells());
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |text: &str| {
+            let error = Pattern::from_rle(text).err().unwrap();
+            (error.line(), error.column())
+        };
+        assert_eq!(error("#C only comments\n"), (0, 0));
+        assert_eq!(error("bo$2bo$3o!"), (1, 1));
+        assert_eq!(error("x = 3, y = three\nbo!"), (1, 12));
+        assert_eq!(error("x = 3\nbo!"), (1, 0));
+        assert_eq!(error("x = 3, y = 3, rule = B3/S23/Q\nbo!"), (1, 22));
+        assert_eq!(error("#N Name\nx = 3, y = 2\nbo$2bo$o!"), (3, 8));
+        assert_eq!(error("x = 3, y = 3\nbo$\n2b?!"), (3, 3));
+        assert_eq!(error("x = 3, y = 3\n2A$bB!"), (2, 5));
+
+        let error = Pattern::from_rle("x = 3, y = 3\nb?o!").err().unwrap();
+        assert_eq!(error.to_string(), "line 2, column 2: unexpected character '?'");
+    }
+
+    #[test]
+    fn test_huge_sizes_and_runs() {
+        let error = |text: &str| {
+            let error = Pattern::from_rle(text).err().unwrap();
+            (error.line(), error.column())
+        };
+        assert_eq!(error("x = 4294967295, y = 4294967295\no!"), (1, 1));
+        assert_eq!(error("x = 100000, y = 100000\no!"), (1, 1));
+        assert_eq!(error("x = 3, y = 3, rule = B3/S23:P100000,100000\no!"), (1, 22));
+
+        // Runs that would overflow a usize are past the box too
+        let huge = usize::MAX.to_string();
+        assert_eq!(error(&format!("x = 3, y = 3\no${}$o!", huge)), (2, 3 + huge.len()));
+        assert_eq!(error(&format!("x = 3, y = 3\n2b{}bo!", huge)), (2, 3 + huge.len()));
+        assert_eq!(error(&format!("x = 3, y = 3\n2b{}o!", huge)), (2, 3 + huge.len()));
+    }
+}
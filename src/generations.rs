@@ -0,0 +1,354 @@
+use std::{
+    cmp::min,
+    fmt,
+    str::FromStr,
+};
+use wasm_bindgen::prelude::*;
+
+use crate::{utils, Pattern, Rule, RuleError, Universe};
+
+// Largest number of states a Generations rule can have, so that every
+// state fits in a byte
+const MAX_STATES: u32 = 256;
+
+// Generations rule, e.g. "B2/S/C3" for Brian's Brain. Live cells that
+// fail to survive don't die straight away, they pass through the
+// refractory states 2..states before becoming dead (state 0) again.
+// Only fully alive cells (state 1) count as live neighbours.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GenerationsRule {
+    rule: Rule,
+    states: u32,
+}
+
+#[wasm_bindgen]
+impl GenerationsRule {
+    // Parse a rulestring either in B/S/C notation ("B2/S/C3", with G
+    // accepted in place of C), or in the S/B/C notation ("/2/3")
+    pub fn parse(rulestring: &str) -> Result<GenerationsRule, RuleError> {
+        let rulestring = rulestring.trim();
+        if rulestring.is_empty() {
+            return Err(RuleError::Empty);
+        }
+
+        let parts: Vec<&str> = rulestring.split('/').collect();
+        if parts.len() != 3 {
+            return Err(RuleError::MissingSeparator);
+        }
+
+        let (mut birth, mut survival, mut states) = (None, None, None);
+        let lettered = parts.iter().all(|part| part.starts_with(|c: char| c.is_ascii_alphabetic()));
+        if lettered {
+            for part in parts {
+                let mut chars = part.chars();
+                match chars.next().map(|c| c.to_ascii_uppercase()) {
+                    Some('B') if birth.is_none() => birth = Some(chars.as_str()),
+                    Some('S') if survival.is_none() => survival = Some(chars.as_str()),
+                    Some('C') | Some('G') if states.is_none() => states = Some(chars.as_str()),
+                    Some(c) => return Err(RuleError::UnexpectedChar(c)),
+                    None => return Err(RuleError::MissingSeparator),
+                }
+            }
+        }
+        else {
+            survival = Some(parts[0]);
+            birth = Some(parts[1]);
+            states = Some(parts[2]);
+        }
+
+        match (birth, survival, states) {
+            (Some(birth), Some(survival), Some(states)) => {
+                let rule = Rule::from_counts(birth, survival)?;
+                let states = Self::parse_states(states)?;
+                Ok(GenerationsRule { rule, states })
+            },
+            _ => Err(RuleError::MissingSeparator),
+        }
+    }
+
+    pub fn states(&self) -> u32 {
+        self.states
+    }
+
+    #[wasm_bindgen(js_name = toString)]
+    pub fn rulestring(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl GenerationsRule {
+    #[inline(always)]
+    pub fn next_state(&self, state: u8, live_neighbours: u8) -> u8 {
+        match state {
+            0 => self.rule.births(live_neighbours) as u8,
+            1 if self.rule.survives(live_neighbours) => 1,
+            _ => ((state as u32 + 1) % self.states) as u8,
+        }
+    }
+
+    fn parse_states(digits: &str) -> Result<u32, RuleError> {
+        if let Some(c) = digits.chars().find(|c| !c.is_ascii_digit()) {
+            return Err(RuleError::UnexpectedChar(c));
+        }
+        match digits.parse::<u32>() {
+            Ok(states) if (2..=MAX_STATES).contains(&states) => Ok(states),
+            Ok(states) => Err(RuleError::InvalidStates(states)),
+            Err(_) => Err(RuleError::InvalidStates(0)),
+        }
+    }
+}
+
+impl FromStr for GenerationsRule {
+    type Err = RuleError;
+
+    fn from_str(s: &str) -> Result<GenerationsRule, RuleError> {
+        GenerationsRule::parse(s)
+    }
+}
+
+impl fmt::Display for GenerationsRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "B")?;
+        self.rule.write_birth(f)?;
+        write!(f, "/S")?;
+        self.rule.write_survival(f)?;
+        write!(f, "/C{}", self.states)
+    }
+}
+
+// Universe for Generations rules. Cells hold a byte each rather than a
+// bit, so JavaScript reads the states through cells() as a Uint8Array
+// of width * height bytes.
+#[wasm_bindgen]
+pub struct GenerationsUniverse {
+    width: usize,
+    height: usize,
+    buffers: [Vec<u8>; 2],
+    curr_index: usize,
+    rule: GenerationsRule,
+}
+
+// Public methods, exposed to JavaScript via bindgen
+#[wasm_bindgen]
+impl GenerationsUniverse {
+    pub fn new(width: u32, height: u32, rulestring: &str) -> Result<GenerationsUniverse, RuleError> {
+        // Enable logging for panics
+        utils::set_panic_hook();
+        let rule = GenerationsRule::parse(rulestring)?;
+        let size = width as usize * height as usize;
+
+        Ok(GenerationsUniverse {
+            width: width as usize,
+            height: height as usize,
+            buffers: [vec![0; size], vec![0; size]],
+            curr_index: 0,
+            rule,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width as u32
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height as u32
+    }
+
+    pub fn cells(&self) -> *const u8 {
+        self.buffers[self.curr_index].as_ptr()
+    }
+
+    pub fn rule(&self) -> GenerationsRule {
+        self.rule
+    }
+
+    pub fn set_rule(&mut self, rule: &GenerationsRule) {
+        self.rule = *rule;
+        // States beyond the new rule's range would never decay back to 0
+        let last = (rule.states - 1) as u8;
+        for state in self.buffers[self.curr_index].iter_mut() {
+            if *state > last {
+                *state = last;
+            }
+        }
+    }
+
+    pub fn set_rulestring(&mut self, rulestring: &str) -> Result<(), RuleError> {
+        let rule = GenerationsRule::parse(rulestring)?;
+        self.set_rule(&rule);
+        Ok(())
+    }
+
+    pub fn tick(&mut self) {
+        let next_index = 1 - self.curr_index;
+        let (width, height) = (self.width, self.height);
+        let (current, next) = {
+            let (first, second) = self.buffers.split_at_mut(1);
+            if self.curr_index == 0 { (&first[0], &mut second[0]) } else { (&second[0], &mut first[0]) }
+        };
+
+        for (idx, state) in current.iter().enumerate() {
+            let mut live_neighbours = 0;
+            for n in Universe::get_neighbour_array(idx, width, height) {
+                live_neighbours += (current[n] == 1) as u8;
+            }
+            next[idx] = self.rule.next_state(*state, live_neighbours);
+        }
+        self.curr_index = next_index;
+    }
+
+    pub fn get_state(&self, row: u32, column: u32) -> u8 {
+        self.buffers[self.curr_index][self.get_index(row as usize, column as usize)]
+    }
+
+    pub fn set_state(&mut self, row: u32, column: u32, state: u8) {
+        let idx = self.get_index(row as usize, column as usize);
+        self.buffers[self.curr_index][idx] = min(state as u32, self.rule.states - 1) as u8;
+    }
+
+    // Toggle between dead and alive, clearing any refractory state
+    pub fn toggle_cell(&mut self, row: u32, column: u32) {
+        let idx = self.get_index(row as usize, column as usize);
+        let cell = &mut self.buffers[self.curr_index][idx];
+        *cell = (*cell == 0) as u8;
+    }
+
+    // Copy a two-state pattern in, with its live cells in state 1
+    pub fn insert_pattern(&mut self, pattern: &Pattern, row: u32, column: u32, angle: u32) {
+        let (row, column) = (row as usize, column as usize);
+        let max_row = min(row + pattern.angle_height(angle), self.height) - row;
+        let max_col = min(column + pattern.angle_width(angle), self.width) - column;
+
+        for r in 0..max_row {
+            for c in 0..max_col {
+                let u_idx = self.get_index(r + row, c + column);
+                let p_idx = pattern.get_angle_index(r, c, angle);
+                self.buffers[self.curr_index][u_idx] = pattern.get_cells()[p_idx] as u8;
+            }
+        }
+    }
+
+    // Number of cells in each state, indexed by state
+    pub fn state_counts(&self) -> Vec<u32> {
+        let mut counts = vec![0; self.rule.states as usize];
+        for &state in self.buffers[self.curr_index].iter() {
+            counts[state as usize] += 1;
+        }
+        counts
+    }
+
+    pub fn render(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl GenerationsUniverse {
+    #[inline(always)]
+    fn get_index(&self, row: usize, column: usize) -> usize {
+        row * self.width + column
+    }
+
+    // Get all the cell states in the universe
+    pub fn get_cells(&self) -> &[u8] {
+        &self.buffers[self.curr_index]
+    }
+}
+
+impl fmt::Display for GenerationsUniverse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in 0..self.height {
+            for col in 0..self.width {
+                let symbol = match self.buffers[self.curr_index][self.get_index(row, col)] {
+                    0 => '◻',
+                    1 => '◼',
+                    _ => '▣',
+                };
+                write!(f, "{}", symbol)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+
+//                      Testing
+// ======================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_notations() {
+        let brians_brain = GenerationsRule::parse("/2/3").unwrap();
+        assert_eq!(GenerationsRule::parse("B2/S/C3").unwrap(), brians_brain);
+        assert_eq!(GenerationsRule::parse("b2/s/g3").unwrap(), brians_brain);
+        assert_eq!(brians_brain.states(), 3);
+        assert_eq!(brians_brain.to_string(), "B2/S/C3");
+
+        let star_wars = GenerationsRule::parse("345/2/4").unwrap();
+        assert_eq!(star_wars.to_string(), "B2/S345/C4");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(GenerationsRule::parse("B2/S"), Err(RuleError::MissingSeparator));
+        assert_eq!(GenerationsRule::parse("/2/1"), Err(RuleError::InvalidStates(1)));
+        assert_eq!(GenerationsRule::parse("/2/300"), Err(RuleError::InvalidStates(300)));
+        assert_eq!(GenerationsRule::parse("/2/x"), Err(RuleError::UnexpectedChar('x')));
+        assert_eq!(GenerationsRule::parse("/9/3"), Err(RuleError::InvalidCount(9)));
+    }
+
+    #[test]
+    fn test_cells_decay_through_states() {
+        let mut universe = GenerationsUniverse::new(10, 10, "345/2/4").unwrap();
+        universe.set_state(5, 5, 1);
+
+        // A lone cell has no neighbours to survive with, so it decays
+        for expected in [2, 3, 0] {
+            universe.tick();
+            assert_eq!(universe.get_state(5, 5), expected);
+        }
+    }
+
+    #[test]
+    fn test_brians_brain_pair_moves() {
+        // Two live cells side by side, each with a refractory cell behind,
+        // travel as one of Brian's Brain's simplest spaceships
+        let mut universe = GenerationsUniverse::new(12, 12, "/2/3").unwrap();
+        for (row, col, state) in [(5, 5, 1), (6, 5, 1), (5, 4, 2), (6, 4, 2)] {
+            universe.set_state(row, col, state);
+        }
+
+        universe.tick();
+        assert_eq!(universe.get_state(5, 6), 1);
+        assert_eq!(universe.get_state(6, 6), 1);
+        assert_eq!(universe.get_state(5, 5), 2);
+        assert_eq!(universe.get_state(5, 4), 0);
+        assert_eq!(universe.state_counts(), vec![140, 2, 2]);
+    }
+
+    #[test]
+    fn test_two_states_matches_life() {
+        let mut life = Universe::new(20, 20);
+        life.insert_pattern(&Pattern::r_pentomino(), 7, 7, 0);
+        let mut generations = GenerationsUniverse::new(20, 20, "23/3/2").unwrap();
+        generations.insert_pattern(&Pattern::r_pentomino(), 7, 7, 0);
+
+        for _ in 0..30 {
+            life.tick();
+            generations.tick();
+            let states: Vec<u8> = (0..400).map(|i| life.get_cells()[i] as u8).collect();
+            assert_eq!(generations.get_cells(), &states[..]);
+        }
+    }
+
+    #[test]
+    fn test_display_empty_rows() {
+        let universe = GenerationsUniverse::new(0, 3, "23/3/2").unwrap();
+        assert_eq!(universe.to_string(), "\n\n\n");
+        let universe = GenerationsUniverse::new(4, 0, "23/3/2").unwrap();
+        assert_eq!(universe.to_string(), "");
+    }
+}
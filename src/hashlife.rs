@@ -0,0 +1,618 @@
+use std::{collections::HashMap, error::Error, fmt};
+use wasm_bindgen::prelude::*;
+
+use crate::{Rule, RuleError, Universe};
+
+type NodeId = u32;
+
+// Leaf nodes are single cells, every other node is a square of side
+// 2^level made of four children of side 2^(level - 1)
+const DEAD: NodeId = 0;
+const ALIVE: NodeId = 1;
+
+// Smallest root we work with, so the root always has grandchildren
+const MIN_LEVEL: u8 = 3;
+
+// Largest root, so sides and coordinates stay well inside an i64
+const MAX_LEVEL: u8 = 60;
+
+// Collect garbage once this many nodes have been created
+const DEFAULT_MAX_NODES: usize = 1 << 22;
+
+// Why HashLife couldn't set a cell or advance
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HashLifeError {
+    // A jump of more than 2^MAX_STEP_EXPONENT generations at once
+    StepTooLarge(u32),
+    // The generation count would go past u64::MAX
+    GenerationOverflow,
+    // A cell, or the pattern after a jump, would be further from the
+    // origin than the largest root reaches
+    OutOfRange,
+}
+
+impl fmt::Display for HashLifeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HashLifeError::StepTooLarge(exponent) => write!(f, "can't jump 2^{} generations at once, the most is 2^{}", exponent, HashLife::MAX_STEP_EXPONENT),
+            HashLifeError::GenerationOverflow => write!(f, "the generation count would overflow"),
+            HashLifeError::OutOfRange => write!(f, "cells would be too far from the origin"),
+        }
+    }
+}
+
+impl Error for HashLifeError {}
+
+impl From<HashLifeError> for JsValue {
+    fn from(error: HashLifeError) -> JsValue {
+        js_sys::Error::new(&error.to_string()).into()
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Node {
+    // nw, ne, sw, se
+    children: [NodeId; 4],
+    level: u8,
+    population: u64,
+}
+
+// Quadtree universe using Gosper's HashLife algorithm. Identical squares
+// are shared, and the result of advancing each square is memoised, so
+// regular patterns can be run for huge numbers of generations by jumping
+// 2^k generations at a time. Unlike Universe, the plane is unbounded.
+#[wasm_bindgen]
+pub struct HashLife {
+    nodes: Vec<Node>,
+    lookup: HashMap<[NodeId; 4], NodeId>,
+    results: HashMap<(NodeId, u8), NodeId>,
+    empty: Vec<NodeId>,
+    root: NodeId,
+    // Row and column of the root's top left corner
+    origin: (i64, i64),
+    generation: u64,
+    rule: Rule,
+    max_nodes: usize,
+}
+
+impl HashLife {
+    // Largest exponent step_pow2 takes. The root needs this many levels
+    // plus MIN_LEVEL, and room for a couple more to pad the pattern.
+    pub const MAX_STEP_EXPONENT: u32 = (MAX_LEVEL - MIN_LEVEL - 2) as u32;
+
+    fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id as usize]
+    }
+
+    fn join(&mut self, children: [NodeId; 4]) -> NodeId {
+        if let Some(&id) = self.lookup.get(&children) {
+            return id;
+        }
+        let level = self.node(children[0]).level + 1;
+        let population = children.iter()
+            .fold(0u64, |sum, &c| sum.saturating_add(self.node(c).population));
+        let id = self.nodes.len() as NodeId;
+        self.nodes.push(Node { children, level, population });
+        self.lookup.insert(children, id);
+        id
+    }
+
+    fn empty(&mut self, level: u8) -> NodeId {
+        while self.empty.len() <= level as usize {
+            let e = *self.empty.last().unwrap();
+            let id = self.join([e, e, e, e]);
+            self.empty.push(id);
+        }
+        self.empty[level as usize]
+    }
+
+    fn level(&self) -> u8 {
+        self.node(self.root).level
+    }
+
+    // Side length of a node at this level, which is at most MAX_LEVEL
+    #[inline(always)]
+    fn side(level: u8) -> i64 {
+        1i64 << level
+    }
+
+    // Wrap the root in a node twice the size, keeping it centred
+    fn expand(&mut self) -> Result<(), HashLifeError> {
+        let level = self.level();
+        if level >= MAX_LEVEL {
+            return Err(HashLifeError::OutOfRange);
+        }
+        let [nw, ne, sw, se] = self.node(self.root).children;
+        let e = self.empty(level - 1);
+
+        let children = [
+            self.join([e, e, e, nw]),
+            self.join([e, e, ne, e]),
+            self.join([e, sw, e, e]),
+            self.join([se, e, e, e]),
+        ];
+        self.root = self.join(children);
+
+        let shift = Self::side(level - 1);
+        self.origin = (self.origin.0 - shift, self.origin.1 - shift);
+        Ok(())
+    }
+
+    fn contains(&self, row: i64, col: i64) -> bool {
+        let side = Self::side(self.level());
+        let (top, left) = self.origin;
+        row >= top && row < top + side && col >= left && col < left + side
+    }
+
+    fn set_in(&mut self, id: NodeId, row: i64, col: i64, alive: bool) -> NodeId {
+        let node = *self.node(id);
+        if node.level == 0 {
+            return if alive { ALIVE } else { DEAD };
+        }
+        let half = Self::side(node.level - 1);
+        let quadrant = (row >= half) as usize * 2 + (col >= half) as usize;
+        let mut children = node.children;
+        children[quadrant] = self.set_in(children[quadrant], row % half, col % half, alive);
+        self.join(children)
+    }
+
+    fn get_in(&self, id: NodeId, row: i64, col: i64) -> bool {
+        let node = self.node(id);
+        if node.level == 0 {
+            return id == ALIVE;
+        }
+        if node.population == 0 {
+            return false;
+        }
+        let half = Self::side(node.level - 1);
+        let quadrant = (row >= half) as usize * 2 + (col >= half) as usize;
+        self.get_in(node.children[quadrant], row % half, col % half)
+    }
+
+    // Centred sub-square, one level down
+    fn centre(&mut self, id: NodeId) -> NodeId {
+        let [nw, ne, sw, se] = self.node(id).children;
+        let children = [
+            self.node(nw).children[3],
+            self.node(ne).children[2],
+            self.node(sw).children[1],
+            self.node(se).children[0],
+        ];
+        self.join(children)
+    }
+
+    // Square straddling the border between two horizontally adjacent nodes
+    fn centre_horizontal(&mut self, west: NodeId, east: NodeId) -> NodeId {
+        let (w, e) = (self.node(west).children, self.node(east).children);
+        self.join([w[1], e[0], w[3], e[2]])
+    }
+
+    // Square straddling the border between two vertically adjacent nodes
+    fn centre_vertical(&mut self, north: NodeId, south: NodeId) -> NodeId {
+        let (n, s) = (self.node(north).children, self.node(south).children);
+        self.join([n[2], n[3], s[0], s[1]])
+    }
+
+    // Advance a 4x4 node by one generation, returning its centre 2x2
+    fn base_step(&mut self, id: NodeId) -> NodeId {
+        let mut grid = [[false; 4]; 4];
+        for (q, &child) in self.node(id).children.iter().enumerate() {
+            for (c, &leaf) in self.node(child).children.iter().enumerate() {
+                let row = (q / 2) * 2 + c / 2;
+                let col = (q % 2) * 2 + c % 2;
+                grid[row][col] = leaf == ALIVE;
+            }
+        }
+
+        let mut cells = [DEAD; 4];
+        for (i, cell) in cells.iter_mut().enumerate() {
+            let (row, col) = (1 + i / 2, 1 + i % 2);
+            // Neighbours are visited in get_neighbour_array order
+            let (mut configuration, mut bit) = (0, 0);
+            for (r, grid_row) in grid.iter().enumerate().skip(row - 1).take(3) {
+                for (c, &alive) in grid_row.iter().enumerate().skip(col - 1).take(3) {
+                    if (r, c) != (row, col) {
+                        configuration |= (alive as u8) << bit;
+                        bit += 1;
+                    }
+                }
+            }
+            if self.rule.next_state_of(grid[row][col], configuration) {
+                *cell = ALIVE;
+            }
+        }
+        self.join(cells)
+    }
+
+    // Advance a node of level L by 2^step generations (step <= L - 2),
+    // returning its centre square at level L - 1
+    fn evolve(&mut self, id: NodeId, step: u8) -> NodeId {
+        let node = *self.node(id);
+        if node.population == 0 {
+            return self.empty(node.level - 1);
+        }
+        if let Some(&result) = self.results.get(&(id, step)) {
+            return result;
+        }
+
+        let result = if node.level == 2 {
+            self.base_step(id)
+        }
+        else {
+            let [nw, ne, sw, se] = node.children;
+            let n01 = self.centre_horizontal(nw, ne);
+            let n10 = self.centre_vertical(nw, sw);
+            let n11 = self.centre(id);
+            let n12 = self.centre_vertical(ne, se);
+            let n21 = self.centre_horizontal(sw, se);
+            let squares = [nw, n01, ne, n10, n11, n12, sw, n21, se];
+
+            // At full speed both halves of the recursion advance time,
+            // otherwise only the second half does
+            let full_speed = step == node.level - 2;
+            let mut inner = [DEAD; 9];
+            for (i, &square) in squares.iter().enumerate() {
+                inner[i] = if full_speed { self.evolve(square, step - 1) } else { self.centre(square) };
+            }
+
+            let second_step = if full_speed { step - 1 } else { step };
+            let quads = [
+                self.join([inner[0], inner[1], inner[3], inner[4]]),
+                self.join([inner[1], inner[2], inner[4], inner[5]]),
+                self.join([inner[3], inner[4], inner[6], inner[7]]),
+                self.join([inner[4], inner[5], inner[7], inner[8]]),
+            ];
+            let mut children = [DEAD; 4];
+            for (child, &quad) in children.iter_mut().zip(quads.iter()) {
+                *child = self.evolve(quad, second_step);
+            }
+            self.join(children)
+        };
+
+        self.results.insert((id, step), result);
+        result
+    }
+
+    // Whether every live cell lies in the middle square a quarter the side
+    // of the root, so nothing can escape the part that evolve returns
+    fn is_padded(&self) -> bool {
+        let root = self.node(self.root);
+        let mut inner = 0u64;
+        for (q, &child) in root.children.iter().enumerate() {
+            let grandchild = self.node(child).children[3 - q];
+            inner = inner.saturating_add(self.node(self.node(grandchild).children[3 - q]).population);
+        }
+        inner == root.population
+    }
+
+    // Rebuild the node store keeping only what the root refers to
+    fn collect_garbage(&mut self) {
+        let mut store = HashLife::with_rule(self.rule);
+        store.max_nodes = self.max_nodes;
+        let mut mapping = HashMap::new();
+        store.root = store.copy_node(self, self.root, &mut mapping);
+        store.origin = self.origin;
+        store.generation = self.generation;
+        *self = store;
+    }
+
+    fn copy_node(&mut self, other: &HashLife, id: NodeId, mapping: &mut HashMap<NodeId, NodeId>) -> NodeId {
+        if id == DEAD || id == ALIVE {
+            return id;
+        }
+        if let Some(&copied) = mapping.get(&id) {
+            return copied;
+        }
+        let mut children = other.node(id).children;
+        for child in children.iter_mut() {
+            *child = self.copy_node(other, *child, mapping);
+        }
+        let copied = self.join(children);
+        mapping.insert(id, copied);
+        copied
+    }
+
+    fn for_each_live<F: FnMut(i64, i64)>(&self, id: NodeId, top: i64, left: i64, window: (i64, i64, i64, i64), f: &mut F) {
+        let node = self.node(id);
+        let (min_row, min_col, max_row, max_col) = window;
+        let side = Self::side(node.level);
+        if node.population == 0 || top >= max_row || left >= max_col || top + side <= min_row || left + side <= min_col {
+            return;
+        }
+        if node.level == 0 {
+            f(top, left);
+            return;
+        }
+        let half = side / 2;
+        for (q, &child) in node.children.iter().enumerate() {
+            let (row, col) = (top + (q / 2) as i64 * half, left + (q % 2) as i64 * half);
+            self.for_each_live(child, row, col, window, f);
+        }
+    }
+
+    pub fn with_rule(rule: Rule) -> HashLife {
+        let dead = Node { children: [DEAD; 4], level: 0, population: 0 };
+        let alive = Node { children: [DEAD; 4], level: 0, population: 1 };
+        let mut hashlife = HashLife {
+            nodes: vec![dead, alive],
+            lookup: HashMap::new(),
+            results: HashMap::new(),
+            empty: vec![DEAD],
+            root: DEAD,
+            origin: (0, 0),
+            generation: 0,
+            rule,
+            max_nodes: DEFAULT_MAX_NODES,
+        };
+        hashlife.root = hashlife.empty(MIN_LEVEL);
+        hashlife
+    }
+
+    // Call f with the row and column of every live cell in the window
+    // [top, top + height) x [left, left + width)
+    pub fn live_cells_in<F: FnMut(i64, i64)>(&self, top: i64, left: i64, height: i64, width: i64, mut f: F) {
+        let (root_top, root_left) = self.origin;
+        self.for_each_live(self.root, root_top, root_left, (top, left, top + height, left + width), &mut f);
+    }
+}
+
+// Public methods, exposed to JavaScript via bindgen
+#[wasm_bindgen]
+impl HashLife {
+    pub fn new() -> HashLife {
+        HashLife::with_rule(Rule::default())
+    }
+
+    // Copy the live cells of a universe, keeping their row and column
+    pub fn from_universe(universe: &Universe) -> Result<HashLife, RuleError> {
+        let mut hashlife = HashLife::new();
+        hashlife.set_rule(&universe.rule)?;
+        let width = universe.width;
+        for idx in universe.get_cells().ones() {
+            hashlife.set_cell((idx / width) as i64, (idx % width) as i64, true)
+                .expect("universe cells are near the origin");
+        }
+        Ok(hashlife)
+    }
+
+    // Copy the window of cells starting at (top, left) into a new universe
+    // with the same rule
+    pub fn to_universe(&self, top: i64, left: i64, width: u32, height: u32) -> Universe {
+        let mut universe = Universe::new(width, height);
+        universe.set_rule(&self.rule);
+        self.live_cells_in(top, left, height as i64, width as i64, |row, col| {
+            universe.toggle_cell((row - top) as u32, (col - left) as u32);
+        });
+        universe
+    }
+
+    pub fn rule(&self) -> Rule {
+        self.rule
+    }
+
+    pub fn set_rule(&mut self, rule: &Rule) -> Result<(), RuleError> {
+        // A rule with B0 would fill the infinite empty plane in one step
+        if rule.births(0) {
+            return Err(RuleError::Unsupported("B0 rules on an unbounded plane"));
+        }
+        // Nodes are evolved from 3x3 blocks of their children
+        if !rule.neighbourhood().is_moore() {
+            return Err(RuleError::Unsupported("non-Moore neighbourhoods in HashLife"));
+        }
+        self.rule = *rule;
+        self.results.clear();
+        Ok(())
+    }
+
+    // Cells can be about 2^59 from the origin
+    pub fn set_cell(&mut self, row: i64, col: i64, alive: bool) -> Result<(), HashLifeError> {
+        while !self.contains(row, col) {
+            self.expand()?;
+        }
+        let (top, left) = self.origin;
+        self.root = self.set_in(self.root, row - top, col - left, alive);
+        Ok(())
+    }
+
+    pub fn get_cell(&self, row: i64, col: i64) -> bool {
+        if !self.contains(row, col) {
+            return false;
+        }
+        let (top, left) = self.origin;
+        self.get_in(self.root, row - top, col - left)
+    }
+
+    // Advance 2^exponent generations in a single step, for an exponent of
+    // at most MAX_STEP_EXPONENT
+    pub fn step_pow2(&mut self, exponent: u32) -> Result<(), HashLifeError> {
+        if exponent > Self::MAX_STEP_EXPONENT {
+            return Err(HashLifeError::StepTooLarge(exponent));
+        }
+        let generation = self.generation.checked_add(1 << exponent).ok_or(HashLifeError::GenerationOverflow)?;
+        let step = exponent as u8;
+        while self.level() < step + MIN_LEVEL || !self.is_padded() {
+            self.expand()?;
+        }
+
+        let level = self.level();
+        self.root = self.evolve(self.root, step);
+        let shift = Self::side(level - 2);
+        self.origin = (self.origin.0 + shift, self.origin.1 + shift);
+        self.generation = generation;
+
+        if self.nodes.len() > self.max_nodes {
+            self.collect_garbage();
+        }
+        Ok(())
+    }
+
+    // Advance any number of generations, one power of two at a time
+    pub fn advance(&mut self, generations: u64) -> Result<(), HashLifeError> {
+        self.generation.checked_add(generations).ok_or(HashLifeError::GenerationOverflow)?;
+        // Powers of two above the largest step are made of several of them
+        for _ in 0..generations >> Self::MAX_STEP_EXPONENT {
+            self.step_pow2(Self::MAX_STEP_EXPONENT)?;
+        }
+        for exponent in (0..Self::MAX_STEP_EXPONENT).rev() {
+            if generations & (1 << exponent) != 0 {
+                self.step_pow2(exponent)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn population(&self) -> u64 {
+        self.node(self.root).population
+    }
+
+    pub fn node_count(&self) -> u32 {
+        self.nodes.len() as u32
+    }
+
+    pub fn set_max_nodes(&mut self, max_nodes: u32) {
+        self.max_nodes = max_nodes as usize;
+    }
+}
+
+impl Default for HashLife {
+    fn default() -> HashLife {
+        HashLife::new()
+    }
+}
+
+
+//                      Testing
+// ======================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Pattern;
+
+    fn universe_with(pattern: &Pattern, size: u32) -> Universe {
+        let mut universe = Universe::new(size, size);
+        let offset = size / 2 - 3;
+        universe.insert_pattern(pattern, offset, offset, 0);
+        universe
+    }
+
+    #[test]
+    fn test_set_and_get_cell() {
+        let mut hashlife = HashLife::new();
+        hashlife.set_cell(-100, 250, true).unwrap();
+        hashlife.set_cell(3, 4, true).unwrap();
+
+        assert!(hashlife.get_cell(-100, 250));
+        assert!(hashlife.get_cell(3, 4));
+        assert!(!hashlife.get_cell(4, 3));
+        assert_eq!(hashlife.population(), 2);
+
+        hashlife.set_cell(3, 4, false).unwrap();
+        assert_eq!(hashlife.population(), 1);
+    }
+
+    #[test]
+    fn test_glider_moves_diagonally() {
+        let universe = universe_with(&Pattern::glider(), 16);
+        let mut hashlife = HashLife::from_universe(&universe).unwrap();
+
+        hashlife.step_pow2(2).unwrap();
+        assert_eq!(hashlife.generation(), 4);
+        assert_eq!(hashlife.population(), 5);
+
+        let moved = hashlife.to_universe(1, 1, 16, 16);
+        assert_eq!(moved.get_cells(), universe.get_cells());
+    }
+
+    #[test]
+    fn test_advance_matches_universe() {
+        let mut universe = universe_with(&Pattern::r_pentomino(), 256);
+        let mut hashlife = HashLife::from_universe(&universe).unwrap();
+
+        // 100 isn't a power of two, so this mixes several step sizes
+        hashlife.advance(100).unwrap();
+        for _ in 0..100 {
+            universe.tick();
+        }
+
+        assert_eq!(hashlife.generation(), 100);
+        assert_eq!(hashlife.to_universe(0, 0, 256, 256).get_cells(), universe.get_cells());
+    }
+
+    #[test]
+    fn test_round_trip_keeps_rule() {
+        for rulestring in ["B36/S23", "B2/S"] {
+            let rule = Rule::parse(rulestring).unwrap();
+            let mut universe = universe_with(&Pattern::r_pentomino(), 32);
+            universe.set_rule(&rule);
+            let hashlife = HashLife::from_universe(&universe).unwrap();
+            let copy = hashlife.to_universe(0, 0, 32, 32);
+            assert_eq!(copy.rule(), rule, "{}", rulestring);
+            assert_eq!(copy.get_cells(), universe.get_cells());
+        }
+    }
+
+    #[test]
+    fn test_large_jump_on_gun() {
+        let universe = universe_with(&Pattern::gosper_glider_gun(), 128);
+        let mut hashlife = HashLife::from_universe(&universe).unwrap();
+
+        // The gun emits a five cell glider every 30 generations
+        hashlife.step_pow2(20).unwrap();
+        let gliders = (1u64 << 20) / 30;
+        assert!(hashlife.population() >= 5 * (gliders - 1));
+        assert!(hashlife.population() <= 5 * (gliders + 1) + 64);
+    }
+
+    #[test]
+    fn test_collect_garbage_keeps_pattern() {
+        let universe = universe_with(&Pattern::gosper_glider_gun(), 128);
+        let mut hashlife = HashLife::from_universe(&universe).unwrap();
+        let mut reference = HashLife::from_universe(&universe).unwrap();
+
+        hashlife.set_max_nodes(1000);
+        for _ in 0..10 {
+            hashlife.step_pow2(6).unwrap();
+        }
+        reference.advance(640).unwrap();
+
+        assert_eq!(hashlife.population(), reference.population());
+        assert_eq!(hashlife.to_universe(-50, -50, 400, 400).get_cells(), reference.to_universe(-50, -50, 400, 400).get_cells());
+    }
+
+    #[test]
+    fn test_rejects_b0_rules() {
+        let mut hashlife = HashLife::new();
+        assert!(hashlife.set_rule(&Rule::parse("B03/S23").unwrap()).is_err());
+        assert!(hashlife.set_rule(&Rule::parse("B36/S23").unwrap()).is_ok());
+    }
+
+    #[test]
+    fn test_rejects_other_neighbourhoods() {
+        let mut hashlife = HashLife::new();
+        assert!(hashlife.set_rule(&Rule::parse("B2/S34H").unwrap()).is_err());
+        assert!(hashlife.set_rule(&Rule::parse("B1/S4V").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_rejects_steps_too_large() {
+        let universe = universe_with(&Pattern::glider(), 16);
+        let mut hashlife = HashLife::from_universe(&universe).unwrap();
+        assert_eq!(hashlife.step_pow2(64), Err(HashLifeError::StepTooLarge(64)));
+        assert_eq!(hashlife.step_pow2(256), Err(HashLifeError::StepTooLarge(256)));
+        assert_eq!(hashlife.generation(), 0);
+
+        // The largest step is fine, and the glider is still a glider
+        hashlife.step_pow2(HashLife::MAX_STEP_EXPONENT).unwrap();
+        assert_eq!(hashlife.generation(), 1 << HashLife::MAX_STEP_EXPONENT);
+        assert_eq!(hashlife.population(), 5);
+
+        assert_eq!(hashlife.advance(u64::MAX), Err(HashLifeError::GenerationOverflow));
+        assert_eq!(hashlife.set_cell(i64::MAX, 0, true), Err(HashLifeError::OutOfRange));
+        assert_eq!(hashlife.set_cell(0, i64::MIN, true), Err(HashLifeError::OutOfRange));
+        assert_eq!(hashlife.population(), 5);
+    }
+}
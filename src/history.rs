@@ -0,0 +1,257 @@
+use std::{collections::VecDeque, mem::{size_of, size_of_val}};
+use fixedbitset::FixedBitSet;
+
+use crate::{BlockRule, Rule, Topology};
+
+// Everything needed to carry on running a universe from a generation
+#[derive(Clone, Debug)]
+pub(crate) struct Frame {
+    pub generation: u64,
+    pub width: usize,
+    pub height: usize,
+    pub cells: FixedBitSet,
+    pub rule: Rule,
+    pub topology: Topology,
+    pub block_rule: Option<BlockRule>,
+    pub block_phase: usize,
+}
+
+impl Frame {
+    fn memory(&self) -> usize {
+        size_of::<Frame>() + size_of_val(self.cells.as_slice())
+    }
+}
+
+// Past generations of a universe. A keyframe is kept every
+// `keyframe_interval` generations, and whenever the universe was edited
+// since the last tick, so replaying from a keyframe always reproduces
+// what happened. The most recent generations are also kept as they are so
+// stepping back a little doesn't need any replaying. When the frames take
+// more than the memory budget the oldest keyframes go first, then the
+// oldest recent generations.
+#[derive(Clone, Debug)]
+pub(crate) struct History {
+    keyframe_interval: u64,
+    recent_capacity: usize,
+    memory_budget: usize,
+    keyframes: Vec<Frame>,
+    recent: VecDeque<Frame>,
+    memory: usize,
+    // Whether the universe changed other than by ticking since the last
+    // frame was recorded
+    edited: bool,
+}
+
+impl History {
+    pub fn new(keyframe_interval: u64, recent_capacity: usize, memory_budget: usize) -> History {
+        History {
+            keyframe_interval: keyframe_interval.max(1),
+            recent_capacity,
+            memory_budget,
+            keyframes: Vec::new(),
+            recent: VecDeque::new(),
+            memory: 0,
+            edited: true,
+        }
+    }
+
+    // An edit at a generation starts a new timeline from there, so frames
+    // of that generation and later are of one that no longer happens
+    pub fn mark_edited(&mut self, generation: u64) {
+        self.forget_from(generation);
+        self.edited = true;
+    }
+
+    pub fn memory(&self) -> usize {
+        self.memory
+    }
+
+    // Earliest generation that can be rebuilt
+    pub fn start(&self) -> Option<u64> {
+        let keyframe = self.keyframes.first().map(|frame| frame.generation);
+        let recent = self.recent.front().map(|frame| frame.generation);
+        match (keyframe, recent) {
+            (Some(keyframe), Some(recent)) => Some(keyframe.min(recent)),
+            (keyframe, recent) => keyframe.or(recent),
+        }
+    }
+
+    // Record the state a universe is about to tick from
+    pub fn record(&mut self, frame: Frame) {
+        let generation = frame.generation;
+        self.truncate(generation);
+        self.remove_recent(generation);
+        let has_keyframe = self.keyframes.last().map_or(false, |keyframe| keyframe.generation == generation);
+
+        if self.edited || self.keyframes.is_empty() || generation % self.keyframe_interval == 0 {
+            if has_keyframe {
+                let old = self.keyframes.pop().unwrap();
+                self.memory -= old.memory();
+            }
+            self.memory += frame.memory();
+            self.keyframes.push(frame);
+        }
+        else if !has_keyframe && self.recent_capacity > 0 {
+            self.memory += frame.memory();
+            self.recent.push_back(frame);
+            if self.recent.len() > self.recent_capacity {
+                let old = self.recent.pop_front().unwrap();
+                self.memory -= old.memory();
+            }
+        }
+        self.edited = false;
+        self.enforce_budget();
+    }
+
+    // Forget every frame after a generation
+    pub fn truncate(&mut self, generation: u64) {
+        self.forget_from(generation.saturating_add(1));
+    }
+
+    // Forget every frame from a generation on
+    fn forget_from(&mut self, generation: u64) {
+        while self.keyframes.last().map_or(false, |frame| frame.generation >= generation) {
+            let old = self.keyframes.pop().unwrap();
+            self.memory -= old.memory();
+        }
+        while self.recent.back().map_or(false, |frame| frame.generation >= generation) {
+            let old = self.recent.pop_back().unwrap();
+            self.memory -= old.memory();
+        }
+    }
+
+    fn remove_recent(&mut self, generation: u64) {
+        if self.recent.back().map_or(false, |frame| frame.generation == generation) {
+            let old = self.recent.pop_back().unwrap();
+            self.memory -= old.memory();
+        }
+    }
+
+    // The frame to rebuild a generation from: the generation itself if
+    // it's recent, otherwise the closest keyframe before it
+    pub fn frame_for(&self, generation: u64) -> Option<&Frame> {
+        if let Some(frame) = self.recent.iter().find(|frame| frame.generation == generation) {
+            return Some(frame);
+        }
+        self.keyframes.iter().rev().find(|frame| frame.generation <= generation)
+    }
+
+    fn enforce_budget(&mut self) {
+        while self.memory > self.memory_budget {
+            let old = if self.keyframes.len() > 1 {
+                self.keyframes.remove(0)
+            }
+            else if let Some(old) = self.recent.pop_front() {
+                old
+            }
+            else {
+                // A lone keyframe is kept even over budget, as without it
+                // nothing could be rebuilt
+                break;
+            };
+            self.memory -= old.memory();
+        }
+    }
+}
+
+
+//                      Testing
+// ======================================================
+#[cfg(test)]
+mod tests {
+    use crate::{Pattern, Universe};
+
+    #[test]
+    fn test_step_back_and_goto_generation() {
+        let mut universe = Universe::new(32, 32);
+        universe.enable_history(10, 4, 1 << 20);
+        universe.insert_pattern(&Pattern::r_pentomino(), 14, 14, 0);
+
+        let mut states = vec![universe.get_cells().clone()];
+        for _ in 0..45 {
+            universe.tick();
+            states.push(universe.get_cells().clone());
+        }
+        assert_eq!(universe.generation(), 45);
+
+        assert!(universe.step_back());
+        assert_eq!(universe.generation(), 44);
+        assert_eq!(universe.get_cells(), &states[44]);
+
+        // Replayed from the keyframe at generation 20
+        assert!(universe.goto_generation(27));
+        assert_eq!(universe.get_cells(), &states[27]);
+        assert!(universe.goto_generation(3));
+        assert_eq!(universe.get_cells(), &states[3]);
+
+        // Going forward just ticks
+        assert!(universe.goto_generation(30));
+        assert_eq!(universe.get_cells(), &states[30]);
+    }
+
+    #[test]
+    fn test_edits_are_kept_when_replaying() {
+        let mut universe = Universe::new(24, 24);
+        universe.enable_history(100, 0, 1 << 20);
+        universe.insert_pattern(&Pattern::glider(), 2, 2, 0);
+        for _ in 0..5 {
+            universe.tick();
+        }
+        universe.insert_pattern(&Pattern::r_pentomino(), 12, 12, 0);
+        let edited = universe.get_cells().clone();
+        for _ in 0..5 {
+            universe.tick();
+        }
+        let expected = universe.get_cells().clone();
+        for _ in 0..5 {
+            universe.tick();
+        }
+
+        // Generation 5 had a keyframe taken after the edit
+        assert!(universe.goto_generation(5));
+        assert_eq!(universe.get_cells(), &edited);
+        assert!(universe.goto_generation(2));
+        assert!(universe.goto_generation(10));
+        assert_eq!(universe.get_cells(), &expected);
+    }
+
+    #[test]
+    fn test_edits_after_going_back() {
+        let mut universe = Universe::new(20, 20);
+        universe.enable_history(10, 4, 1 << 20);
+        universe.insert_pattern(&Pattern::blinker(), 2, 2, 0);
+        for _ in 0..30 {
+            universe.tick();
+        }
+        assert!(universe.goto_generation(12));
+        for (row, col) in [(10, 10), (10, 11), (11, 10), (11, 11)] {
+            universe.toggle_cell(row, col);
+        }
+
+        // Generation 20 is reached from the edit, not from the old timeline
+        assert!(universe.goto_generation(20));
+        let block = [(10, 10), (10, 11), (11, 10), (11, 11)].map(|(row, col)| row * 20 + col);
+        assert!(block.iter().all(|&idx| universe.get_cells()[idx]));
+        assert_eq!(universe.population(), 7);
+        assert!(universe.step_back());
+        assert_eq!(universe.population(), 7);
+    }
+
+    #[test]
+    fn test_memory_budget() {
+        let mut universe = Universe::new(64, 64);
+        // Room for a few frames of 64 words each
+        universe.enable_history(2, 8, 3000);
+        universe.insert_pattern(&Pattern::r_pentomino(), 30, 30, 0);
+        for _ in 0..50 {
+            universe.tick();
+        }
+        assert!(universe.history_memory() <= 3000);
+        assert!(universe.history_start() > 0);
+        assert!(!universe.goto_generation(0));
+        assert!(universe.goto_generation(universe.history_start()));
+
+        universe.disable_history();
+        assert!(!universe.step_back());
+    }
+}
@@ -0,0 +1,232 @@
+use std::fmt;
+
+use crate::RuleError;
+
+// Isotropic non-totalistic rules in Hensel notation, e.g. "B2-a/S12".
+// A neighbour configuration is a byte with a bit per Moore neighbour, in
+// Universe::get_neighbour_array order (NW, N, NE, W, E, SW, S, SE). Each
+// count from 1 to 7 is split into classes of configurations that are
+// rotations or reflections of each other, named by a letter.
+
+// Set of neighbour configurations, a bit for each of the 256
+pub type Configs = [u64; 4];
+
+// Class letters of each neighbour count, in Hensel's order
+const LETTERS: [&str; 9] = ["", "ce", "cekain", "cekainyqjr", "cekainyqjrtwz", "cekainyqjr", "cekain", "ce", ""];
+
+// One configuration from each class, in the same order as LETTERS
+const REPRESENTATIVES: [&[u8]; 9] = [
+    &[],
+    &[0x01, 0x02],
+    &[0x05, 0x0a, 0x0c, 0x03, 0x18, 0x24],
+    &[0x25, 0x1a, 0x32, 0x0b, 0x07, 0x0d, 0x31, 0x26, 0x0e, 0x19],
+    &[0xa5, 0x5a, 0x33, 0x0f, 0x1d, 0x27, 0x35, 0x36, 0x3a, 0x1b, 0x39, 0x2e, 0x3c],
+    &[0x5b, 0xa7, 0x75, 0x2f, 0x1f, 0x3b, 0x5d, 0x3e, 0x37, 0x3d],
+    &[0x5f, 0xaf, 0x77, 0x3f, 0xbd, 0x7e],
+    &[0x7f, 0xbf],
+    &[],
+];
+
+// Row and column in the 3x3 square of each configuration bit
+const POSITIONS: [(usize, usize); 8] = [(0, 0), (0, 1), (0, 2), (1, 0), (1, 2), (2, 0), (2, 1), (2, 2)];
+
+fn transform<F: Fn(usize, usize) -> (usize, usize)>(config: u8, f: F) -> u8 {
+    let mut image = 0;
+    for (bit, &(row, col)) in POSITIONS.iter().enumerate() {
+        if config >> bit & 1 != 0 {
+            let target = f(row, col);
+            image |= 1 << POSITIONS.iter().position(|&p| p == target).unwrap();
+        }
+    }
+    image
+}
+
+// The eight rotations and reflections of a configuration
+fn images(config: u8) -> [u8; 8] {
+    let mut images = [0; 8];
+    let mut current = config;
+    for pair in images.chunks_mut(2) {
+        pair[0] = current;
+        pair[1] = transform(current, |row, col| (row, 2 - col));
+        current = transform(current, |row, col| (col, 2 - row));
+    }
+    images
+}
+
+// Class letter of every configuration, or '\0' for 0 and 8 neighbours
+fn class_letters() -> [char; 256] {
+    let mut letters = ['\0'; 256];
+    for (count, representatives) in REPRESENTATIVES.iter().enumerate() {
+        for (&representative, letter) in representatives.iter().zip(LETTERS[count].chars()) {
+            for image in images(representative) {
+                letters[image as usize] = letter;
+            }
+        }
+    }
+    letters
+}
+
+#[inline(always)]
+pub fn contains(configs: &Configs, config: u8) -> bool {
+    configs[config as usize / 64] >> (config % 64) & 1 != 0
+}
+
+fn insert(configs: &mut Configs, config: u8) {
+    configs[config as usize / 64] |= 1 << (config % 64);
+}
+
+// Whether a part of a rulestring uses Hensel letters at all
+pub fn is_hensel(part: &str) -> bool {
+    part.chars().any(|c| c.is_ascii_alphabetic() || c == '-')
+}
+
+// Parse one part of a rulestring, e.g. "2-a" or "34q", into the set of
+// configurations it includes
+pub fn parse_configs(part: &str) -> Result<Configs, RuleError> {
+    let letters = class_letters();
+    let mut configs = [0; 4];
+    let mut chars = part.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let count = c.to_digit(10).ok_or(RuleError::UnexpectedChar(c))? as u8;
+        if count > 8 {
+            return Err(RuleError::InvalidCount(count));
+        }
+        let negated = chars.next_if_eq(&'-').is_some();
+        let mut chosen = String::new();
+        while let Some(letter) = chars.next_if(|c| c.is_ascii_alphabetic()) {
+            let letter = letter.to_ascii_lowercase();
+            if !LETTERS[count as usize].contains(letter) {
+                return Err(RuleError::UnexpectedChar(letter));
+            }
+            chosen.push(letter);
+        }
+        if negated && chosen.is_empty() {
+            return Err(RuleError::UnexpectedChar('-'));
+        }
+
+        for config in (0..=255u8).filter(|config| config.count_ones() == count as u32) {
+            if chosen.is_empty() || chosen.contains(letters[config as usize]) != negated {
+                insert(&mut configs, config);
+            }
+        }
+    }
+    Ok(configs)
+}
+
+// Whether a set of configurations includes either all or none of each
+// count, so the same set could be written without letters
+pub fn is_totalistic(configs: &Configs) -> bool {
+    (0..=8).all(|count| {
+        let mut members = (0..=255u8).filter(|config| config.count_ones() == count);
+        let first = contains(configs, members.next().unwrap());
+        members.all(|config| contains(configs, config) == first)
+    })
+}
+
+// Write a set of configurations in Hensel notation, using whichever of
+// the included or excluded letters is shorter for each count
+pub fn write_configs(f: &mut fmt::Formatter<'_>, configs: &Configs) -> fmt::Result {
+    for count in 0..=8 {
+        let classes = LETTERS[count];
+        let representatives = REPRESENTATIVES[count];
+        if classes.is_empty() {
+            let config = if count == 0 { 0 } else { 0xff };
+            if contains(configs, config) {
+                write!(f, "{}", count)?;
+            }
+            continue;
+        }
+
+        let (included, excluded): (String, String) = {
+            let mut included = String::new();
+            let mut excluded = String::new();
+            for (letter, &representative) in classes.chars().zip(representatives.iter()) {
+                if contains(configs, representative) { included.push(letter) } else { excluded.push(letter) }
+            }
+            (included, excluded)
+        };
+        if included.is_empty() {
+            continue;
+        }
+        write!(f, "{}", count)?;
+        if excluded.is_empty() {
+            continue;
+        }
+        if excluded.len() < included.len() {
+            write!(f, "-{}", excluded)?;
+        }
+        else {
+            write!(f, "{}", included)?;
+        }
+    }
+    Ok(())
+}
+
+
+//                      Testing
+// ======================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classes_partition_configurations() {
+        let letters = class_letters();
+        for config in 0..=255u8 {
+            let count = config.count_ones();
+            let letter = letters[config as usize];
+            if count == 0 || count == 8 {
+                assert_eq!(letter, '\0');
+                continue;
+            }
+            assert!(LETTERS[count as usize].contains(letter), "config {:#04x}", config);
+            // Hensel letters for 5 to 7 neighbours name the complement of
+            // the same class for 3 to 1
+            if count != 4 {
+                assert_eq!(letters[!config as usize], letter, "config {:#04x}", config);
+            }
+        }
+
+        // Number of configurations in each class, in LETTERS order
+        let sizes: [&[usize]; 9] = [
+            &[],
+            &[4, 4],
+            &[4, 4, 8, 8, 2, 2],
+            &[4, 4, 4, 4, 4, 8, 4, 8, 8, 8],
+            &[1, 1, 8, 8, 4, 8, 8, 4, 8, 8, 4, 4, 4],
+            &[4, 4, 4, 4, 4, 8, 4, 8, 8, 8],
+            &[4, 4, 8, 8, 2, 2],
+            &[4, 4],
+            &[],
+        ];
+        for (count, sizes) in sizes.iter().enumerate() {
+            for (letter, &size) in LETTERS[count].chars().zip(sizes.iter()) {
+                let members = (0..=255u8)
+                    .filter(|&config| config.count_ones() == count as u32 && letters[config as usize] == letter)
+                    .count();
+                assert_eq!(members, size, "{}{}", count, letter);
+            }
+        }
+        assert_eq!(letters[0xe4], 'n');
+        assert_eq!(letters[0xb8], 'i');
+        assert_eq!(letters[0xc9], 'w');
+    }
+
+    #[test]
+    fn test_parse_configs() {
+        let configs = parse_configs("2-a").unwrap();
+        assert!(contains(&configs, 0x05));
+        assert!(!contains(&configs, 0x03));
+        assert!(!contains(&configs, 0x07));
+
+        assert_eq!(parse_configs("1ce").unwrap(), parse_configs("1").unwrap());
+        assert!(is_totalistic(&parse_configs("23").unwrap()));
+        assert!(!is_totalistic(&configs));
+
+        assert_eq!(parse_configs("2x"), Err(RuleError::UnexpectedChar('x')));
+        assert_eq!(parse_configs("2t"), Err(RuleError::UnexpectedChar('t')));
+        assert_eq!(parse_configs("3-"), Err(RuleError::UnexpectedChar('-')));
+        assert_eq!(parse_configs("8c"), Err(RuleError::UnexpectedChar('c')));
+    }
+}
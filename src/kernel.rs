@@ -0,0 +1,405 @@
+use std::{
+    cmp::min,
+    ops::Range,
+};
+use fixedbitset::FixedBitSet;
+use wasm_bindgen::prelude::*;
+
+use crate::{Boundary, Rule, Topology};
+
+// Which implementation Universe::tick uses to compute a generation. New
+// universes use the bit-sliced kernel when built with the parallel
+// feature, so a plain tick runs on every thread, and the active cells
+// kernel otherwise.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kernel {
+    // Visit only the cells that changed last tick, and their neighbours
+    ActiveCells,
+    // Compute every cell, 64 at a time, in parallel row bands when built
+    // with the parallel feature
+    BitSliced,
+}
+
+impl Default for Kernel {
+    #[cfg(feature = "parallel")]
+    fn default() -> Kernel {
+        Kernel::BitSliced
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    fn default() -> Kernel {
+        Kernel::ActiveCells
+    }
+}
+
+// Bit-sliced tick kernel. Each row is loaded into 64 bit words (bit c of
+// word i is column 64 * i + c), the eight neighbour rows are built with
+// whole-word shifts, and the neighbour count of all 64 cells is added up
+// at once with a tree of full adders holding the count in bit planes.
+
+const BLOCK_BITS: usize = usize::BITS as usize;
+
+#[inline(always)]
+fn low_mask(bits: usize) -> u64 {
+    if bits >= 64 { u64::MAX } else { (1 << bits) - 1 }
+}
+
+// Number of words needed to hold a row of the given width
+#[inline(always)]
+pub(crate) fn row_words(width: usize) -> usize {
+    width.div_ceil(64)
+}
+
+// Read up to 64 bits starting at an arbitrary bit offset
+fn read_bits(blocks: &[usize], start: usize, len: usize) -> u64 {
+    let mut word = 0;
+    let mut read = 0;
+    while read < len {
+        let pos = start + read;
+        let offset = pos % BLOCK_BITS;
+        let take = min(BLOCK_BITS - offset, len - read);
+        let bits = (blocks[pos / BLOCK_BITS] >> offset) as u64 & low_mask(take);
+        word |= bits << read;
+        read += take;
+    }
+    word
+}
+
+// Write up to 64 bits starting at an arbitrary bit offset
+fn write_bits(blocks: &mut [usize], start: usize, len: usize, word: u64) {
+    let mut written = 0;
+    while written < len {
+        let pos = start + written;
+        let offset = pos % BLOCK_BITS;
+        let take = min(BLOCK_BITS - offset, len - written);
+        let mask = (low_mask(take) as usize) << offset;
+        let bits = ((word >> written) as usize) << offset;
+        let block = &mut blocks[pos / BLOCK_BITS];
+        *block = (*block & !mask) | (bits & mask);
+        written += take;
+    }
+}
+
+pub(crate) fn load_row(cells: &FixedBitSet, width: usize, row: usize, out: &mut [u64]) {
+    let blocks = cells.as_slice();
+    let start = row * width;
+    for (i, word) in out.iter_mut().enumerate() {
+        let col = i * 64;
+        *word = read_bits(blocks, start + col, min(64, width - col));
+    }
+}
+
+pub(crate) fn store_row(cells: &mut FixedBitSet, width: usize, row: usize, words: &[u64]) {
+    let blocks = cells.as_mut_slice();
+    let start = row * width;
+    for (i, &word) in words.iter().enumerate() {
+        let col = i * 64;
+        write_bits(blocks, start + col, min(64, width - col), word);
+    }
+}
+
+// Bit c of the output is column c - 1 of the row, wrapping at the west
+// edge if wrap is set and dead beyond it otherwise
+fn shift_west(row: &[u64], width: usize, wrap: bool, out: &mut [u64]) {
+    let mut carry = if wrap { (row[(width - 1) / 64] >> ((width - 1) % 64)) & 1 } else { 0 };
+    for (i, word) in out.iter_mut().enumerate() {
+        *word = (row[i] << 1) | carry;
+        carry = row[i] >> 63;
+    }
+    let last = out.len() - 1;
+    out[last] &= low_mask(width - last * 64);
+}
+
+// Bit c of the output is column c + 1 of the row, wrapping at the east
+// edge if wrap is set and dead beyond it otherwise
+fn shift_east(row: &[u64], width: usize, wrap: bool, out: &mut [u64]) {
+    let words = row.len();
+    for (i, word) in out.iter_mut().enumerate() {
+        let carry = if i + 1 < words { row[i + 1] << 63 } else { 0 };
+        *word = (row[i] >> 1) | carry;
+    }
+    if wrap {
+        let last_col = width - 1;
+        out[last_col / 64] |= (row[0] & 1) << (last_col % 64);
+    }
+}
+
+#[inline(always)]
+fn full_add(a: u64, b: u64, c: u64) -> (u64, u64) {
+    let partial = a ^ b;
+    (partial ^ c, (a & b) | (c & partial))
+}
+
+#[inline(always)]
+fn half_add(a: u64, b: u64) -> (u64, u64) {
+    (a ^ b, a & b)
+}
+
+// Isotropic rules depend on the arrangement of the neighbours, so look
+// each of the 64 cells up in the rule's table one at a time
+fn next_word_isotropic(table: &[u64; 8], cell: u64, neighbours: [u64; 8]) -> u64 {
+    let mut next = 0;
+    for bit in 0..64 {
+        let mut index = ((cell >> bit) & 1) << 8;
+        for (i, word) in neighbours.iter().enumerate() {
+            index |= ((word >> bit) & 1) << i;
+        }
+        next |= ((table[index as usize / 64] >> (index % 64)) & 1) << bit;
+    }
+    next
+}
+
+// Next state of 64 cells at once, given the eight neighbour words
+#[inline(always)]
+pub(crate) fn next_word(rule: &Rule, cell: u64, neighbours: [u64; 8]) -> u64 {
+    if let Some(table) = rule.isotropic_table() {
+        return next_word_isotropic(table, cell, neighbours);
+    }
+    let [n0, n1, n2, n3, n4, n5, n6, n7] = neighbours;
+
+    // Count bits of weight 1, 2, 4 and 8
+    let (s0, c0) = full_add(n0, n1, n2);
+    let (s1, c1) = full_add(n3, n4, n5);
+    let (s2, c2) = half_add(n6, n7);
+    let (ones, c3) = full_add(s0, s1, s2);
+    let (t0, c4) = full_add(c0, c1, c2);
+    let (twos, c5) = half_add(t0, c3);
+    let (fours, eights) = half_add(c4, c5);
+
+    let mut next = 0;
+    for count in 0..=8u8 {
+        let (born, survives) = (rule.births(count), rule.survives(count));
+        if !born && !survives {
+            continue;
+        }
+        let bit = |plane: u64, weight: u8| if count & weight != 0 { plane } else { !plane };
+        let matches = bit(ones, 1) & bit(twos, 2) & bit(fours, 4) & bit(eights, 8);
+        let applies = match (born, survives) {
+            (true, true) => u64::MAX,
+            (true, false) => !cell,
+            _ => cell,
+        };
+        next |= matches & applies;
+    }
+    next
+}
+
+// A loaded row together with its west and east shifted copies
+struct ShiftedRow {
+    centre: Vec<u64>,
+    west: Vec<u64>,
+    east: Vec<u64>,
+}
+
+impl ShiftedRow {
+    fn new(words: usize) -> ShiftedRow {
+        ShiftedRow { centre: vec![0; words], west: vec![0; words], east: vec![0; words] }
+    }
+
+    // Load a row, or an all dead row for rows beyond a plane's edges
+    fn load(&mut self, cells: &FixedBitSet, width: usize, row: Option<usize>, wrap: bool) {
+        match row {
+            Some(row) => load_row(cells, width, row, &mut self.centre),
+            None => self.centre.fill(0),
+        }
+        shift_west(&self.centre, width, wrap, &mut self.west);
+        shift_east(&self.centre, width, wrap, &mut self.east);
+    }
+}
+
+// Whether the bit-sliced kernel can run a rule on a topology. Neighbourhoods
+// inside the 3x3 square are handled by masking out the missing neighbour
+// words, and only plain tori and planes are supported.
+pub(crate) fn supports(rule: &Rule, topology: &Topology) -> bool {
+    rule.neighbourhood().moore_subset().is_some()
+        && (topology.is_plain_torus() || topology.boundary() == Boundary::Plane)
+}
+
+// Compute the next generation of a band of rows on a plain torus or a
+// plane, writing the row words one after another into out
+pub(crate) fn step_rows(cells: &FixedBitSet, width: usize, height: usize, rule: &Rule, topology: &Topology, rows: Range<usize>, out: &mut Vec<u64>) {
+    let words = row_words(width);
+    let wrap = topology.is_plain_torus();
+    let row_above = |row: usize| if row > 0 { Some(row - 1) } else if wrap { Some(height - 1) } else { None };
+    let row_below = |row: usize| if row + 1 < height { Some(row + 1) } else if wrap { Some(0) } else { None };
+    let included = rule.neighbourhood().moore_subset().expect("neighbourhood wider than the 3x3 square");
+    let masks = included.map(|include| if include { u64::MAX } else { 0 });
+    let moore = included == [true; 8];
+    out.clear();
+    out.reserve(rows.len() * words);
+    if rows.is_empty() {
+        return;
+    }
+
+    let mut north = ShiftedRow::new(words);
+    let mut centre = ShiftedRow::new(words);
+    let mut south = ShiftedRow::new(words);
+    north.load(cells, width, row_above(rows.start), wrap);
+    centre.load(cells, width, Some(rows.start), wrap);
+
+    for row in rows {
+        south.load(cells, width, row_below(row), wrap);
+        for i in 0..words {
+            let mut neighbours = [
+                north.west[i], north.centre[i], north.east[i],
+                centre.west[i], centre.east[i],
+                south.west[i], south.centre[i], south.east[i],
+            ];
+            if !moore {
+                for (word, mask) in neighbours.iter_mut().zip(masks) {
+                    *word &= mask;
+                }
+            }
+            out.push(next_word(rule, centre.centre[i], neighbours));
+        }
+        // Roll the rows down, reusing the old north row's buffers
+        std::mem::swap(&mut north, &mut centre);
+        std::mem::swap(&mut centre, &mut south);
+    }
+}
+
+// Write a band of rows produced by step_rows, starting at first_row
+pub(crate) fn store_rows(cells: &mut FixedBitSet, width: usize, first_row: usize, words: &[u64]) {
+    for (i, row) in words.chunks(row_words(width)).enumerate() {
+        store_row(cells, width, first_row + i, row);
+    }
+}
+
+// Compute the next generation of the whole universe into next
+pub(crate) fn step_sequential(cells: &FixedBitSet, next: &mut FixedBitSet, width: usize, height: usize, rule: &Rule, topology: &Topology) {
+    let mut rows = Vec::new();
+    step_rows(cells, width, height, rule, topology, 0..height, &mut rows);
+    store_rows(next, width, 0, &rows);
+}
+
+// Smallest band worth handing to another thread
+#[cfg(feature = "parallel")]
+const MIN_BAND_ROWS: usize = 16;
+
+// Compute the next generation in bands of rows on rayon's thread pool.
+// Every band reads the rows just outside it from the current generation,
+// which is shared read-only, so the halo rows at band edges need no
+// exchange. Bands aren't word aligned in the bitset, so they are written
+// back one after another.
+#[cfg(feature = "parallel")]
+pub(crate) fn step_parallel(cells: &FixedBitSet, next: &mut FixedBitSet, width: usize, height: usize, rule: &Rule, topology: &Topology) {
+    use rayon::prelude::*;
+
+    let band_rows = std::cmp::max(MIN_BAND_ROWS, height.div_ceil(rayon::current_num_threads() * 4));
+    let bands: Vec<Range<usize>> = (0..height).step_by(band_rows)
+        .map(|start| start..min(start + band_rows, height))
+        .collect();
+    let words: Vec<Vec<u64>> = bands.par_iter()
+        .map(|band| {
+            let mut out = Vec::new();
+            step_rows(cells, width, height, rule, topology, band.clone(), &mut out);
+            out
+        })
+        .collect();
+
+    for (band, words) in bands.iter().zip(words.iter()) {
+        store_rows(next, width, band.start, words);
+    }
+}
+
+// Universes too short to split into two bands stay on this thread
+#[cfg(feature = "parallel")]
+pub(crate) fn step(cells: &FixedBitSet, next: &mut FixedBitSet, width: usize, height: usize, rule: &Rule, topology: &Topology) {
+    if height < 2 * MIN_BAND_ROWS {
+        step_sequential(cells, next, width, height, rule, topology)
+    }
+    else {
+        step_parallel(cells, next, width, height, rule, topology)
+    }
+}
+
+#[cfg(not(feature = "parallel"))]
+pub(crate) fn step(cells: &FixedBitSet, next: &mut FixedBitSet, width: usize, height: usize, rule: &Rule, topology: &Topology) {
+    step_sequential(cells, next, width, height, rule, topology)
+}
+
+
+//                      Testing
+// ======================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_and_store_row() {
+        let width = 100;
+        let mut cells = FixedBitSet::with_capacity(width * 3);
+        for col in [0, 1, 63, 64, 99] {
+            cells.insert(width + col);
+        }
+
+        let mut row = vec![0; row_words(width)];
+        load_row(&cells, width, 1, &mut row);
+        assert_eq!(row, vec![(1 << 63) | 0b11, 1 | (1 << 35)]);
+
+        let mut copy = FixedBitSet::with_capacity(width * 3);
+        store_row(&mut copy, width, 2, &row);
+        let expected: Vec<usize> = cells.ones().map(|i| i + width).collect();
+        assert_eq!(copy.ones().collect::<Vec<usize>>(), expected);
+    }
+
+    #[test]
+    fn test_shifts_wrap() {
+        let width = 70;
+        // Columns 0 and 69 alive
+        let row = vec![1, 1 << 5];
+
+        let mut west = vec![0; 2];
+        shift_west(&row, width, true, &mut west);
+        assert_eq!(west, vec![0b11, 0]);
+        shift_west(&row, width, false, &mut west);
+        assert_eq!(west, vec![0b10, 0]);
+
+        let mut east = vec![0; 2];
+        shift_east(&row, width, true, &mut east);
+        assert_eq!(east, vec![0, (1 << 4) | (1 << 5)]);
+        shift_east(&row, width, false, &mut east);
+        assert_eq!(east, vec![0, 1 << 4]);
+    }
+
+    #[test]
+    fn test_next_word_counts() {
+        let rule = Rule::conway();
+        // Bit 0: dead with three neighbours, bit 1: alive with two,
+        // bit 2: alive with five, bit 3: dead with eight
+        let cell = 0b0110;
+        let neighbours = [0b1111, 0b1111, 0b1101, 0b1100, 0b1100, 0b1000, 0b1000, 0b1000];
+        assert_eq!(next_word(&rule, cell, neighbours), 0b0011);
+    }
+
+    #[test]
+    fn test_default_kernel() {
+        let expected = if cfg!(feature = "parallel") { Kernel::BitSliced } else { Kernel::ActiveCells };
+        assert_eq!(Kernel::default(), expected);
+        assert_eq!(crate::Universe::new(8, 8).kernel(), expected);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel_matches_sequential() {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(4).build().unwrap();
+        for (width, height) in [(1, 1), (70, 15), (64, 64), (100, 131)] {
+            for topology in [Topology::torus(), Topology::plane()] {
+                let mut cells = FixedBitSet::with_capacity(width * height);
+                for i in (0..width * height).filter(|i| i % 3 == 0 || i % 7 == 0) {
+                    cells.insert(i);
+                }
+
+                for _ in 0..10 {
+                    let mut sequential = FixedBitSet::with_capacity(width * height);
+                    let mut parallel = FixedBitSet::with_capacity(width * height);
+                    step_sequential(&cells, &mut sequential, width, height, &Rule::conway(), &topology);
+                    pool.install(|| step_parallel(&cells, &mut parallel, width, height, &Rule::conway(), &topology));
+                    assert_eq!(sequential, parallel, "{}x{} {}", width, height, topology);
+                    cells = sequential;
+                }
+            }
+        }
+    }
+}
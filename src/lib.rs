@@ -1,5 +1,25 @@
 mod utils;
 mod timer;
+mod rule;
+mod hashlife;
+mod kernel;
+mod generations;
+mod ltl;
+mod neighbourhood;
+mod topology;
+mod sparse;
+mod isotropic;
+mod ruletable;
+mod elementary;
+mod margolus;
+mod history;
+mod stats;
+mod random;
+mod anchor;
+mod cycle;
+mod analysis;
+mod census;
+mod formats;
 extern crate js_sys;
 extern crate web_sys;
 
@@ -8,6 +28,10 @@ use fixedbitset::FixedBitSet;
 
 #[allow(unused_imports)]
 use timer::Timer;
+use history::{Frame, History};
+use stats::Stats;
+use random::Random;
+use cycle::CycleDetector;
 #[allow(unused_imports)]
 use web_sys::console;
 #[allow(unused_imports)]
@@ -17,6 +41,24 @@ use std:: {
     fmt,
 };
 
+pub use rule::{Rule, RuleError};
+pub use hashlife::{HashLife, HashLifeError};
+pub use kernel::Kernel;
+pub use generations::{GenerationsRule, GenerationsUniverse};
+pub use ltl::{LargerThanLife, LtlRule, RangeShape};
+pub use neighbourhood::Neighbourhood;
+pub use topology::{Boundary, Topology};
+pub use sparse::SparseUniverse;
+pub use ruletable::{RuleTable, RuleTableError, RuleTableUniverse};
+pub use elementary::ElementaryUniverse;
+pub use margolus::BlockRule;
+pub use random::seed_from_str;
+pub use anchor::{Anchor, GeometryError};
+pub use cycle::{CycleKind, CycleReport};
+pub use analysis::{analyse_pattern, PatternAnalysis, PatternKind};
+pub use census::{Census, CensusEntry};
+pub use formats::PatternError;
+
 // A macro to provide console logging syntax
 #[allow(unused_macros)]
 macro_rules! log {
@@ -31,9 +73,29 @@ pub struct Universe {
     height: usize,
     buffers: [FixedBitSet; 2],
     active_cell_buffers: [FixedBitSet; 2],
-    curr_index: usize
+    curr_index: usize,
+    rule: Rule,
+    topology: Topology,
+    kernel: Kernel,
+    // Block rule run on the Margolus partition instead of the rule, and
+    // which of the two partitions the next tick uses
+    block_rule: Option<BlockRule>,
+    block_phase: usize,
+    generation: u64,
+    history: Option<History>,
+    // Golly style speed: each step runs 2^step_exponent generations, or
+    // for negative exponents one generation every 2^-step_exponent steps
+    step_exponent: i32,
+    steps_waited: u32,
+    stats: Stats,
+    cycle_detector: Option<CycleDetector>,
 }
 
+// Range of step exponents, from a generation every 64 steps to about a
+// million generations a step
+const MIN_STEP_EXPONENT: i32 = -6;
+const MAX_STEP_EXPONENT: i32 = 20;
+
 // Pattern struct to hold various patterns we might want
 // to add to our universe. Use type aliasing as Patterns
 // need essentially the exact same fields as Universe, 
@@ -42,6 +104,31 @@ pub struct Universe {
 type Pattern = Universe;
 
 impl Universe {
+    fn from_buffers(width: usize, height: usize, current: FixedBitSet, curr_active: FixedBitSet) -> Universe {
+        let size = width * height;
+        let next = FixedBitSet::with_capacity(size);
+        let next_active = FixedBitSet::with_capacity(size);
+
+        Universe { 
+            width, 
+            height, 
+            buffers: [current, next], 
+            active_cell_buffers: [curr_active, next_active], 
+            curr_index: 0, 
+            rule: Rule::default(),
+            topology: Topology::default(),
+            kernel: Kernel::default(),
+            block_rule: None,
+            block_phase: 0,
+            generation: 0,
+            history: None,
+            step_exponent: 0,
+            steps_waited: 0,
+            stats: Stats::new(),
+            cycle_detector: None,
+        }
+    }
+
     #[inline(always)]
     fn get_index(width: usize, row: usize, column: usize) -> usize {
         row * width + column
@@ -63,19 +150,263 @@ impl Universe {
 
     fn get_angle_index(&self, row: usize, col: usize, angle: u32) -> usize {
         match angle {
-            90 => ((self.height - col - 1) * self.width + row) as usize, 
-            180 => self.buffers[0].len() - (row * self.width + col + 1) as usize,
-            270 => (col * self.width + (self.width - row - 1)) as usize,
-            _ => (row * self.width + col) as usize
+            90 => (self.height - col - 1) * self.width + row, 
+            180 => self.buffers[0].len() - (row * self.width + col + 1),
+            270 => col * self.width + (self.width - row - 1),
+            _ => row * self.width + col
         }
     }
 
-    fn insert_neighbours(active_cells: &mut FixedBitSet, index: usize, width: usize, height: usize) {
+    // Index of the cell at a row and column offset, mapped back onto the
+    // grid by the topology, or None if it's beyond a dead edge
+    #[inline(always)]
+    fn offset_index(index: usize, width: usize, height: usize, topology: &Topology, row: i32, col: i32) -> Option<usize> {
+        let r = (index / width) as isize + row as isize;
+        let c = (index % width) as isize + col as isize;
+        topology.wrap(r, c, width, height).map(|(r, c)| Self::get_index(width, r, c))
+    }
+
+    // Insert every cell whose neighbourhood contains the cell at index.
+    // Custom neighbourhoods needn't be symmetric, so on a torus or plane
+    // these are the cells at the negated offsets. Twisted edges can also
+    // reflect or transpose the neighbourhood, so there every reflection
+    // and rotation of the offsets is marked.
+    fn insert_neighbours(active_cells: &mut FixedBitSet, index: usize, width: usize, height: usize, neighbourhood: &Neighbourhood, topology: &Topology) {
         unsafe {
-            for i in Self::get_neighbours(index, width, height) {
-                active_cells.insert_unchecked(i);
+            if neighbourhood.is_moore() && topology.is_plain_torus() {
+                for i in Self::get_neighbours(index, width, height) {
+                    active_cells.insert_unchecked(i);
+                }
+            }
+            else if topology.is_translational() {
+                for (row, col) in neighbourhood.offsets() {
+                    if let Some(i) = Self::offset_index(index, width, height, topology, -row, -col) {
+                        active_cells.insert_unchecked(i);
+                    }
+                }
+            }
+            else {
+                for (row, col) in neighbourhood.offsets() {
+                    let images = IntoIterator::into_iter([(row, col), (col, row)])
+                        .flat_map(|(r, c)| [(r, c), (-r, c), (r, -c), (-r, -c)]);
+                    for (r, c) in images {
+                        if let Some(i) = Self::offset_index(index, width, height, topology, r, c) {
+                            active_cells.insert_unchecked(i);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Mark a cell and its neighbours to be checked on the next tick
+    fn mark_active(&mut self, index: usize) {
+        self.mark_edited();
+        let active_cells = &mut self.active_cell_buffers[self.curr_index];
+        active_cells.insert(index);
+        Self::insert_neighbours(active_cells, index, self.width, self.height, &self.rule.neighbourhood(), &self.topology);
+    }
+
+    // Mark every cell to be checked on the next tick, for changes that
+    // could affect cells anywhere in the universe
+    fn mark_all_active(&mut self) {
+        self.mark_edited();
+        self.active_cell_buffers[self.curr_index].insert_range(..);
+    }
+
+    // Reallocate every buffer after the universe changes size
+    fn reset_buffers(&mut self) {
+        self.mark_edited();
+        let size = self.width * self.height;
+        self.buffers = [FixedBitSet::with_capacity(size), FixedBitSet::with_capacity(size)];
+        self.active_cell_buffers = [FixedBitSet::with_capacity(size), FixedBitSet::with_capacity(size)];
+    }
+
+    fn tick_active_cells(&mut self) {
+        if !self.rule.neighbourhood().is_moore() || !self.topology.is_plain_torus() {
+            return self.tick_active_cells_offsets();
+        }
+        let next_index = 1 - self.curr_index;
+        let (width, height) = (self.width, self.height);
+        let rule = self.rule;
+        let isotropic = rule.is_isotropic();
+        unsafe {
+            let current = self.buffers.as_mut_ptr().add(self.curr_index);
+            let next = self.buffers.as_mut_ptr().add(next_index);
+            let curr_active = self.active_cell_buffers.as_mut_ptr().add(self.curr_index);
+            let next_active = self.active_cell_buffers.as_mut_ptr().add(next_index);
+
+            // The next active set only holds cells affected by this tick,
+            // anything left over from two ticks ago is stale
+            (*next_active).clear();
+
+            for idx in (*curr_active).ones() {
+                let cell = (*current).contains_unchecked(idx);
+                let neighbours = Self::get_neighbour_array(idx, width, height);
+
+                let live = if isotropic {
+                    // One bit per neighbour, so the rule can see where the
+                    // live neighbours are
+                    let mut configuration = 0;
+                    for (bit, &n) in neighbours.iter().enumerate() {
+                        configuration |= ((*current).contains_unchecked(n) as u8) << bit;
+                    }
+                    rule.next_state_of(cell, configuration)
+                }
+                else {
+                    let mut live_neighbours = 0;
+                    for &n in neighbours.iter() {
+                        live_neighbours += (*current).contains_unchecked(n) as u8;
+                    }
+                    rule.next_state(cell, live_neighbours)
+                };
+                let changed = live != cell;
+                (*next).set_unchecked(idx, live);
+
+                if changed {
+                    (*next_active).insert_unchecked(idx);
+                    for &n in neighbours.iter() {
+                        (*next_active).insert_unchecked(n);
+                    }
+                }
             }
         }
+        self.curr_index = next_index;
+    }
+
+    // Active cell tick for any neighbourhood and topology, reading
+    // neighbours through their offsets rather than the unrolled Moore
+    // indices
+    fn tick_active_cells_offsets(&mut self) {
+        let next_index = 1 - self.curr_index;
+        let (width, height) = (self.width, self.height);
+        let (rule, topology) = (self.rule, self.topology);
+        let neighbourhood = rule.neighbourhood();
+        let offsets: Vec<(i32, i32)> = neighbourhood.offsets().collect();
+        let (buffers, active_cell_buffers) = (&mut self.buffers, &mut self.active_cell_buffers);
+        let (current, next) = Self::split_buffers(buffers, self.curr_index);
+        let (curr_active, next_active) = Self::split_buffers(active_cell_buffers, self.curr_index);
+
+        next_active.clear();
+        for idx in curr_active.ones() {
+            let cell = current[idx];
+            let live = if rule.is_isotropic() {
+                // Isotropic rules are Moore only, whose offsets come in
+                // get_neighbour_array order
+                let mut configuration = 0;
+                for (bit, &(row, col)) in offsets.iter().enumerate() {
+                    let alive = Self::offset_index(idx, width, height, &topology, row, col).map_or(false, |n| current[n]);
+                    configuration |= (alive as u8) << bit;
+                }
+                rule.next_state_of(cell, configuration)
+            }
+            else {
+                let live_neighbours = offsets.iter()
+                    .filter_map(|&(row, col)| Self::offset_index(idx, width, height, &topology, row, col))
+                    .filter(|&n| current[n])
+                    .count();
+                rule.next_state(cell, live_neighbours as u8)
+            };
+            next.set(idx, live);
+            if live != cell {
+                next_active.insert(idx);
+                Self::insert_neighbours(next_active, idx, width, height, &neighbourhood, &topology);
+            }
+        }
+        self.curr_index = next_index;
+    }
+
+    // Current and next buffers of a double-buffered pair
+    fn split_buffers(buffers: &mut [FixedBitSet; 2], curr_index: usize) -> (&FixedBitSet, &mut FixedBitSet) {
+        let (first, second) = buffers.split_at_mut(1);
+        if curr_index == 0 { (&first[0], &mut second[0]) } else { (&second[0], &mut first[0]) }
+    }
+
+    fn tick_bit_sliced(&mut self) {
+        let next_index = 1 - self.curr_index;
+        let (width, height) = (self.width, self.height);
+        if width > 0 && height > 0 {
+            let (current, next) = Self::split_buffers(&mut self.buffers, self.curr_index);
+            kernel::step(current, next, width, height, &self.rule, &self.topology);
+        }
+        self.curr_index = next_index;
+    }
+
+    // Changes other than ticking can't be replayed, so the history needs a
+    // keyframe of the state they leave
+    fn mark_edited(&mut self) {
+        if let Some(history) = self.history.as_mut() {
+            history.mark_edited(self.generation);
+        }
+        if let Some(detector) = self.cycle_detector.as_mut() {
+            detector.reset();
+        }
+    }
+
+    fn frame(&self) -> Frame {
+        Frame {
+            generation: self.generation,
+            width: self.width,
+            height: self.height,
+            cells: self.buffers[self.curr_index].clone(),
+            rule: self.rule,
+            topology: self.topology,
+            block_rule: self.block_rule,
+            block_phase: self.block_phase,
+        }
+    }
+
+    fn restore(&mut self, frame: &Frame) {
+        if (frame.width, frame.height) != (self.width, self.height) {
+            self.width = frame.width;
+            self.height = frame.height;
+            self.reset_buffers();
+        }
+        self.buffers[self.curr_index].clone_from(&frame.cells);
+        self.rule = frame.rule;
+        self.topology = frame.topology;
+        self.block_rule = frame.block_rule;
+        self.block_phase = frame.block_phase;
+        self.generation = frame.generation;
+        self.mark_all_active();
+    }
+
+    fn record_stats(&mut self) {
+        let current = &self.buffers[self.curr_index];
+        let previous = &self.buffers[1 - self.curr_index];
+        self.stats.record(previous, current, self.generation);
+        if let Some(detector) = self.cycle_detector.as_mut() {
+            detector.observe(current, self.width, self.generation);
+        }
+    }
+
+    fn tick_block(&mut self, block_rule: &BlockRule, phase: usize) {
+        let next_index = 1 - self.curr_index;
+        let (width, height) = (self.width, self.height);
+        if width > 0 && height > 0 {
+            let (current, next) = Self::split_buffers(&mut self.buffers, self.curr_index);
+            block_rule.step(current, next, width, height, phase, self.topology.is_plain_torus());
+        }
+        self.curr_index = next_index;
+    }
+
+    // Resize without checking the topology allows the new size
+    fn resize_unchecked(&mut self, width: usize, height: usize, anchor: Anchor) {
+        let (row_offset, col_offset) = anchor.offsets((self.width, self.height), (width, height));
+        let old_width = self.width;
+        let old_cells = std::mem::take(&mut self.buffers[self.curr_index]);
+
+        self.width = width;
+        self.height = height;
+        self.reset_buffers();
+        for idx in old_cells.ones() {
+            let row = (idx / old_width) as isize + row_offset;
+            let col = (idx % old_width) as isize + col_offset;
+            if (0..height as isize).contains(&row) && (0..width as isize).contains(&col) {
+                self.buffers[self.curr_index].insert(Self::get_index(width, row as usize, col as usize));
+            }
+        }
+        self.mark_all_active();
     }
 }
 
@@ -88,34 +419,47 @@ impl Universe {
         let (width, height) = (width as usize, height as usize);
         let size = width * height;
         let current = FixedBitSet::with_capacity(size);
-        let next = FixedBitSet::with_capacity(size); 
         let curr_active = FixedBitSet::with_capacity(size);
-        let next_active = FixedBitSet::with_capacity(size);
 
-        Universe { width, height, buffers: [current, next], active_cell_buffers: [curr_active, next_active], curr_index: 0 }
+        Self::from_buffers(width, height, current, curr_active)
     }
 
+    // Random soup with a quarter of the cells alive, different every time
     pub fn new_rand(width: u32, height: u32) -> Universe {
-        // Enable logging for panics
-        utils::set_panic_hook();
-        let (width, height) = (width as usize, height as usize);
-        let size = width * height;
-        let mut current = FixedBitSet::with_capacity(size);
-        let next = FixedBitSet::with_capacity(size);
-        let mut curr_active = FixedBitSet::with_capacity(size);
-        let next_active = FixedBitSet::with_capacity(size);
+        Self::new_soup(width, height, random::random_seed(), 0.25)
+    }
 
-        for i in 0..size{
-            let state = js_sys::Math::random() < 0.25;
-            current.set(i, state);
-            curr_active.insert(i);
-            Self::insert_neighbours(&mut curr_active, i, width, height);
-        }
-        
-        Universe { width, height, buffers: [current, next], active_cell_buffers: [curr_active, next_active], curr_index: 0 }
+    // Random soup from a seed, the same for the same seed on every
+    // platform. Use seed_from_str to seed from a string.
+    pub fn new_soup(width: u32, height: u32, seed: u64, density: f64) -> Universe {
+        let mut universe = Universe::new(width, height);
+        universe.fill_soup(seed, density);
+        universe
     }
 
-    
+    // Replace every cell with a seeded random soup
+    pub fn fill_soup(&mut self, seed: u64, density: f64) {
+        self.fill_soup_region(seed, density, 0, 0, self.width as u32, self.height as u32);
+    }
+
+    // Replace the cells in a region with a seeded random soup, clipped to
+    // the universe. Cells are filled row by row, so the same seed and
+    // region size always give the same soup wherever it's put.
+    pub fn fill_soup_region(&mut self, seed: u64, density: f64, row: u32, column: u32, width: u32, height: u32) {
+        let mut random = Random::new(seed);
+        let (row, column, width) = (row as usize, column as usize, width as usize);
+        let rows = row.min(self.height)..row.saturating_add(height as usize).min(self.height);
+        let columns = column.min(self.width)..column.saturating_add(width).min(self.width);
+        for r in rows {
+            for c in columns.clone() {
+                let idx = Self::get_index(self.width, r, c);
+                self.buffers[self.curr_index].set(idx, random.chance(density));
+            }
+            // Cells past the right edge would have used these numbers
+            random.skip((width - columns.len()) as u64);
+        }
+        self.mark_all_active();
+    }
 
     pub fn new_sparse(width: u32, height: u32, scarcity: u32) -> Universe {
         // Enable logging for panics
@@ -123,32 +467,21 @@ impl Universe {
         let (width, height, scarcity) = (width as usize, height as usize, scarcity as usize);
         let size = width * height;
         let mut current = FixedBitSet::with_capacity(size);
-        let next = FixedBitSet::with_capacity(size);
         let mut curr_active = FixedBitSet::with_capacity(size);
-        let next_active = FixedBitSet::with_capacity(size);
 
         for i in 0..size {
             let state = i % scarcity == 0;
             current.set(i, state);
-            curr_active.insert(i);
-            Self::insert_neighbours(&mut curr_active, i, width, height);
         }
+        curr_active.insert_range(..);
 
-        Universe { width, height, buffers: [current, next], active_cell_buffers: [curr_active, next_active], curr_index: 0 }
+        Self::from_buffers(width, height, current, curr_active)
     }
 
     pub fn new_oscillators(width: u32, height: u32, spacing: u32) -> Universe {
         // Enable logging for panics
         utils::set_panic_hook();
-        let (w, h) = (width as usize, height as usize);
-        let size = w * h;
-        let current = FixedBitSet::with_capacity(size);
-        let next = FixedBitSet::with_capacity(size);
-        let curr_active = FixedBitSet::with_capacity(size);
-        let next_active = FixedBitSet::with_capacity(size);
-        
-        let mut universe = Universe 
-        { width: w, height: h, buffers: [current, next], active_cell_buffers: [curr_active, next_active], curr_index: 0 };
+        let mut universe = Universe::new(width, height);
 
         let pattern = Pattern::blinker();
 
@@ -169,22 +502,32 @@ impl Universe {
         self.width as u32
     }
 
+    // Change the width, keeping the cells on the left. A sphere has to stay
+    // square, so its height changes too.
     pub fn set_width(&mut self, width: u32) {
-        self.width = width as usize;
-        let size = self.width * self.height;
-        self.buffers[0] = FixedBitSet::with_capacity(size);
-        self.buffers[1] = FixedBitSet::with_capacity(size);
-    } 
+        let height = if self.topology.boundary() == Boundary::Sphere { width } else { self.height as u32 };
+        self.resize_unchecked(width as usize, height as usize, Anchor::TopLeft);
+    }
 
     pub fn height(&self) -> u32 {
         self.height as u32
     }
 
+    // Change the height, keeping the cells at the top. A sphere has to stay
+    // square, so its width changes too.
     pub fn set_height(&mut self, height: u32) {
-        self.height = height as usize;
-        let size = self.width * self.height;
-        self.buffers[0] = FixedBitSet::with_capacity(size);
-        self.buffers[1] = FixedBitSet::with_capacity(size);
+        let width = if self.topology.boundary() == Boundary::Sphere { height } else { self.width as u32 };
+        self.resize_unchecked(width as usize, height as usize, Anchor::TopLeft);
+    }
+
+    // Change the size, keeping the pattern in place relative to the
+    // anchor. Cells that end up outside are cropped and new ones are dead.
+    pub fn resize(&mut self, width: u32, height: u32, anchor: Anchor) -> Result<(), GeometryError> {
+        if self.topology.boundary() == Boundary::Sphere && width != height {
+            return Err(GeometryError::NonSquareSphere { width, height });
+        }
+        self.resize_unchecked(width as usize, height as usize, anchor);
+        Ok(())
     }
 
     pub fn cells(&self) -> *const usize {
@@ -192,40 +535,142 @@ impl Universe {
     }
 
     pub fn tick(&mut self) {
-        let next_index = 1 - self.curr_index;
-        let (width, height) = (self.width, self.height);
-        unsafe {
-            let current = self.buffers.as_mut_ptr().add(self.curr_index) as *mut FixedBitSet;
-            let next = self.buffers.as_mut_ptr().add(next_index) as *mut FixedBitSet;
-            let curr_active = self.active_cell_buffers.as_mut_ptr().add(self.curr_index) as *mut FixedBitSet;
-            let next_active = self.active_cell_buffers.as_mut_ptr().add(next_index) as *mut FixedBitSet;
+        if self.history.is_some() {
+            let frame = self.frame();
+            self.history.as_mut().unwrap().record(frame);
+        }
+        self.generation += 1;
+        if let Some(block_rule) = self.block_rule {
+            self.tick_block(&block_rule, self.block_phase);
+            self.block_phase = 1 - self.block_phase;
+        }
+        else {
+            match self.kernel {
+                Kernel::BitSliced if kernel::supports(&self.rule, &self.topology) => self.tick_bit_sliced(),
+                // Neighbourhoods and topologies the bit-sliced kernel can't
+                // handle fall back to the active cells kernel
+                _ => self.tick_active_cells(),
+            }
+        }
+        self.record_stats();
+    }
 
-            for idx in (*curr_active).ones() {
-                let cell = (*current).contains_unchecked(idx);
-                let neighbours = Self::get_neighbour_array(idx, width, height);
+    pub fn population(&self) -> u32 {
+        self.buffers[self.curr_index].count_ones(..) as u32
+    }
 
-                let mut live_neighbours = 0;
-                for &n in neighbours.iter() {
-                    live_neighbours += (*current).contains_unchecked(n) as u8;
-                }
+    // Cells that came alive in the last tick
+    pub fn births(&self) -> u32 {
+        self.stats.births
+    }
 
-                let (live, changed) = match(cell, live_neighbours) {
-                    (true, x) if x < 2 => (false, true),
-                    (true, x) if x > 3 => (false, true),
-                    (false, 3) => (true, true),
-                    (other, _) => (other, false)
-                };
-                (*next).set_unchecked(idx, live);
+    // Cells that died in the last tick
+    pub fn deaths(&self) -> u32 {
+        self.stats.deaths
+    }
 
-                if changed {
-                    (*next_active).insert_unchecked(idx);
-                    for &n in neighbours.iter() {
-                        (*next_active).insert_unchecked(n);
-                    }
-                }
-            }
+    // Population after each of the most recent ticks, oldest first
+    pub fn population_history(&self) -> Vec<u32> {
+        self.stats.history()
+    }
+
+    // Generation the first entry of the population history is for
+    pub fn population_history_start(&self) -> u64 {
+        self.stats.history_start().unwrap_or(self.generation)
+    }
+
+    // How many ticks the population history covers
+    pub fn set_population_history_length(&mut self, length: u32) {
+        self.stats.set_history_length(length as usize);
+    }
+
+    // Hash of which cells are alive, equal for equal states
+    pub fn state_hash(&self) -> u64 {
+        cycle::state_hash(&self.buffers[self.curr_index])
+    }
+
+    // Watch for the universe repeating a state from up to max_period
+    // generations before, in place or translated. The whole universe is
+    // compared, so a spaceship only counts as translated when nothing else
+    // is alive. The current state is the first one watched, and detection
+    // restarts whenever the universe is edited or steps backwards.
+    pub fn enable_cycle_detection(&mut self, max_period: u32) {
+        let mut detector = CycleDetector::new(max_period as usize);
+        detector.observe(&self.buffers[self.curr_index], self.width, self.generation);
+        self.cycle_detector = Some(detector);
+    }
+
+    pub fn disable_cycle_detection(&mut self) {
+        self.cycle_detector = None;
+    }
+
+    // The cycle found since detection was enabled or last restarted
+    pub fn cycle_report(&self) -> Option<CycleReport> {
+        self.cycle_detector.as_ref().and_then(|detector| detector.report())
+    }
+
+    // Count the objects the live cells make up, e.g. what a soup settled
+    // into. Objects crossing the edges are followed round them.
+    pub fn census(&self) -> Result<Census, RuleError> {
+        census::take(self)
+    }
+
+    pub fn tick_n(&mut self, n: u32) {
+        for _ in 0..n {
+            self.tick();
         }
-        self.curr_index = next_index;
+    }
+
+    // Run as many generations as fit in a wall clock budget, returning how
+    // many were run
+    pub fn tick_for_ms(&mut self, budget: f64) -> u32 {
+        let start = utils::now_ms();
+        let mut ticks = 0;
+        while utils::now_ms() - start < budget {
+            self.tick();
+            ticks += 1;
+        }
+        ticks
+    }
+
+    pub fn step_exponent(&self) -> i32 {
+        self.step_exponent
+    }
+
+    pub fn set_step_exponent(&mut self, exponent: i32) {
+        self.step_exponent = exponent.clamp(MIN_STEP_EXPONENT, MAX_STEP_EXPONENT);
+        self.steps_waited = 0;
+    }
+
+    // Advance by one step of the current speed, for a render loop to call
+    // every frame. Returns how many generations were run.
+    pub fn step(&mut self) -> u32 {
+        if self.step_exponent >= 0 {
+            let generations = 1 << self.step_exponent;
+            self.tick_n(generations);
+            return generations;
+        }
+        self.steps_waited += 1;
+        if self.steps_waited < 1 << -self.step_exponent {
+            return 0;
+        }
+        self.steps_waited = 0;
+        self.tick();
+        1
+    }
+
+    pub fn kernel(&self) -> Kernel {
+        self.kernel
+    }
+
+    pub fn set_kernel(&mut self, kernel: Kernel) {
+        // The bit-sliced kernel doesn't track active cells, so when
+        // switching either way (it may fall back to active cells for some
+        // neighbourhoods and topologies) every cell needs checking again
+        if self.kernel != kernel {
+            self.mark_all_active();
+        }
+        self.kernel = kernel;
     }
 
     pub fn toggle_cell_not_active(&mut self, row: u32, column: u32) {
@@ -236,8 +681,7 @@ impl Universe {
     pub fn toggle_cell(&mut self, row: u32, column: u32) {
         let idx = Self::get_index(self.width, row as usize, column as usize);
         self.buffers[self.curr_index].toggle(idx);
-        self.active_cell_buffers[self.curr_index].insert(idx);
-        Self::insert_neighbours(&mut self.active_cell_buffers[self.curr_index], idx, self.width, self.height);
+        self.mark_active(idx);
     }
 
     pub fn insert_pattern(&mut self, pattern: &Pattern, row: u32, column: u32, angle: u32) {
@@ -253,14 +697,202 @@ impl Universe {
                 let p_idx = pattern.get_angle_index(r, c, angle);
 
                 self.buffers[self.curr_index].set(u_idx, pattern.buffers[pattern.curr_index][p_idx]);
-                self.active_cell_buffers[self.curr_index].insert(u_idx);
+                self.mark_active(u_idx);
             } 
         }
     }
 
+    // Number of cells that will be checked on the next tick
+    pub fn active_cell_count(&self) -> u32 {
+        if self.block_rule.is_some() {
+            return (self.width * self.height) as u32;
+        }
+        match self.kernel {
+            Kernel::ActiveCells => self.active_cell_buffers[self.curr_index].count_ones(..) as u32,
+            Kernel::BitSliced if kernel::supports(&self.rule, &self.topology) => (self.width * self.height) as u32,
+            _ => self.active_cell_buffers[self.curr_index].count_ones(..) as u32,
+        }
+    }
+
     pub fn render(&self) -> String {
         self.to_string()
     }
+
+    pub fn rule(&self) -> Rule {
+        self.rule
+    }
+
+    pub fn set_rule(&mut self, rule: &Rule) {
+        self.rule = *rule;
+        self.block_rule = None;
+        // Cells that were stable under the old rule may not be under the
+        // new one, so every cell needs checking on the next tick
+        self.mark_all_active();
+    }
+
+    // Set the rule from a rulestring, optionally followed by a bounded
+    // grid suffix such as "B3/S23:P100,80". Without a suffix the current
+    // topology is kept.
+    pub fn set_rulestring(&mut self, rulestring: &str) -> Result<(), RuleError> {
+        let (rulestring, suffix) = match rulestring.split_once(':') {
+            Some((rulestring, suffix)) => (rulestring, Some(Topology::parse(suffix)?)),
+            None => (rulestring, None),
+        };
+        let rule = Rule::parse(rulestring)?;
+        if let Some(topology) = suffix {
+            // Only a sphere without a size can fail, on a universe that
+            // isn't square
+            self.set_topology(&topology).map_err(|_| RuleError::Unsupported("spheres that aren't square"))?;
+        }
+        self.set_rule(&rule);
+        Ok(())
+    }
+
+    // The rulestring with the universe's bounded grid suffix
+    pub fn rulestring(&self) -> String {
+        format!("{}{}", self.rule, self.topology())
+    }
+
+    pub fn topology(&self) -> Topology {
+        self.topology.sized(self.width, self.height)
+    }
+
+    // Change how the edges are joined. If the topology gives a size the
+    // universe is resized to it, which clears every cell.
+    pub fn set_topology(&mut self, topology: &Topology) -> Result<(), GeometryError> {
+        let (width, height) = match (topology.width(), topology.height()) {
+            (0, 0) => (self.width, self.height),
+            (width, height) => (width as usize, height as usize),
+        };
+        if topology.boundary() == Boundary::Sphere && width != height {
+            return Err(GeometryError::NonSquareSphere { width: width as u32, height: height as u32 });
+        }
+        if (width, height) != (self.width, self.height) {
+            self.width = width;
+            self.height = height;
+            self.reset_buffers();
+        }
+        self.topology = *topology;
+        self.mark_all_active();
+        Ok(())
+    }
+
+    pub fn block_rule(&self) -> Option<BlockRule> {
+        self.block_rule
+    }
+
+    // Run a block rule on the Margolus partition until the next call to
+    // set_rule, starting from the even partition
+    pub fn set_block_rule(&mut self, block_rule: &BlockRule) {
+        self.block_rule = Some(*block_rule);
+        self.block_phase = 0;
+        // The active cells go stale while the block rule runs
+        self.mark_all_active();
+    }
+
+    // Partition the next tick uses, 0 for blocks at even rows and columns
+    pub fn block_phase(&self) -> u32 {
+        self.block_phase as u32
+    }
+
+    // Undo the last tick of a reversible block rule
+    pub fn tick_reverse(&mut self) -> Result<(), RuleError> {
+        let inverse = self.block_rule
+            .and_then(|block_rule| block_rule.inverse())
+            .ok_or(RuleError::Unsupported("reverse steps without a reversible block rule"))?;
+        self.block_phase = 1 - self.block_phase;
+        self.tick_block(&inverse, self.block_phase);
+        if let Some(detector) = self.cycle_detector.as_mut() {
+            detector.reset();
+        }
+        if self.generation > 0 {
+            self.generation -= 1;
+            if let Some(history) = self.history.as_mut() {
+                history.truncate(self.generation);
+            }
+        }
+        else {
+            // Before generation 0 isn't somewhere the history can go back
+            // to, so start it afresh from here
+            self.mark_edited();
+        }
+        self.record_stats();
+        Ok(())
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    // Keep past generations so they can be gone back to: a keyframe every
+    // keyframe_interval generations and the last recent_generations as
+    // they are, in at most memory_budget bytes
+    pub fn enable_history(&mut self, keyframe_interval: u32, recent_generations: u32, memory_budget: u32) {
+        self.history = Some(History::new(keyframe_interval as u64, recent_generations as usize, memory_budget as usize));
+    }
+
+    pub fn disable_history(&mut self) {
+        self.history = None;
+    }
+
+    // Bytes the history is using
+    pub fn history_memory(&self) -> u32 {
+        self.history.as_ref().map_or(0, |history| history.memory() as u32)
+    }
+
+    // Earliest generation the history can go back to
+    pub fn history_start(&self) -> u64 {
+        self.history.as_ref().and_then(|history| history.start()).unwrap_or(self.generation)
+    }
+
+    // Go back a generation, returning whether the history had it
+    pub fn step_back(&mut self) -> bool {
+        self.generation > 0 && self.goto_generation(self.generation - 1)
+    }
+
+    // Rebuild a generation from the closest frame the history has before
+    // it, ticking forward from there or from the current generation,
+    // whichever is closer. Frames after the generation are kept until the
+    // next tick or edit, so going back and then forward again keeps any
+    // earlier edits, while an edit made after going back replaces them.
+    // Returns whether the generation could be reached, leaving the
+    // universe as it was if not.
+    pub fn goto_generation(&mut self, generation: u64) -> bool {
+        let frame = self.history.as_ref()
+            .and_then(|history| history.frame_for(generation))
+            .filter(|frame| generation < self.generation || frame.generation > self.generation)
+            .cloned();
+        match frame {
+            Some(frame) => {
+                // Replay without recording, as the history already has these
+                let history = self.history.take();
+                self.restore(&frame);
+                while self.generation < generation {
+                    self.tick();
+                }
+                self.history = history;
+                self.stats.truncate(generation);
+            },
+            None if generation < self.generation => return false,
+            None => {
+                while self.generation < generation {
+                    self.tick();
+                }
+            },
+        }
+        true
+    }
+
+    pub fn neighbourhood(&self) -> Neighbourhood {
+        self.rule.neighbourhood()
+    }
+
+    // Keep the birth and survival counts but count them over a different
+    // neighbourhood
+    pub fn set_neighbourhood(&mut self, neighbourhood: &Neighbourhood) {
+        let rule = self.rule.with_neighbourhood(neighbourhood);
+        self.set_rule(&rule);
+    }
 }
 
 
@@ -272,11 +904,17 @@ impl Universe {
         &self.buffers[self.curr_index]
     }
 
+    // Get the cells that will be checked on the next tick
+    pub fn get_active_cells(&self) -> &FixedBitSet {
+        &self.active_cell_buffers[self.curr_index]
+    }
+
     // Set cells to be alive by passing row and col
     pub fn set_cells(&mut self, cells: &[(usize, usize)]) {
         for (row, col) in cells.iter().cloned() {
             let idx = Self::get_index(self.width, row, col);
             self.buffers[self.curr_index].set(idx, true);
+            self.mark_active(idx);
         }
     }
 
@@ -293,7 +931,7 @@ impl Universe {
         let row_idx = width * row;
         let south_row_idx = width * south;
 
-        let indices = [
+        [
             north_row_idx + west,
             north_row_idx + col,
             north_row_idx + east,
@@ -302,8 +940,7 @@ impl Universe {
             south_row_idx + west,
             south_row_idx + col, 
             south_row_idx + east
-        ];
-        indices
+        ]
     }
 
     pub fn get_neighbours(index: usize, width: usize, height: usize) -> impl Iterator<Item = usize> {
@@ -335,6 +972,13 @@ impl Universe {
     pub fn index_neighbour_count(&self, index: usize) -> u8 {
         let (width, height) = (self.width, self.height);
         let cells = &self.buffers[self.curr_index];
+        let neighbourhood = self.rule.neighbourhood();
+        if !neighbourhood.is_moore() || !self.topology.is_plain_torus() {
+            return neighbourhood.offsets()
+                .filter_map(|(row, col)| Self::offset_index(index, width, height, &self.topology, row, col))
+                .filter(|&n| cells[n])
+                .count() as u8;
+        }
         let row = index / width;
         let col = index % width;
 
@@ -371,7 +1015,7 @@ impl fmt::Display for Universe {
                 let symbol = if self.buffers[self.curr_index][idx] { '◼' } else { '◻' };
                 write!(f, "{}", symbol)?;
             }
-            write!(f, "\n")?;
+            writeln!(f)?;
         }
         Ok(())
     }
@@ -381,13 +1025,11 @@ impl fmt::Display for Universe {
 #[wasm_bindgen]
 impl Pattern {
     fn new_plain(width: usize, height: usize) -> Pattern {
-        let size = (width * height) as usize;
+        let size = width * height;
         let current = FixedBitSet::with_capacity(size);
-        let next = FixedBitSet::with_capacity(0);
-        let c = FixedBitSet::with_capacity(0);
-        let n = FixedBitSet::with_capacity(0);
+        let active = FixedBitSet::with_capacity(size);
         
-        Pattern { width, height, buffers: [current, next], active_cell_buffers: [c, n], curr_index: 0 }
+        Pattern::from_buffers(width, height, current, active)
     }
 
     // Constructor methods for simple oscillators
@@ -652,4 +1294,472 @@ mod tests {
 
         assert_eq!(expected_count, count);
     }
+
+    // Compute the next generation by checking every cell, without
+    // relying on the active cell optimisation
+    fn brute_force_tick(universe: &Universe) -> FixedBitSet {
+        let cells = universe.get_cells();
+        let mut next = FixedBitSet::with_capacity(cells.len());
+        for idx in 0..cells.len() {
+            if universe.rule.is_isotropic() {
+                let (row, col) = ((idx / universe.width) as isize, (idx % universe.width) as isize);
+                let mut configuration = 0;
+                for (bit, (dr, dc)) in universe.neighbourhood().offsets().enumerate() {
+                    let neighbour = universe.topology.wrap(row + dr as isize, col + dc as isize, universe.width, universe.height);
+                    let alive = neighbour.map_or(false, |(r, c)| cells[Universe::get_index(universe.width, r, c)]);
+                    configuration |= (alive as u8) << bit;
+                }
+                next.set(idx, universe.rule.next_state_of(cells[idx], configuration));
+            }
+            else {
+                let count = universe.index_neighbour_count(idx);
+                next.set(idx, universe.rule.next_state(cells[idx], count));
+            }
+        }
+        next
+    }
+
+    // The cells that changed between two generations, and their neighbours
+    fn brute_force_active(universe: &Universe, previous: &FixedBitSet) -> FixedBitSet {
+        let cells = universe.get_cells();
+        let mut active = FixedBitSet::with_capacity(cells.len());
+        for idx in cells.symmetric_difference(previous) {
+            active.insert(idx);
+            for n in Universe::get_neighbours(idx, universe.width, universe.height) {
+                active.insert(n);
+            }
+        }
+        active
+    }
+
+    #[test]
+    fn test_active_cells_rebuilt_each_tick() {
+        let mut universe = Universe::new(40, 30);
+        universe.set_kernel(Kernel::ActiveCells);
+        universe.insert_pattern(&Pattern::r_pentomino(), 12, 15, 0);
+
+        for _ in 0..50 {
+            let previous = universe.get_cells().clone();
+            universe.tick();
+            assert_eq!(universe.get_active_cells(), &brute_force_active(&universe, &previous));
+        }
+    }
+
+    #[test]
+    fn test_active_cells_stay_bounded_for_oscillators() {
+        let mut universe = Universe::new(30, 30);
+        universe.set_kernel(Kernel::ActiveCells);
+        universe.insert_pattern(&Pattern::blinker(), 10, 10, 0);
+        universe.tick();
+        let count = universe.active_cell_count();
+
+        for _ in 0..20 {
+            universe.tick();
+            assert_eq!(universe.active_cell_count(), count);
+        }
+    }
+
+    #[test]
+    fn test_active_cells_after_edits() {
+        let mut universe = Universe::new(20, 20);
+        universe.toggle_cell(0, 0);
+        let expected = brute_force_active(&universe, &FixedBitSet::with_capacity(400));
+        assert_eq!(universe.get_active_cells(), &expected);
+
+        let mut universe = Universe::new(20, 20);
+        universe.insert_pattern(&Pattern::glider(), 16, 16, 0);
+        let expected = brute_force_active(&universe, &FixedBitSet::with_capacity(400));
+        assert!(universe.get_active_cells().is_superset(&expected));
+
+        // The glider wraps around the edges, which only works if cells
+        // next to the pattern's box were marked as well
+        let mut reference = Universe::new(20, 20);
+        reference.insert_pattern(&Pattern::glider(), 16, 16, 0);
+        reference.set_kernel(Kernel::BitSliced);
+        for _ in 0..40 {
+            universe.tick();
+            reference.tick();
+            assert_eq!(universe.get_cells(), reference.get_cells());
+        }
+    }
+
+    #[test]
+    fn test_active_cells_after_resize() {
+        let mut universe = Universe::new_sparse(20, 20, 3);
+        universe.set_kernel(Kernel::ActiveCells);
+        universe.tick();
+        universe.set_width(30);
+        universe.set_height(25);
+        assert_eq!(universe.get_active_cells().len(), 30 * 25);
+        // The cells are kept, so every one of them needs checking again
+        assert_eq!(universe.active_cell_count(), 30 * 25);
+
+        universe.insert_pattern(&Pattern::glider(), 20, 20, 0);
+        for _ in 0..10 {
+            let previous = universe.get_cells().clone();
+            universe.tick();
+            assert_eq!(universe.get_active_cells(), &brute_force_active(&universe, &previous));
+        }
+    }
+
+    #[test]
+    fn test_set_rulestring() {
+        let mut universe = Universe::new(10, 10);
+        assert_eq!(universe.rule().to_string(), "B3/S23");
+
+        universe.set_rulestring("B36/S23").unwrap();
+        assert_eq!(universe.rule(), Rule::parse("B36/S23").unwrap());
+
+        assert!(universe.set_rulestring("B3/S239").is_err());
+        assert_eq!(universe.rule().to_string(), "B36/S23");
+    }
+
+    #[test]
+    fn test_tick_highlife_birth() {
+        let mut universe = Universe::new(8, 8);
+        universe.set_rulestring("B36/S23").unwrap();
+        // Six live neighbours around (3,3), which only HighLife brings to life
+        universe.set_cells(&[(2,2), (2,3), (2,4), (4,2), (4,3), (4,4)]);
+
+        universe.tick();
+        assert!(universe.get_cells()[Universe::get_index(8, 3, 3)]);
+    }
+
+    #[test]
+    fn test_bit_sliced_matches_active_cells() {
+        for (width, height) in [(1, 5), (3, 3), (63, 10), (64, 9), (65, 12), (130, 7)] {
+            for rulestring in ["B3/S23", "B36/S23", "B2/S", "B3678/S34678", "B0/S8"] {
+                let rule = Rule::parse(rulestring).unwrap();
+                let mut active = Universe::new_sparse(width, height, 3);
+                let mut sliced = Universe::new_sparse(width, height, 3);
+                active.set_rule(&rule);
+                sliced.set_rule(&rule);
+                sliced.set_kernel(Kernel::BitSliced);
+
+                for generation in 0..30 {
+                    active.tick();
+                    sliced.tick();
+                    assert_eq!(active.get_cells(), sliced.get_cells(), 
+                        "{}x{} rule {} generation {}", width, height, rulestring, generation);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_switch_kernel_mid_run() {
+        let mut reference = Universe::new_oscillators(40, 40, 7);
+        reference.insert_pattern(&Pattern::r_pentomino(), 10, 10, 0);
+        let mut universe = Universe::new_oscillators(40, 40, 7);
+        universe.insert_pattern(&Pattern::r_pentomino(), 10, 10, 0);
+
+        for generation in 0..60 {
+            if generation % 10 == 0 {
+                let kernel = if universe.kernel() == Kernel::ActiveCells { Kernel::BitSliced } else { Kernel::ActiveCells };
+                universe.set_kernel(kernel);
+            }
+            reference.tick();
+            universe.tick();
+            assert_eq!(reference.get_cells(), universe.get_cells(), "generation {}", generation);
+        }
+    }
+
+    #[test]
+    fn test_tick_matches_brute_force() {
+        for rulestring in ["B3/S23", "B36/S23", "B2/S", "B3678/S34678", "B0/S8"] {
+            let mut universe = Universe::new_sparse(24, 17, 3);
+            universe.set_rulestring(rulestring).unwrap();
+
+            for _ in 0..20 {
+                let expected = brute_force_tick(&universe);
+                universe.tick();
+                assert_eq!(universe.get_cells(), &expected, "rule {}", rulestring);
+            }
+        }
+    }
+
+    #[test]
+    fn test_neighbourhood_counts() {
+        let mut universe = Universe::new(10, 10);
+        // Every Moore neighbour of (0, 0) except the NE one, at the far
+        // corner of the torus
+        universe.set_cells(&[(9,9), (9,0), (0,9), (0,1), (1,9), (1,0), (1,1)]);
+        let idx = Universe::get_index(10, 0, 0);
+        assert_eq!(universe.index_neighbour_count(idx), 7);
+
+        // Hexagonal drops the NE and SW corners, only SW was alive
+        universe.set_rulestring("B2/S34H").unwrap();
+        assert_eq!(universe.index_neighbour_count(idx), 6);
+        universe.set_rulestring("B1/S4V").unwrap();
+        assert_eq!(universe.index_neighbour_count(idx), 4);
+        universe.set_neighbourhood(&Neighbourhood::custom(&[0, -1, -3, 3]).unwrap());
+        assert_eq!(universe.index_neighbour_count(idx), 1);
+        assert_eq!(universe.rule().to_string(), "B1/S4");
+    }
+
+    #[test]
+    fn test_neighbourhoods_match_brute_force() {
+        // Asymmetric, so the active cells must be marked at negated offsets
+        let custom = Neighbourhood::custom(&[-2, 0, 0, 1, 1, 1, -1, -3]).unwrap();
+        let rules = [
+            Rule::parse("B2/S34H").unwrap(),
+            Rule::parse("B13/S012V").unwrap(),
+            Rule::parse("B1/S12").unwrap().with_neighbourhood(&custom),
+        ];
+        for rule in rules.iter() {
+            for kernel in [Kernel::ActiveCells, Kernel::BitSliced] {
+                let mut universe = Universe::new(24, 17);
+                universe.set_kernel(kernel);
+                universe.set_rule(rule);
+                universe.insert_pattern(&Pattern::r_pentomino(), 6, 8, 0);
+
+                for generation in 0..30 {
+                    let expected = brute_force_tick(&universe);
+                    universe.tick();
+                    assert_eq!(universe.get_cells(), &expected, "rule {} generation {}", rule, generation);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_topologies_match_brute_force() {
+        let custom = Neighbourhood::custom(&[-2, 0, 0, 1, 1, 1, -1, -3]).unwrap();
+        let rules = [
+            Rule::conway(),
+            Rule::parse("B2/S34H").unwrap(),
+            Rule::parse("B1/S12").unwrap().with_neighbourhood(&custom),
+        ];
+        let suffixes = [":T20,20", ":T20+3,20", ":T20,20-2", ":P", ":K20*,20", ":K20,20*", ":C", ":S"];
+        for suffix in suffixes.iter() {
+            for rule in rules.iter() {
+                for kernel in [Kernel::ActiveCells, Kernel::BitSliced] {
+                    let mut universe = Universe::new(20, 20);
+                    universe.set_kernel(kernel);
+                    universe.set_topology(&Topology::parse(suffix).unwrap()).unwrap();
+                    universe.set_rule(rule);
+                    // Start next to a corner so the pattern crosses the edges
+                    universe.insert_pattern(&Pattern::r_pentomino(), 15, 16, 0);
+                    universe.insert_pattern(&Pattern::glider(), 0, 0, 180);
+
+                    for generation in 0..40 {
+                        let expected = brute_force_tick(&universe);
+                        universe.tick();
+                        assert_eq!(universe.get_cells(), &expected, 
+                            "{} rule {} generation {}", suffix, rule, generation);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_isotropic_rules_match_brute_force() {
+        for rulestring in ["B3/S2-i34q", "B2-a/S12", "B2ce3-ck/S1e2k3ai4c"] {
+            for suffix in [":T30,24", ":P", ":K30*,24"] {
+                for kernel in [Kernel::ActiveCells, Kernel::BitSliced] {
+                    let mut universe = Universe::new_sparse(30, 24, 3);
+                    universe.set_kernel(kernel);
+                    universe.set_rulestring(&format!("{}{}", rulestring, suffix)).unwrap();
+
+                    for generation in 0..25 {
+                        let expected = brute_force_tick(&universe);
+                        universe.tick();
+                        assert_eq!(universe.get_cells(), &expected, 
+                            "{}{} generation {}", rulestring, suffix, generation);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_isotropic_rules_match_across_engines() {
+        let rule = Rule::parse("B3/S2-i34q").unwrap();
+        let mut universe = Universe::new(128, 128);
+        universe.set_rule(&rule);
+        universe.insert_pattern(&Pattern::r_pentomino(), 60, 60, 0);
+        universe.insert_pattern(&Pattern::glider(), 20, 20, 0);
+        let mut hashlife = HashLife::from_universe(&universe).unwrap();
+        let mut sparse = SparseUniverse::from_universe(&universe).unwrap();
+
+        for _ in 0..40 {
+            universe.tick();
+            sparse.tick();
+        }
+        hashlife.advance(40).unwrap();
+        assert_eq!(hashlife.to_universe(0, 0, 128, 128).get_cells(), universe.get_cells());
+        assert_eq!(sparse.to_universe(0, 0, 128, 128).get_cells(), universe.get_cells());
+    }
+
+    #[test]
+    fn test_glider_leaves_plane() {
+        let mut universe = Universe::new(12, 12);
+        universe.set_rulestring("B3/S23:P").unwrap();
+        universe.insert_pattern(&Pattern::glider(), 4, 4, 0);
+
+        // The glider turns into a block against the corner rather than
+        // wrapping back round
+        for _ in 0..60 {
+            universe.tick();
+        }
+        let block: Vec<usize> = [(10, 10), (10, 11), (11, 10), (11, 11)].iter()
+            .map(|&(row, col)| Universe::get_index(12, row, col))
+            .collect();
+        assert_eq!(universe.get_cells().ones().collect::<Vec<usize>>(), block);
+    }
+
+    #[test]
+    fn test_set_rulestring_with_topology() {
+        let mut universe = Universe::new(10, 10);
+        assert_eq!(universe.rulestring(), "B3/S23:T10,10");
+
+        universe.set_rulestring("B36/S23:K30*,20").unwrap();
+        assert_eq!((universe.width(), universe.height()), (30, 20));
+        assert_eq!(universe.rulestring(), "B36/S23:K30*,20");
+
+        // Without a suffix the topology is kept
+        universe.set_rulestring("B3/S23").unwrap();
+        assert_eq!(universe.topology().boundary(), Boundary::KleinBottle);
+
+        assert!(universe.set_rulestring("B3/S23:S").is_err());
+        assert!(universe.set_rulestring("B3/S23:Q").is_err());
+        assert_eq!(universe.rulestring(), "B3/S23:K30*,20");
+    }
+
+    #[test]
+    fn test_tick_n_and_step_exponent() {
+        let mut universe = Universe::new(32, 32);
+        universe.insert_pattern(&Pattern::r_pentomino(), 14, 14, 0);
+        let mut reference = Universe::new(32, 32);
+        reference.insert_pattern(&Pattern::r_pentomino(), 14, 14, 0);
+
+        universe.tick_n(10);
+        for _ in 0..10 {
+            reference.tick();
+        }
+        assert_eq!(universe.get_cells(), reference.get_cells());
+        assert_eq!(universe.generation(), 10);
+
+        universe.set_step_exponent(3);
+        assert_eq!(universe.step(), 8);
+        assert_eq!(universe.generation(), 18);
+
+        // Negative exponents wait a few steps between generations
+        universe.set_step_exponent(-2);
+        assert_eq!((universe.step(), universe.step(), universe.step(), universe.step()), (0, 0, 0, 1));
+        assert_eq!(universe.generation(), 19);
+        universe.set_step_exponent(100);
+        assert_eq!(universe.step_exponent(), MAX_STEP_EXPONENT);
+    }
+
+    #[test]
+    fn test_tick_for_ms() {
+        let mut universe = Universe::new(64, 64);
+        universe.insert_pattern(&Pattern::r_pentomino(), 30, 30, 0);
+        assert_eq!(universe.tick_for_ms(0.0), 0);
+        let ticks = universe.tick_for_ms(5.0);
+        assert!(ticks > 0);
+        assert_eq!(universe.generation(), ticks as u64);
+    }
+
+    #[test]
+    fn test_population_statistics() {
+        let mut universe = Universe::new(16, 16);
+        universe.insert_pattern(&Pattern::blinker(), 5, 5, 0);
+        assert_eq!(universe.population(), 3);
+        universe.tick();
+        assert_eq!((universe.population(), universe.births(), universe.deaths()), (3, 2, 2));
+
+        universe.insert_pattern(&Pattern::glider(), 10, 10, 0);
+        universe.set_population_history_length(4);
+        for _ in 0..6 {
+            universe.tick();
+        }
+        assert_eq!(universe.generation(), 7);
+        assert_eq!(universe.population_history(), vec![8, 8, 8, 8]);
+        assert_eq!(universe.population_history_start(), 4);
+
+        // Going back forgets the populations after the generation reached
+        universe.enable_history(1, 0, 1 << 20);
+        universe.tick();
+        universe.tick();
+        assert!(universe.step_back());
+        assert_eq!(universe.population_history(), vec![8, 8, 8]);
+        assert_eq!(universe.population_history_start(), 6);
+    }
+
+    #[test]
+    fn test_seeded_soups() {
+        let soup = Universe::new_soup(40, 30, 42, 0.3);
+        assert_eq!(soup.get_cells(), Universe::new_soup(40, 30, 42, 0.3).get_cells());
+        assert_ne!(soup.get_cells(), Universe::new_soup(40, 30, 43, 0.3).get_cells());
+        let population = soup.population();
+        assert!((300..420).contains(&population), "{}", population);
+
+        // The same seed fills a region the same wherever it is
+        let mut universe = Universe::new(40, 30);
+        universe.fill_soup_region(seed_from_str("soup"), 0.5, 2, 3, 16, 16);
+        let mut moved = Universe::new(40, 30);
+        moved.fill_soup_region(seed_from_str("soup"), 0.5, 10, 20, 16, 16);
+        for r in 0..16 {
+            for c in 0..16 {
+                let idx = Universe::get_index(40, r + 2, c + 3);
+                let moved_idx = Universe::get_index(40, r + 10, c + 20);
+                assert_eq!(universe.get_cells()[idx], moved.get_cells()[moved_idx]);
+            }
+        }
+        assert_eq!(universe.population(), moved.population());
+
+        // Clipping keeps the part inside the same, and doesn't visit the
+        // rest of a huge region
+        let mut clipped = Universe::new(10, 8);
+        clipped.fill_soup_region(seed_from_str("soup"), 0.5, 2, 3, 16, 16);
+        for (r, c) in (2..8).flat_map(|r| (3..10).map(move |c| (r, c))) {
+            assert_eq!(clipped.get_cells()[Universe::get_index(10, r, c)], universe.get_cells()[Universe::get_index(40, r, c)]);
+        }
+        clipped.fill_soup_region(1, 0.5, 0, 0, u32::MAX, u32::MAX);
+        clipped.fill_soup_region(1, 0.5, u32::MAX, u32::MAX, u32::MAX, u32::MAX);
+
+        // Unseeded soups work natively too
+        assert_eq!(Universe::new_rand(8, 8).get_cells().len(), 64);
+    }
+
+    #[test]
+    fn test_resize_keeps_cells() {
+        let mut universe = Universe::new(10, 10);
+        universe.set_cells(&[(0, 0), (4, 5), (9, 9)]);
+
+        universe.resize(20, 14, Anchor::Centre).unwrap();
+        assert_eq!((universe.width(), universe.height()), (20, 14));
+        let cells: Vec<usize> = universe.get_cells().ones().collect();
+        assert_eq!(cells, vec![Universe::get_index(20, 2, 5), Universe::get_index(20, 6, 10), Universe::get_index(20, 11, 14)]);
+
+        // Shrinking crops the far corner
+        universe.resize(10, 10, Anchor::BottomRight).unwrap();
+        let cells: Vec<usize> = universe.get_cells().ones().collect();
+        assert_eq!(cells, vec![Universe::get_index(10, 2, 0), Universe::get_index(10, 7, 4)]);
+
+        universe.set_width(5);
+        universe.set_height(8);
+        let cells: Vec<usize> = universe.get_cells().ones().collect();
+        assert_eq!(cells, vec![Universe::get_index(5, 2, 0), Universe::get_index(5, 7, 4)]);
+
+        // Every cell is checked after resizing, so the next tick is right
+        assert_eq!(universe.get_active_cells().count_ones(..), 40);
+        universe.tick();
+        assert_eq!(universe.population(), 0);
+
+        assert_eq!(universe.set_topology(&Topology::sphere()), Err(GeometryError::NonSquareSphere { width: 5, height: 8 }));
+        universe.resize(8, 8, Anchor::TopLeft).unwrap();
+        universe.set_topology(&Topology::sphere()).unwrap();
+        assert_eq!(universe.resize(8, 9, Anchor::TopLeft), Err(GeometryError::NonSquareSphere { width: 8, height: 9 }));
+        assert_eq!((universe.width(), universe.height()), (8, 8));
+
+        // Changing one side of a sphere changes the other to keep it square
+        universe.set_width(6);
+        assert_eq!((universe.width(), universe.height()), (6, 6));
+        universe.set_height(7);
+        assert_eq!((universe.width(), universe.height()), (7, 7));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,456 @@
+use std::{
+    cmp::min,
+    fmt,
+    str::FromStr,
+};
+use wasm_bindgen::prelude::*;
+
+use crate::{utils, Pattern, RuleError};
+
+// Largest neighbourhood range accepted in a rulestring
+const MAX_RANGE: u32 = 50;
+
+// Shape of a Larger than Life neighbourhood of range r
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RangeShape {
+    // The (2r + 1) x (2r + 1) square
+    Moore,
+    // The diamond of cells within Manhattan distance r
+    VonNeumann,
+}
+
+// Larger than Life rule in Golly's syntax, e.g. Bosco's rule is
+// "R5,C0,M1,S34..58,B34..45,NM". Births and survivals are given as
+// inclusive intervals of live neighbour counts, M1 includes the cell
+// itself in its count, and C above 2 adds Generations style decay.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LtlRule {
+    range: u32,
+    states: u32,
+    middle: bool,
+    survival: (u32, u32),
+    birth: (u32, u32),
+    shape: RangeShape,
+}
+
+#[wasm_bindgen]
+impl LtlRule {
+    pub fn parse(rulestring: &str) -> Result<LtlRule, RuleError> {
+        let rulestring = rulestring.trim();
+        if rulestring.is_empty() {
+            return Err(RuleError::Empty);
+        }
+
+        let (mut range, mut states, mut middle) = (None, None, None);
+        let (mut survival, mut birth, mut shape) = (None, None, None);
+        for part in rulestring.split(',') {
+            let mut chars = part.trim().chars();
+            let key = chars.next().map(|c| c.to_ascii_uppercase());
+            let value = chars.as_str();
+            match key {
+                Some('R') if range.is_none() => range = Some(Self::parse_number(value)?),
+                Some('C') if states.is_none() => states = Some(Self::parse_number(value)?),
+                Some('M') if middle.is_none() => middle = Some(Self::parse_number(value)?),
+                Some('S') if survival.is_none() => survival = Some(Self::parse_interval(value)?),
+                Some('B') if birth.is_none() => birth = Some(Self::parse_interval(value)?),
+                Some('N') if shape.is_none() => {
+                    shape = match value.to_ascii_uppercase().as_str() {
+                        "M" => Some(RangeShape::Moore),
+                        "N" => Some(RangeShape::VonNeumann),
+                        other => return Err(RuleError::UnexpectedChar(other.chars().next().unwrap_or('N'))),
+                    }
+                },
+                Some(c) => return Err(RuleError::UnexpectedChar(c)),
+                None => return Err(RuleError::MissingSeparator),
+            }
+        }
+
+        let range = range.ok_or(RuleError::MissingSeparator)?;
+        if range == 0 || range > MAX_RANGE {
+            return Err(RuleError::InvalidRange(range));
+        }
+        let states = states.unwrap_or(0);
+        if states > 256 {
+            return Err(RuleError::InvalidStates(states));
+        }
+        let middle = match middle.unwrap_or(0) {
+            0 => false,
+            1 => true,
+            _ => return Err(RuleError::UnexpectedChar('M')),
+        };
+
+        Ok(LtlRule {
+            range,
+            states,
+            middle,
+            survival: survival.ok_or(RuleError::MissingSeparator)?,
+            birth: birth.ok_or(RuleError::MissingSeparator)?,
+            shape: shape.unwrap_or(RangeShape::Moore),
+        })
+    }
+
+    pub fn range(&self) -> u32 {
+        self.range
+    }
+
+    pub fn shape(&self) -> RangeShape {
+        self.shape
+    }
+
+    // Number of cell states, where C0 and C2 both mean plain live and dead
+    pub fn states(&self) -> u32 {
+        if self.states < 2 { 2 } else { self.states }
+    }
+
+    #[wasm_bindgen(js_name = toString)]
+    pub fn rulestring(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl LtlRule {
+    #[inline(always)]
+    pub fn next_state(&self, state: u8, live_neighbours: u32) -> u8 {
+        let within = |(low, high): (u32, u32)| live_neighbours >= low && live_neighbours <= high;
+        match state {
+            0 => within(self.birth) as u8,
+            1 if within(self.survival) => 1,
+            _ => ((state as u32 + 1) % self.states()) as u8,
+        }
+    }
+
+    fn parse_number(digits: &str) -> Result<u32, RuleError> {
+        if let Some(c) = digits.chars().find(|c| !c.is_ascii_digit()) {
+            return Err(RuleError::UnexpectedChar(c));
+        }
+        digits.parse().map_err(|_| RuleError::MissingSeparator)
+    }
+
+    fn parse_interval(interval: &str) -> Result<(u32, u32), RuleError> {
+        match interval.split_once("..") {
+            Some((low, high)) => Ok((Self::parse_number(low)?, Self::parse_number(high)?)),
+            None => {
+                let count = Self::parse_number(interval)?;
+                Ok((count, count))
+            },
+        }
+    }
+}
+
+impl FromStr for LtlRule {
+    type Err = RuleError;
+
+    fn from_str(s: &str) -> Result<LtlRule, RuleError> {
+        LtlRule::parse(s)
+    }
+}
+
+impl fmt::Display for LtlRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let shape = match self.shape {
+            RangeShape::Moore => 'M',
+            RangeShape::VonNeumann => 'N',
+        };
+        write!(f, "R{},C{},M{},S{}..{},B{}..{},N{}",
+            self.range, self.states, self.middle as u8,
+            self.survival.0, self.survival.1, self.birth.0, self.birth.1, shape)
+    }
+}
+
+// Universe for Larger than Life rules, with a byte per cell like
+// GenerationsUniverse. Neighbour counts come from prefix sums over a copy
+// of the live cells padded by the range on every side, so the cost of a
+// tick barely depends on the range for the Moore shape, and grows only
+// linearly with it for von Neumann.
+#[wasm_bindgen]
+pub struct LargerThanLife {
+    width: usize,
+    height: usize,
+    buffers: [Vec<u8>; 2],
+    curr_index: usize,
+    rule: LtlRule,
+    // Prefix sums of the padded live cells, reused between ticks
+    sums: Vec<u32>,
+}
+
+impl LargerThanLife {
+    #[inline(always)]
+    fn get_index(&self, row: usize, column: usize) -> usize {
+        row * self.width + column
+    }
+
+    // Fill the prefix sums of the live cells padded by the range, wrapping
+    // around the torus. For the Moore shape these are 2D summed area
+    // sums, for von Neumann they are sums along each padded row.
+    fn build_sums(&mut self) {
+        let r = self.rule.range as usize;
+        let (width, height) = (self.width, self.height);
+        let (padded_width, padded_height) = (width + 2 * r + 1, height + 2 * r);
+        let cells = &self.buffers[self.curr_index];
+        let moore = self.rule.shape == RangeShape::Moore;
+
+        self.sums.clear();
+        self.sums.resize(padded_width * (padded_height + 1), 0);
+        for y in 0..padded_height {
+            let row = (y + height * (r / height + 1) - r) % height;
+            let mut running = 0;
+            for x in 0..padded_width - 1 {
+                let col = (x + width * (r / width + 1) - r) % width;
+                running += (cells[row * width + col] == 1) as u32;
+                let above = if moore { self.sums[y * padded_width + x + 1] } else { 0 };
+                self.sums[(y + 1) * padded_width + x + 1] = running + above;
+            }
+        }
+    }
+
+    // Live cells in the padded rectangle [top, bottom) x [left, right)
+    #[inline(always)]
+    fn box_sum(&self, top: usize, left: usize, bottom: usize, right: usize) -> u32 {
+        let w = self.width + 2 * self.rule.range as usize + 1;
+        self.sums[bottom * w + right] + self.sums[top * w + left]
+            - self.sums[top * w + right] - self.sums[bottom * w + left]
+    }
+
+    // Live cells along one padded row between columns [left, right)
+    #[inline(always)]
+    fn row_sum(&self, row: usize, left: usize, right: usize) -> u32 {
+        let w = self.width + 2 * self.rule.range as usize + 1;
+        self.sums[(row + 1) * w + right] - self.sums[(row + 1) * w + left]
+    }
+
+    pub fn neighbour_count(&self, row: usize, col: usize) -> u32 {
+        let r = self.rule.range as usize;
+        let count = match self.rule.shape {
+            // In padded coordinates the cell sits at (row + r, col + r)
+            RangeShape::Moore => self.box_sum(row, col, row + 2 * r + 1, col + 2 * r + 1),
+            RangeShape::VonNeumann => {
+                let mut count = 0;
+                for dy in 0..=2 * r {
+                    let reach = r - dy.abs_diff(r);
+                    count += self.row_sum(row + dy, col + r - reach, col + r + reach + 1);
+                }
+                count
+            },
+        };
+        let alive = self.buffers[self.curr_index][self.get_index(row, col)] == 1;
+        if alive && !self.rule.middle { count - 1 } else { count }
+    }
+
+    // Get all the cell states in the universe
+    pub fn get_cells(&self) -> &[u8] {
+        &self.buffers[self.curr_index]
+    }
+}
+
+// Public methods, exposed to JavaScript via bindgen
+#[wasm_bindgen]
+impl LargerThanLife {
+    pub fn new(width: u32, height: u32, rulestring: &str) -> Result<LargerThanLife, RuleError> {
+        // Enable logging for panics
+        utils::set_panic_hook();
+        let rule = LtlRule::parse(rulestring)?;
+        let size = width as usize * height as usize;
+
+        Ok(LargerThanLife {
+            width: width as usize,
+            height: height as usize,
+            buffers: [vec![0; size], vec![0; size]],
+            curr_index: 0,
+            rule,
+            sums: Vec::new(),
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width as u32
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height as u32
+    }
+
+    pub fn cells(&self) -> *const u8 {
+        self.buffers[self.curr_index].as_ptr()
+    }
+
+    pub fn rule(&self) -> LtlRule {
+        self.rule
+    }
+
+    pub fn set_rule(&mut self, rule: &LtlRule) {
+        self.rule = *rule;
+        let last = (rule.states() - 1) as u8;
+        for state in self.buffers[self.curr_index].iter_mut() {
+            if *state > last {
+                *state = last;
+            }
+        }
+    }
+
+    pub fn set_rulestring(&mut self, rulestring: &str) -> Result<(), RuleError> {
+        let rule = LtlRule::parse(rulestring)?;
+        self.set_rule(&rule);
+        Ok(())
+    }
+
+    pub fn tick(&mut self) {
+        if self.width == 0 || self.height == 0 {
+            return;
+        }
+        self.build_sums();
+
+        let next_index = 1 - self.curr_index;
+        let mut next = std::mem::take(&mut self.buffers[next_index]);
+        for row in 0..self.height {
+            for col in 0..self.width {
+                let idx = self.get_index(row, col);
+                let count = self.neighbour_count(row, col);
+                next[idx] = self.rule.next_state(self.buffers[self.curr_index][idx], count);
+            }
+        }
+        self.buffers[next_index] = next;
+        self.curr_index = next_index;
+    }
+
+    pub fn get_state(&self, row: u32, column: u32) -> u8 {
+        self.buffers[self.curr_index][self.get_index(row as usize, column as usize)]
+    }
+
+    pub fn set_state(&mut self, row: u32, column: u32, state: u8) {
+        let idx = self.get_index(row as usize, column as usize);
+        self.buffers[self.curr_index][idx] = min(state as u32, self.rule.states() - 1) as u8;
+    }
+
+    pub fn toggle_cell(&mut self, row: u32, column: u32) {
+        let idx = self.get_index(row as usize, column as usize);
+        let cell = &mut self.buffers[self.curr_index][idx];
+        *cell = (*cell == 0) as u8;
+    }
+
+    // Copy a two-state pattern in, with its live cells in state 1
+    pub fn insert_pattern(&mut self, pattern: &Pattern, row: u32, column: u32, angle: u32) {
+        let (row, column) = (row as usize, column as usize);
+        let max_row = min(row + pattern.angle_height(angle), self.height) - row;
+        let max_col = min(column + pattern.angle_width(angle), self.width) - column;
+
+        for r in 0..max_row {
+            for c in 0..max_col {
+                let u_idx = self.get_index(r + row, c + column);
+                let p_idx = pattern.get_angle_index(r, c, angle);
+                self.buffers[self.curr_index][u_idx] = pattern.get_cells()[p_idx] as u8;
+            }
+        }
+    }
+
+    pub fn population(&self) -> u32 {
+        self.buffers[self.curr_index].iter().filter(|&&state| state == 1).count() as u32
+    }
+}
+
+
+//                      Testing
+// ======================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Universe;
+
+    // Fill a universe with a fixed pseudo-random soup
+    fn soup(universe: &mut LargerThanLife, density: u32) {
+        let mut state = 12345u32;
+        for row in 0..universe.height() {
+            for col in 0..universe.width() {
+                state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                if (state >> 16) % 100 < density {
+                    universe.set_state(row, col, 1);
+                }
+            }
+        }
+    }
+
+    fn naive_count(universe: &LargerThanLife, row: usize, col: usize) -> u32 {
+        let rule = universe.rule;
+        let r = rule.range as isize;
+        let (width, height) = (universe.width as isize, universe.height as isize);
+        let mut count = 0;
+        for dy in -r..=r {
+            for dx in -r..=r {
+                if rule.shape == RangeShape::VonNeumann && dy.abs() + dx.abs() > r {
+                    continue;
+                }
+                if (dy, dx) == (0, 0) && !rule.middle {
+                    continue;
+                }
+                let y = (row as isize + dy).rem_euclid(height) as usize;
+                let x = (col as isize + dx).rem_euclid(width) as usize;
+                count += (universe.get_cells()[y * universe.width + x] == 1) as u32;
+            }
+        }
+        count
+    }
+
+    #[test]
+    fn test_parse_bosco() {
+        let rule = LtlRule::parse("R5,C0,M1,S34..58,B34..45,NM").unwrap();
+        assert_eq!(rule.range(), 5);
+        assert_eq!(rule.states(), 2);
+        assert_eq!(rule.shape(), RangeShape::Moore);
+        assert_eq!(rule.to_string(), "R5,C0,M1,S34..58,B34..45,NM");
+
+        let neumann = LtlRule::parse("r3,c4,m0,s2..6,b3,nn").unwrap();
+        assert_eq!(neumann.to_string(), "R3,C4,M0,S2..6,B3..3,NN");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(LtlRule::parse(""), Err(RuleError::Empty));
+        assert_eq!(LtlRule::parse("R0,C0,M1,S1..2,B1..2,NM"), Err(RuleError::InvalidRange(0)));
+        assert_eq!(LtlRule::parse("R5,C0,M1,S1..2,NM"), Err(RuleError::MissingSeparator));
+        assert_eq!(LtlRule::parse("R5,C0,M1,S1..2,B1..2,NX"), Err(RuleError::UnexpectedChar('X')));
+        assert_eq!(LtlRule::parse("R5,C0,M1,S1..x,B1..2,NM"), Err(RuleError::UnexpectedChar('x')));
+    }
+
+    #[test]
+    fn test_counts_match_naive() {
+        for rulestring in ["R1,C0,M0,S2..3,B3..3,NM", "R4,C0,M1,S10..20,B8..12,NM", "R10,C0,M1,S30..60,B35..45,NM",
+                           "R3,C0,M0,S2..6,B3..5,NN", "R7,C0,M1,S10..30,B12..20,NN"] {
+            let mut universe = LargerThanLife::new(23, 17, rulestring).unwrap();
+            soup(&mut universe, 40);
+            universe.build_sums();
+
+            for row in 0..universe.height {
+                for col in 0..universe.width {
+                    assert_eq!(universe.neighbour_count(row, col), naive_count(&universe, row, col),
+                        "rule {} at ({}, {})", rulestring, row, col);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_range_one_matches_life() {
+        let mut life = Universe::new(20, 20);
+        life.insert_pattern(&Pattern::r_pentomino(), 7, 7, 0);
+        let mut ltl = LargerThanLife::new(20, 20, "R1,C0,M0,S2..3,B3..3,NM").unwrap();
+        ltl.insert_pattern(&Pattern::r_pentomino(), 7, 7, 0);
+
+        for _ in 0..30 {
+            life.tick();
+            ltl.tick();
+            let states: Vec<u8> = (0..400).map(|i| life.get_cells()[i] as u8).collect();
+            assert_eq!(ltl.get_cells(), &states[..]);
+        }
+    }
+
+    #[test]
+    fn test_decay_states() {
+        let mut universe = LargerThanLife::new(30, 30, "R2,C3,M0,S30..40,B30..40,NM").unwrap();
+        universe.set_state(10, 10, 1);
+        universe.tick();
+        assert_eq!(universe.get_state(10, 10), 2);
+        universe.tick();
+        assert_eq!(universe.get_state(10, 10), 0);
+    }
+}
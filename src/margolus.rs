@@ -0,0 +1,229 @@
+use std::fmt;
+use fixedbitset::FixedBitSet;
+use wasm_bindgen::prelude::*;
+
+use crate::RuleError;
+
+// Block rule on the Margolus partition: the universe is cut into 2x2
+// blocks and each block is replaced by the table entry for its state.
+// Alternate generations offset the blocks by one cell down and right. A
+// block's state has a bit per cell: top left (bit 0), top right (bit 1),
+// bottom left (bit 2) and bottom right (bit 3), as in MCell's notation.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockRule {
+    table: [u8; 16],
+}
+
+#[wasm_bindgen]
+impl BlockRule {
+    pub fn new(table: &[u8]) -> Result<BlockRule, RuleError> {
+        if table.len() != 16 || table.iter().any(|&entry| entry > 15) {
+            return Err(RuleError::InvalidBlockTable);
+        }
+        let mut entries = [0; 16];
+        entries.copy_from_slice(table);
+        Ok(BlockRule { table: entries })
+    }
+
+    // Parse MCell's notation, e.g. "M0;2;8;3;1;5;6;7;4;9;10;11;12;13;14;15"
+    pub fn parse(rulestring: &str) -> Result<BlockRule, RuleError> {
+        let rulestring = rulestring.trim();
+        if rulestring.is_empty() {
+            return Err(RuleError::Empty);
+        }
+        let mut chars = rulestring.chars();
+        match chars.next() {
+            Some('M') | Some('m') => {},
+            Some(c) => return Err(RuleError::UnexpectedChar(c)),
+            None => return Err(RuleError::Empty),
+        }
+        let table = chars.as_str().split(|c| c == ';' || c == ',')
+            .map(|entry| entry.trim().parse::<u8>().map_err(|_| RuleError::InvalidBlockTable))
+            .collect::<Result<Vec<u8>, RuleError>>()?;
+        Self::new(&table)
+    }
+
+    // Blocks with two live cells stay as they are, the rest are
+    // complemented, and those that had three live cells turn around
+    pub fn critters() -> BlockRule {
+        Self::from_fn(|block| match block.count_ones() {
+            2 => block,
+            3 => Self::rotate_half(!block & 0xf),
+            _ => !block & 0xf,
+        })
+    }
+
+    // Blocks that are all alive or all dead are complemented
+    pub fn tron() -> BlockRule {
+        Self::from_fn(|block| if block == 0 || block == 0xf { !block & 0xf } else { block })
+    }
+
+    // Billiard ball machine: a lone cell moves to the opposite corner and
+    // two balls colliding head on leave at right angles
+    pub fn billiard_ball() -> BlockRule {
+        Self::from_fn(|block| match block {
+            0b1001 => 0b0110,
+            0b0110 => 0b1001,
+            _ if block.count_ones() == 1 => Self::rotate_half(block),
+            _ => block,
+        })
+    }
+
+    pub fn table(&self) -> Vec<u8> {
+        self.table.to_vec()
+    }
+
+    // Whether every block state has its own image, so the rule can be run
+    // backwards
+    pub fn is_reversible(&self) -> bool {
+        self.inverse().is_some()
+    }
+
+    pub fn inverse(&self) -> Option<BlockRule> {
+        let mut inverse = [16; 16];
+        for (block, &image) in self.table.iter().enumerate() {
+            if inverse[image as usize] != 16 {
+                return None;
+            }
+            inverse[image as usize] = block as u8;
+        }
+        Some(BlockRule { table: inverse })
+    }
+
+    #[wasm_bindgen(js_name = toString)]
+    pub fn rulestring(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl BlockRule {
+    fn from_fn<F: Fn(u8) -> u8>(f: F) -> BlockRule {
+        let mut table = [0; 16];
+        for (block, entry) in table.iter_mut().enumerate() {
+            *entry = f(block as u8);
+        }
+        BlockRule { table }
+    }
+
+    // Swap opposite corners, turning a block by 180 degrees
+    fn rotate_half(block: u8) -> u8 {
+        (block & 1) << 3 | (block & 2) << 1 | (block & 4) >> 1 | (block & 8) >> 3
+    }
+
+    // Apply the rule to every block of the partition for the given phase,
+    // 0 for blocks starting at even rows and columns and 1 for odd. Blocks
+    // only cross the edges when `wrap` is set and the size is even, so
+    // cells left over at the edges keep their state.
+    pub(crate) fn step(&self, current: &FixedBitSet, next: &mut FixedBitSet, width: usize, height: usize, phase: usize, wrap: bool) {
+        next.clone_from(current);
+        let starts = |size: usize| {
+            let wraps = wrap && size % 2 == 0;
+            (phase..size).step_by(2).filter(move |&start| start + 1 < size || wraps)
+        };
+
+        for row in starts(height) {
+            let below = (row + 1) % height;
+            for col in starts(width) {
+                let right = (col + 1) % width;
+                let cells = [row * width + col, row * width + right, below * width + col, below * width + right];
+                let block = cells.iter().enumerate().fold(0, |block, (bit, &idx)| block | (current[idx] as usize) << bit);
+                let image = self.table[block];
+                for (bit, &idx) in cells.iter().enumerate() {
+                    next.set(idx, image >> bit & 1 != 0);
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for BlockRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "M")?;
+        for (i, entry) in self.table.iter().enumerate() {
+            if i > 0 {
+                write!(f, ";")?;
+            }
+            write!(f, "{}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+
+//                      Testing
+// ======================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Pattern, Topology, Universe};
+
+    #[test]
+    fn test_parse_and_display() {
+        let critters = BlockRule::critters();
+        assert_eq!(critters.to_string(), "M15;14;13;3;11;5;6;1;7;9;10;2;12;4;8;0");
+        assert_eq!(BlockRule::parse(&critters.to_string()), Ok(critters));
+        assert_eq!(BlockRule::parse("M1;2;3"), Err(RuleError::InvalidBlockTable));
+        assert_eq!(BlockRule::parse("M0;1;2;3;4;5;6;7;8;9;10;11;12;13;14;16"), Err(RuleError::InvalidBlockTable));
+        assert_eq!(BlockRule::parse("B3/S23"), Err(RuleError::UnexpectedChar('B')));
+
+        for rule in [critters, BlockRule::tron(), BlockRule::billiard_ball()] {
+            assert!(rule.is_reversible());
+        }
+        assert!(!BlockRule::new(&[0; 16]).unwrap().is_reversible());
+    }
+
+    #[test]
+    fn test_ball_moves_diagonally() {
+        let mut universe = Universe::new(8, 8);
+        universe.set_block_rule(&BlockRule::billiard_ball());
+        universe.set_cells(&[(2, 2)]);
+        universe.tick();
+        assert_eq!(universe.block_phase(), 1);
+        assert!(universe.get_cells()[3 * 8 + 3]);
+        universe.tick();
+        assert!(universe.get_cells()[4 * 8 + 4]);
+        assert_eq!(universe.get_cells().count_ones(..), 1);
+    }
+
+    #[test]
+    fn test_reversing_restores_the_start() {
+        let mut universe = Universe::new(16, 12);
+        universe.insert_pattern(&Pattern::r_pentomino(), 3, 4, 0);
+        universe.insert_pattern(&Pattern::glider(), 7, 10, 0);
+        universe.set_block_rule(&BlockRule::critters());
+        let start = universe.get_cells().clone();
+
+        for _ in 0..25 {
+            universe.tick();
+        }
+        assert_ne!(universe.get_cells(), &start);
+        for _ in 0..25 {
+            universe.tick_reverse().unwrap();
+        }
+        assert_eq!(universe.get_cells(), &start);
+        assert_eq!(universe.block_phase(), 0);
+    }
+
+    #[test]
+    fn test_edges_without_wrapping() {
+        // On a plane, the odd phase leaves the outer ring of cells alone
+        let mut universe = Universe::new(4, 4);
+        universe.set_topology(&Topology::plane()).unwrap();
+        universe.set_block_rule(&BlockRule::tron());
+        universe.tick();
+        assert_eq!(universe.get_cells().count_ones(..), 16);
+        universe.tick();
+        assert_eq!(universe.get_cells().ones().collect::<Vec<_>>(), vec![0, 1, 2, 3, 4, 7, 8, 11, 12, 13, 14, 15]);
+    }
+
+    #[test]
+    fn test_reverse_needs_reversible_rule() {
+        let mut universe = Universe::new(4, 4);
+        assert!(universe.tick_reverse().is_err());
+        universe.set_block_rule(&BlockRule::new(&[0; 16]).unwrap());
+        assert!(universe.tick_reverse().is_err());
+        universe.set_rule(&crate::Rule::conway());
+        assert_eq!(universe.block_rule(), None);
+    }
+}
@@ -0,0 +1,171 @@
+use wasm_bindgen::prelude::*;
+
+use crate::RuleError;
+
+// Furthest a neighbour can be from its cell, in rows or columns
+pub const MAX_REACH: i32 = 3;
+const SIDE: i32 = 2 * MAX_REACH + 1;
+
+// Offsets of the Moore neighbours, in the same order as the indices
+// returned by Universe::get_neighbour_array
+const MOORE_OFFSETS: [(i32, i32); 8] = [
+    (-1, -1), (-1, 0), (-1, 1),
+    (0, -1), (0, 1),
+    (1, -1), (1, 0), (1, 1),
+];
+
+// Set of (row, column) offsets that count as a cell's neighbours. The
+// offsets are kept as a bitmask over the square of cells within MAX_REACH
+// of the centre, so neighbourhoods stay cheap to copy around with rules.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Neighbourhood {
+    mask: u64,
+}
+
+#[wasm_bindgen]
+impl Neighbourhood {
+    pub fn moore() -> Neighbourhood {
+        Self::from_offsets(&MOORE_OFFSETS)
+    }
+
+    pub fn von_neumann() -> Neighbourhood {
+        Self::from_offsets(&[(-1, 0), (0, -1), (0, 1), (1, 0)])
+    }
+
+    // Hexagonal neighbourhood emulated on the square grid the way Golly
+    // does, by ignoring the NE and SW corners of the Moore neighbourhood
+    pub fn hexagonal() -> Neighbourhood {
+        Self::from_offsets(&[(-1, -1), (-1, 0), (0, -1), (0, 1), (1, 0), (1, 1)])
+    }
+
+    // Build a neighbourhood from a flat list of row and column offset
+    // pairs, e.g. [-2, 0, 2, 0] for the cells two rows above and below
+    pub fn custom(offsets: &[i32]) -> Result<Neighbourhood, RuleError> {
+        if offsets.len() % 2 != 0 {
+            return Err(RuleError::UnpairedOffsets(offsets.len()));
+        }
+        let mut pairs = Vec::with_capacity(offsets.len() / 2);
+        for pair in offsets.chunks(2) {
+            let (row, col) = (pair[0], pair[1]);
+            if (row, col) == (0, 0) || row.abs() > MAX_REACH || col.abs() > MAX_REACH {
+                return Err(RuleError::InvalidOffset(row, col));
+            }
+            pairs.push((row, col));
+        }
+        Ok(Self::from_offsets(&pairs))
+    }
+
+    // Number of neighbours each cell has
+    pub fn size(&self) -> u32 {
+        self.mask.count_ones()
+    }
+
+    pub fn contains(&self, row: i32, col: i32) -> bool {
+        row.abs() <= MAX_REACH && col.abs() <= MAX_REACH && self.mask & Self::bit(row, col) != 0
+    }
+
+    // Flat list of row and column offset pairs, as taken by custom
+    pub fn offset_list(&self) -> Vec<i32> {
+        self.offsets().flat_map(|(row, col)| [row, col]).collect()
+    }
+}
+
+impl Neighbourhood {
+    #[inline(always)]
+    fn bit(row: i32, col: i32) -> u64 {
+        1 << ((row + MAX_REACH) * SIDE + col + MAX_REACH)
+    }
+
+    fn from_offsets(offsets: &[(i32, i32)]) -> Neighbourhood {
+        let mask = offsets.iter().fold(0, |mask, &(row, col)| mask | Self::bit(row, col));
+        Neighbourhood { mask }
+    }
+
+    // Offsets of every neighbour, row by row
+    pub fn offsets(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        (-MAX_REACH..=MAX_REACH)
+            .flat_map(|row| (-MAX_REACH..=MAX_REACH).map(move |col| (row, col)))
+            .filter(move |&(row, col)| self.mask & Self::bit(row, col) != 0)
+    }
+
+    pub fn is_moore(&self) -> bool {
+        *self == Self::moore()
+    }
+
+    // For neighbourhoods inside the 3x3 square, which of the eight Moore
+    // neighbours are included, in get_neighbour_array order
+    pub fn moore_subset(&self) -> Option<[bool; 8]> {
+        if self.offsets().any(|(row, col)| row.abs() > 1 || col.abs() > 1) {
+            return None;
+        }
+        let mut included = [false; 8];
+        for (include, &(row, col)) in included.iter_mut().zip(MOORE_OFFSETS.iter()) {
+            *include = self.contains(row, col);
+        }
+        Some(included)
+    }
+
+    // Suffix Golly uses for this neighbourhood in rulestrings
+    pub fn suffix(&self) -> Option<char> {
+        if *self == Self::von_neumann() {
+            Some('V')
+        }
+        else if *self == Self::hexagonal() {
+            Some('H')
+        }
+        else {
+            None
+        }
+    }
+
+    pub fn from_suffix(suffix: char) -> Option<Neighbourhood> {
+        match suffix.to_ascii_uppercase() {
+            'V' => Some(Self::von_neumann()),
+            'H' => Some(Self::hexagonal()),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Neighbourhood {
+    fn default() -> Neighbourhood {
+        Neighbourhood::moore()
+    }
+}
+
+
+//                      Testing
+// ======================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sizes() {
+        assert_eq!(Neighbourhood::moore().size(), 8);
+        assert_eq!(Neighbourhood::von_neumann().size(), 4);
+        assert_eq!(Neighbourhood::hexagonal().size(), 6);
+    }
+
+    #[test]
+    fn test_hexagonal_skips_ne_and_sw() {
+        let hex = Neighbourhood::hexagonal();
+        assert!(!hex.contains(-1, 1));
+        assert!(!hex.contains(1, -1));
+        assert_eq!(hex.moore_subset(), Some([true, true, false, true, true, false, true, true]));
+    }
+
+    #[test]
+    fn test_custom() {
+        let custom = Neighbourhood::custom(&[-2, 0, 2, 0, 0, 3]).unwrap();
+        assert_eq!(custom.size(), 3);
+        assert_eq!(custom.offset_list(), vec![-2, 0, 0, 3, 2, 0]);
+        assert_eq!(custom.moore_subset(), None);
+
+        assert_eq!(Neighbourhood::custom(&[0, 0]), Err(RuleError::InvalidOffset(0, 0)));
+        assert_eq!(Neighbourhood::custom(&[4, 1]), Err(RuleError::InvalidOffset(4, 1)));
+        assert_eq!(Neighbourhood::custom(&[1, 0, 2]), Err(RuleError::UnpairedOffsets(3)));
+        assert!(Neighbourhood::custom(&[1, 1, 2]).is_err());
+    }
+}
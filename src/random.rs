@@ -0,0 +1,89 @@
+use wasm_bindgen::prelude::*;
+
+// Small seeded generator (SplitMix64) for reproducible soups. It only
+// uses integer arithmetic, so a seed gives the same soup on wasm and
+// native builds.
+#[derive(Clone, Debug)]
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Random {
+        Random { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // Move on as if next_u64 had been called count times
+    pub fn skip(&mut self, count: u64) {
+        self.state = self.state.wrapping_add(count.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    }
+
+    // Whether an event with the given probability happens. The density is
+    // turned into a 53 bit threshold, which is exact on every platform.
+    pub fn chance(&mut self, density: f64) -> bool {
+        let threshold = (density.clamp(0.0, 1.0) * (1u64 << 53) as f64) as u64;
+        self.next_u64() >> 11 < threshold
+    }
+}
+
+// Turn a string into a seed with 64 bit FNV-1a, so soups can be named
+#[wasm_bindgen]
+pub fn seed_from_str(seed: &str) -> u64 {
+    seed.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100_0000_01b3))
+}
+
+// A seed that's different every time, for when the soup needn't be
+// reproducible
+pub(crate) fn random_seed() -> u64 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        let high = (js_sys::Math::random() * 4294967296.0) as u64;
+        let low = (js_sys::Math::random() * 4294967296.0) as u64;
+        high << 32 | low
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        use std::time::{SystemTime, UNIX_EPOCH};
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos() as u64);
+        Random::new(nanos).next_u64()
+    }
+}
+
+
+//                      Testing
+// ======================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sequence_is_fixed() {
+        // Reference outputs of SplitMix64 seeded with 0
+        let mut random = Random::new(0);
+        assert_eq!(random.next_u64(), 0xe220_a839_7b1d_cdaf);
+        assert_eq!(random.next_u64(), 0x6e78_9e6a_a1b9_65f4);
+        assert_eq!(seed_from_str(""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(seed_from_str("a"), 0xaf63_dc4c_8601_ec8c);
+
+        let mut skipped = Random::new(0);
+        skipped.skip(1);
+        assert_eq!(skipped.next_u64(), 0x6e78_9e6a_a1b9_65f4);
+    }
+
+    #[test]
+    fn test_chance() {
+        let mut random = Random::new(7);
+        assert!((0..1000).all(|_| !random.chance(0.0)));
+        assert!((0..1000).all(|_| random.chance(1.0)));
+        let hits = (0..10000).filter(|_| random.chance(0.25)).count();
+        assert!((2300..2700).contains(&hits), "{}", hits);
+    }
+}
@@ -0,0 +1,397 @@
+use std::{
+    error::Error,
+    fmt,
+    str::FromStr,
+};
+use wasm_bindgen::prelude::*;
+
+use crate::{isotropic, Neighbourhood};
+
+// Outer-totalistic Life-like rule, e.g. "B3/S23" for Conway's Life.
+// Birth and survival conditions are stored as bitmasks indexed by the
+// number of live neighbours, so bit n of `birth` is set if a dead cell
+// with n live neighbours should come alive. Neighbours are counted over
+// the rule's neighbourhood, Moore unless the rulestring ends in Golly's
+// "V" (von Neumann) or "H" (hexagonal) suffix.
+//
+// Isotropic non-totalistic rules in Hensel notation ("B2-a/S12") also
+// look at where the neighbours are. Those keep a 512 entry lookup table
+// indexed by the cell's state in bit 8 and its neighbour configuration in
+// bits 0-7, while the count masks hold every count any configuration of
+// which is included.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rule {
+    birth: u16,
+    survival: u16,
+    neighbourhood: Neighbourhood,
+    table: Option<[u64; 8]>,
+}
+
+// Largest neighbour count a B/S rulestring can refer to
+const MAX_NEIGHBOURS: u8 = 8;
+
+#[wasm_bindgen]
+impl Rule {
+    pub fn conway() -> Rule {
+        Rule { birth: 1 << 3, survival: 1 << 2 | 1 << 3, neighbourhood: Neighbourhood::moore(), table: None }
+    }
+
+    // Parse a rulestring in B/S notation ("B36/S23"), or in the older
+    // S/B notation where survival comes first ("23/36"), optionally
+    // followed by a neighbourhood suffix ("B2/S34H"). Either notation can
+    // use Hensel's letters for isotropic rules ("B2-a/S12").
+    pub fn parse(rulestring: &str) -> Result<Rule, RuleError> {
+        let mut rulestring = rulestring.trim();
+        if rulestring.is_empty() {
+            return Err(RuleError::Empty);
+        }
+
+        let mut neighbourhood = Neighbourhood::moore();
+        if let Some(suffixed) = rulestring.chars().last().and_then(Neighbourhood::from_suffix) {
+            neighbourhood = suffixed;
+            rulestring = &rulestring[..rulestring.len() - 1];
+        }
+        let max_count = neighbourhood.size() as u8;
+
+        let parts: Vec<&str> = rulestring.split('/').collect();
+        if parts.len() != 2 {
+            return Err(RuleError::MissingSeparator);
+        }
+
+        let first = parts[0].chars().next().map(|c| c.to_ascii_uppercase());
+        let lettered = matches!(first, Some('B') | Some('S'));
+        let hensel = parts.iter().any(|part| {
+            let mut chars = part.chars();
+            if lettered {
+                chars.next();
+            }
+            isotropic::is_hensel(chars.as_str())
+        });
+        if hensel {
+            if !neighbourhood.is_moore() {
+                return Err(RuleError::Unsupported("isotropic rules outside the Moore neighbourhood"));
+            }
+            return Self::parse_isotropic(&parts, lettered);
+        }
+
+        let (mut birth, mut survival) = (None, None);
+        if lettered {
+            for part in parts {
+                let mut chars = part.chars();
+                match chars.next().map(|c| c.to_ascii_uppercase()) {
+                    Some('B') if birth.is_none() => birth = Some(Self::parse_counts(chars.as_str(), max_count)?),
+                    Some('S') if survival.is_none() => survival = Some(Self::parse_counts(chars.as_str(), max_count)?),
+                    Some(c) => return Err(RuleError::UnexpectedChar(c)),
+                    None => return Err(RuleError::MissingSeparator),
+                }
+            }
+        }
+        else {
+            survival = Some(Self::parse_counts(parts[0], max_count)?);
+            birth = Some(Self::parse_counts(parts[1], max_count)?);
+        }
+
+        match (birth, survival) {
+            (Some(birth), Some(survival)) => Ok(Rule { birth, survival, neighbourhood, table: None }),
+            _ => Err(RuleError::MissingSeparator),
+        }
+    }
+
+    // Whether the rule depends on the arrangement of neighbours rather
+    // than just how many there are
+    pub fn is_isotropic(&self) -> bool {
+        self.table.is_some()
+    }
+
+    pub fn births(&self, live_neighbours: u8) -> bool {
+        live_neighbours <= MAX_NEIGHBOURS && self.birth & (1 << live_neighbours) != 0
+    }
+
+    pub fn survives(&self, live_neighbours: u8) -> bool {
+        live_neighbours <= MAX_NEIGHBOURS && self.survival & (1 << live_neighbours) != 0
+    }
+
+    pub fn neighbourhood(&self) -> Neighbourhood {
+        self.neighbourhood
+    }
+
+    // The same birth and survival counts over a different neighbourhood.
+    // Hensel letters only describe Moore neighbours, so isotropic rules
+    // fall back to their count masks anywhere else.
+    pub fn with_neighbourhood(&self, neighbourhood: &Neighbourhood) -> Rule {
+        let table = if neighbourhood.is_moore() { self.table } else { None };
+        Rule { neighbourhood: *neighbourhood, table, ..*self }
+    }
+
+    #[wasm_bindgen(js_name = toString)]
+    pub fn rulestring(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl Rule {
+    #[inline(always)]
+    pub fn next_state(&self, alive: bool, live_neighbours: u8) -> bool {
+        let mask = if alive { self.survival } else { self.birth };
+        // Custom neighbourhoods can have more neighbours than the masks hold
+        mask.checked_shr(live_neighbours as u32).unwrap_or(0) & 1 != 0
+    }
+
+    // Next state given the configuration of the Moore neighbours, with a
+    // bit for each in Universe::get_neighbour_array order
+    #[inline(always)]
+    pub fn next_state_of(&self, alive: bool, neighbours: u8) -> bool {
+        match &self.table {
+            Some(table) => {
+                let index = (alive as usize) << 8 | neighbours as usize;
+                table[index / 64] >> (index % 64) & 1 != 0
+            },
+            None => self.next_state(alive, neighbours.count_ones() as u8),
+        }
+    }
+
+    pub(crate) fn isotropic_table(&self) -> Option<&[u64; 8]> {
+        self.table.as_ref()
+    }
+
+    fn parse_isotropic(parts: &[&str], lettered: bool) -> Result<Rule, RuleError> {
+        let (mut birth, mut survival) = (None, None);
+        for (i, part) in parts.iter().enumerate() {
+            let (kind, configs) = if lettered {
+                let mut chars = part.chars();
+                let kind = chars.next().map(|c| c.to_ascii_uppercase());
+                (kind, chars.as_str())
+            }
+            else {
+                (if i == 0 { Some('S') } else { Some('B') }, *part)
+            };
+            match kind {
+                Some('B') if birth.is_none() => birth = Some(isotropic::parse_configs(configs)?),
+                Some('S') if survival.is_none() => survival = Some(isotropic::parse_configs(configs)?),
+                Some(c) => return Err(RuleError::UnexpectedChar(c)),
+                None => return Err(RuleError::MissingSeparator),
+            }
+        }
+        let (birth, survival) = match (birth, survival) {
+            (Some(birth), Some(survival)) => (birth, survival),
+            _ => return Err(RuleError::MissingSeparator),
+        };
+
+        let mask = |configs: &isotropic::Configs| (0..=255u8)
+            .filter(|&config| isotropic::contains(configs, config))
+            .fold(0, |mask, config| mask | 1 << config.count_ones());
+        let mut table = [0; 8];
+        table[..4].copy_from_slice(&birth);
+        table[4..].copy_from_slice(&survival);
+        // Letters that add up to whole counts make a totalistic rule
+        let table = if isotropic::is_totalistic(&birth) && isotropic::is_totalistic(&survival) { None } else { Some(table) };
+
+        Ok(Rule { birth: mask(&birth), survival: mask(&survival), neighbourhood: Neighbourhood::moore(), table })
+    }
+
+    // Build a Moore rule from the digit lists of its birth and survival parts
+    pub(crate) fn from_counts(birth: &str, survival: &str) -> Result<Rule, RuleError> {
+        Ok(Rule {
+            birth: Self::parse_counts(birth, MAX_NEIGHBOURS)?,
+            survival: Self::parse_counts(survival, MAX_NEIGHBOURS)?,
+            neighbourhood: Neighbourhood::moore(),
+            table: None,
+        })
+    }
+
+    fn parse_counts(digits: &str, max_count: u8) -> Result<u16, RuleError> {
+        let mut mask = 0;
+        for c in digits.chars() {
+            let count = c.to_digit(10).ok_or(RuleError::UnexpectedChar(c))? as u8;
+            if count > max_count {
+                return Err(RuleError::InvalidCount(count));
+            }
+            mask |= 1 << count;
+        }
+        Ok(mask)
+    }
+
+    // Write the birth and survival parts without the separator, for other
+    // rule families that embed a B/S rule in their own rulestrings
+    pub(crate) fn write_birth(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.table {
+            Some(table) => isotropic::write_configs(f, &[table[0], table[1], table[2], table[3]]),
+            None => Self::write_counts(f, self.birth),
+        }
+    }
+
+    pub(crate) fn write_survival(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.table {
+            Some(table) => isotropic::write_configs(f, &[table[4], table[5], table[6], table[7]]),
+            None => Self::write_counts(f, self.survival),
+        }
+    }
+
+    fn write_counts(f: &mut fmt::Formatter<'_>, mask: u16) -> fmt::Result {
+        for count in 0..=MAX_NEIGHBOURS {
+            if mask & (1 << count) != 0 {
+                write!(f, "{}", count)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for Rule {
+    fn default() -> Rule {
+        Rule::conway()
+    }
+}
+
+impl FromStr for Rule {
+    type Err = RuleError;
+
+    fn from_str(s: &str) -> Result<Rule, RuleError> {
+        Rule::parse(s)
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "B")?;
+        self.write_birth(f)?;
+        write!(f, "/S")?;
+        self.write_survival(f)?;
+        match self.neighbourhood.suffix() {
+            Some(suffix) => write!(f, "{}", suffix),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RuleError {
+    Empty,
+    MissingSeparator,
+    UnexpectedChar(char),
+    InvalidCount(u8),
+    InvalidStates(u32),
+    InvalidRange(u32),
+    InvalidOffset(i32, i32),
+    UnpairedOffsets(usize),
+    InvalidBlockTable,
+    Unsupported(&'static str),
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleError::Empty => write!(f, "rulestring is empty"),
+            RuleError::MissingSeparator => write!(f, "rulestring must have a birth and a survival part separated by '/'"),
+            RuleError::UnexpectedChar(c) => write!(f, "unexpected character '{}' in rulestring", c),
+            RuleError::InvalidCount(n) => write!(f, "neighbour count {} is out of range for the neighbourhood", n),
+            RuleError::InvalidStates(n) => write!(f, "number of states {} is out of range", n),
+            RuleError::InvalidRange(n) => write!(f, "neighbourhood range {} is out of range", n),
+            RuleError::InvalidOffset(row, col) => write!(f, "neighbour offset ({}, {}) is out of range", row, col),
+            RuleError::UnpairedOffsets(n) => write!(f, "neighbour offsets come in row and column pairs, but {} numbers were given", n),
+            RuleError::InvalidBlockTable => write!(f, "block rules need 16 entries from 0 to 15"),
+            RuleError::Unsupported(what) => write!(f, "{} are not supported", what),
+        }
+    }
+}
+
+impl Error for RuleError {}
+
+impl From<RuleError> for JsValue {
+    fn from(error: RuleError) -> JsValue {
+        js_sys::Error::new(&error.to_string()).into()
+    }
+}
+
+
+//                      Testing
+// ======================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_conway() {
+        let rule = Rule::parse("B3/S23").unwrap();
+        assert_eq!(rule, Rule::conway());
+        assert_eq!(Rule::parse("b3/s23").unwrap(), rule);
+        assert_eq!(Rule::parse("S23/B3").unwrap(), rule);
+        assert_eq!(Rule::parse("23/3").unwrap(), rule);
+    }
+
+    #[test]
+    fn test_parse_empty_conditions() {
+        let seeds = Rule::parse("B2/S").unwrap();
+        assert!(seeds.births(2));
+        for n in 0..=8 {
+            assert!(!seeds.survives(n));
+        }
+    }
+
+    #[test]
+    fn test_next_state() {
+        let highlife = Rule::parse("B36/S23").unwrap();
+        assert!(highlife.next_state(false, 6));
+        assert!(highlife.next_state(true, 2));
+        assert!(!highlife.next_state(true, 6));
+        assert!(!highlife.next_state(false, 2));
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        for rulestring in ["B3/S23", "B36/S23", "B2/S", "B3678/S34678", "B/S012345678"] {
+            let rule = Rule::parse(rulestring).unwrap();
+            assert_eq!(rule.to_string(), rulestring);
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(Rule::parse(""), Err(RuleError::Empty));
+        assert_eq!(Rule::parse("B3S23"), Err(RuleError::MissingSeparator));
+        assert_eq!(Rule::parse("B3/B23"), Err(RuleError::UnexpectedChar('B')));
+        assert_eq!(Rule::parse("B39/S23"), Err(RuleError::InvalidCount(9)));
+        assert_eq!(Rule::parse("B3/S2x"), Err(RuleError::UnexpectedChar('x')));
+        assert_eq!(Rule::parse("B5/S4V"), Err(RuleError::InvalidCount(5)));
+        assert_eq!(Rule::parse("B7/S2H"), Err(RuleError::InvalidCount(7)));
+    }
+
+    #[test]
+    fn test_parse_isotropic() {
+        let rule = Rule::parse("B2-a/S12").unwrap();
+        assert!(rule.is_isotropic());
+        assert_eq!(rule.to_string(), "B2-a/S12");
+        assert_eq!(Rule::parse("12/2-a").unwrap(), rule);
+        // NW and NE alive is 2c, NW and N is 2a
+        assert!(rule.next_state_of(false, 0x05));
+        assert!(!rule.next_state_of(false, 0x03));
+        assert!(rule.births(2));
+
+        let tlife = Rule::parse("B3/S2-i34q").unwrap();
+        assert_eq!(tlife.to_string(), "B3/S2-i34q");
+        assert!(tlife.next_state_of(true, 0x36));
+        assert!(!tlife.next_state_of(true, 0x18));
+
+        // Letters covering whole counts are just a totalistic rule
+        let conway = Rule::parse("B3cekainyqjr/S2cekain3").unwrap();
+        assert_eq!(conway, Rule::conway());
+        assert!(!conway.is_isotropic());
+
+        assert!(Rule::parse("B2x/S").is_err());
+        assert!(Rule::parse("B2a/S2H").is_err());
+    }
+
+    #[test]
+    fn test_parse_neighbourhood_suffix() {
+        let hex = Rule::parse("B2/S34H").unwrap();
+        assert_eq!(hex.neighbourhood(), Neighbourhood::hexagonal());
+        assert_eq!(hex.to_string(), "B2/S34H");
+        assert_eq!(Rule::parse("34/2h").unwrap(), hex);
+
+        let von_neumann = Rule::parse("B13/S012V").unwrap();
+        assert_eq!(von_neumann.neighbourhood(), Neighbourhood::von_neumann());
+        assert_eq!(von_neumann.to_string(), "B13/S012V");
+        assert_eq!(Rule::conway().neighbourhood(), Neighbourhood::moore());
+    }
+}
@@ -0,0 +1,925 @@
+use std::{
+    cmp::min,
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt,
+};
+use wasm_bindgen::prelude::*;
+
+use crate::{utils, Pattern};
+
+// Rules loaded from Golly's .rule files, which define a CA with up to 256
+// states either as a list of transitions (@TABLE) or as a decision tree
+// over the neighbour states (@TREE).
+
+const MAX_STATES: usize = 256;
+
+// Limit on transitions once variables and symmetries are expanded
+const MAX_TRANSITIONS: usize = 1 << 16;
+
+// Set of states, a bit for each of the 256
+type States = [u64; 4];
+
+fn single(state: u8) -> States {
+    let mut states = [0; 4];
+    states[state as usize / 64] |= 1 << (state % 64);
+    states
+}
+
+fn states_iter(states: &States) -> impl Iterator<Item = u8> + '_ {
+    (0..=255u8).filter(move |&state| states[state as usize / 64] >> (state % 64) & 1 != 0)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RuleTableError {
+    // Line of the file the error is on, counting from 1, or 0 if it's
+    // about the file as a whole
+    line: usize,
+    message: String,
+}
+
+impl RuleTableError {
+    fn new(line: usize, message: impl Into<String>) -> RuleTableError {
+        RuleTableError { line, message: message.into() }
+    }
+
+    pub fn line(&self) -> usize {
+        self.line
+    }
+}
+
+impl fmt::Display for RuleTableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}", self.message)
+        }
+        else {
+            write!(f, "line {}: {}", self.line, self.message)
+        }
+    }
+}
+
+impl Error for RuleTableError {}
+
+impl From<RuleTableError> for JsValue {
+    fn from(error: RuleTableError) -> JsValue {
+        js_sys::Error::new(&error.to_string()).into()
+    }
+}
+
+// Neighbourhoods a rule table can use
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TableNeighbourhood {
+    Moore,
+    VonNeumann,
+    Hexagonal,
+    OneDimensional,
+}
+
+impl TableNeighbourhood {
+    fn parse(name: &str) -> Option<TableNeighbourhood> {
+        match name {
+            "Moore" => Some(TableNeighbourhood::Moore),
+            "vonNeumann" => Some(TableNeighbourhood::VonNeumann),
+            "hexagonal" => Some(TableNeighbourhood::Hexagonal),
+            "oneDimensional" => Some(TableNeighbourhood::OneDimensional),
+            _ => None,
+        }
+    }
+
+    // Row and column offsets of the neighbours in the order transitions
+    // list them, clockwise from north
+    fn offsets(&self) -> &'static [(i32, i32)] {
+        match self {
+            TableNeighbourhood::Moore => &[(-1, 0), (-1, 1), (0, 1), (1, 1), (1, 0), (1, -1), (0, -1), (-1, -1)],
+            TableNeighbourhood::VonNeumann => &[(-1, 0), (0, 1), (1, 0), (0, -1)],
+            TableNeighbourhood::Hexagonal => &[(-1, 0), (0, 1), (1, 1), (1, 0), (0, -1), (-1, -1)],
+            TableNeighbourhood::OneDimensional => &[(0, -1), (0, 1)],
+        }
+    }
+
+    // Permutations of the neighbours for a symmetries line, each giving
+    // the neighbour read into every position. None stands for "permute".
+    fn symmetries(&self, name: &str) -> Option<Option<Vec<Vec<usize>>>> {
+        let n = self.offsets().len();
+        let (rotations, reflect) = match (self, name) {
+            (_, "none") => (1, false),
+            (_, "permute") => return Some(None),
+            (TableNeighbourhood::Moore, "rotate4") | (TableNeighbourhood::VonNeumann, "rotate4") => (4, false),
+            (TableNeighbourhood::Moore, "rotate8") => (8, false),
+            (TableNeighbourhood::Moore, "reflect_horizontal") | (TableNeighbourhood::VonNeumann, "reflect_horizontal") => (1, true),
+            (TableNeighbourhood::Moore, "rotate4reflect") | (TableNeighbourhood::VonNeumann, "rotate4reflect") => (4, true),
+            (TableNeighbourhood::Moore, "rotate8reflect") => (8, true),
+            (TableNeighbourhood::Hexagonal, "rotate2") => (2, false),
+            (TableNeighbourhood::Hexagonal, "rotate3") => (3, false),
+            (TableNeighbourhood::Hexagonal, "rotate6") => (6, false),
+            (TableNeighbourhood::Hexagonal, "rotate6reflect") => (6, true),
+            (TableNeighbourhood::OneDimensional, "reflect") => (1, true),
+            _ => return None,
+        };
+
+        // The neighbours are in cyclic order, so rotating is shifting the
+        // list and reflecting is reversing it about the first neighbour
+        let mut permutations = Vec::new();
+        for rotation in 0..rotations {
+            let shift = rotation * n / rotations;
+            let rotated: Vec<usize> = (0..n).map(|i| (i + shift) % n).collect();
+            if reflect {
+                permutations.push(rotated.iter().map(|&i| (n - i) % n).collect());
+            }
+            permutations.push(rotated);
+        }
+        Some(Some(permutations))
+    }
+}
+
+// Transition with a set of states for the centre (position 0) and each
+// neighbour
+#[derive(Clone, Debug)]
+struct Transition {
+    inputs: Vec<States>,
+    output: u8,
+}
+
+// Transition table compiled for lookup: for each position and state, the
+// set of transitions that state matches at that position, as a bitset.
+// The next state comes from the first transition matched at every
+// position, or the cell keeps its state if none is.
+#[derive(Clone, Debug)]
+struct Table {
+    outputs: Vec<u8>,
+    words: usize,
+    matches: Vec<u64>,
+}
+
+impl Table {
+    fn compile(transitions: &[Transition], states: usize) -> Table {
+        let words = transitions.len().div_ceil(64).max(1);
+        let positions = transitions.first().map_or(1, |t| t.inputs.len());
+        let mut matches = vec![0; positions * states * words];
+        for (t, transition) in transitions.iter().enumerate() {
+            for (position, inputs) in transition.inputs.iter().enumerate() {
+                for state in states_iter(inputs) {
+                    matches[(position * states + state as usize) * words + t / 64] |= 1 << (t % 64);
+                }
+            }
+        }
+        Table { outputs: transitions.iter().map(|t| t.output).collect(), words, matches }
+    }
+
+    fn next_state(&self, states: usize, centre: u8, neighbours: &[u8]) -> u8 {
+        let lookup = |position: usize, state: u8| (position * states + state as usize) * self.words;
+        for word in 0..self.words {
+            let mut matched = self.matches[lookup(0, centre) + word];
+            for (i, &state) in neighbours.iter().enumerate() {
+                if matched == 0 {
+                    break;
+                }
+                matched &= self.matches[lookup(i + 1, state) + word];
+            }
+            if matched != 0 {
+                return self.outputs[word * 64 + matched.trailing_zeros() as usize];
+            }
+        }
+        centre
+    }
+}
+
+// Rule tree: each node at level L picks a child by the state of one
+// variable, and level 1 nodes pick the next state itself
+#[derive(Clone, Debug)]
+struct Tree {
+    // Children of every node, states wide
+    children: Vec<u32>,
+    root: u32,
+}
+
+impl Tree {
+    // Neighbours in the order the tree reads them (NW, NE, SW, SE, N, W,
+    // E, S for Moore, N, W, E, S for von Neumann) as indices into the
+    // clockwise neighbour order, followed by the centre
+    fn next_state(&self, states: usize, neighbourhood: TableNeighbourhood, centre: u8, neighbours: &[u8]) -> u8 {
+        let order: &[usize] = match neighbourhood {
+            TableNeighbourhood::Moore => &[7, 1, 5, 3, 0, 6, 2, 4],
+            _ => &[0, 3, 1, 2],
+        };
+        let mut node = self.root as usize;
+        for &i in order {
+            node = self.children[node * states + neighbours[i] as usize] as usize;
+        }
+        self.children[node * states + centre as usize] as u8
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Kind {
+    Table(Table),
+    Tree(Tree),
+}
+
+// A multi-state rule loaded from a Golly .rule file
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct RuleTable {
+    name: String,
+    states: usize,
+    neighbourhood: TableNeighbourhood,
+    kind: Kind,
+}
+
+#[wasm_bindgen]
+impl RuleTable {
+    // Parse the text of a .rule file, using its @TABLE or @TREE section.
+    // Text without any @ sections is read as a bare table.
+    pub fn parse(text: &str) -> Result<RuleTable, RuleTableError> {
+        let mut name = String::new();
+        let mut section: Option<(&str, usize, Vec<&str>)> = None;
+        let mut current: Option<(&str, usize, Vec<&str>)> = None;
+        let mut any_sections = false;
+
+        for (i, line) in text.lines().enumerate() {
+            let trimmed = line.trim();
+            if let Some(header) = trimmed.strip_prefix('@') {
+                any_sections = true;
+                let mut words = header.split_whitespace();
+                let kind = words.next().unwrap_or("");
+                if kind == "RULE" {
+                    name = words.next().unwrap_or("").to_string();
+                }
+                if section.is_none() {
+                    section = current.take().filter(|(kind, _, _)| *kind == "TABLE" || *kind == "TREE");
+                }
+                current = Some((kind, i + 2, Vec::new()));
+            }
+            else if let Some((_, _, lines)) = current.as_mut() {
+                lines.push(line);
+            }
+        }
+        if section.is_none() {
+            section = current.take().filter(|(kind, _, _)| *kind == "TABLE" || *kind == "TREE");
+        }
+
+        let (kind, first_line, lines) = match section {
+            Some(section) => section,
+            None if !any_sections => ("TABLE", 1, text.lines().collect()),
+            None => return Err(RuleTableError::new(0, "no @TABLE or @TREE section")),
+        };
+        let mut rule = match kind {
+            "TABLE" => Self::parse_table(&lines, first_line)?,
+            _ => Self::parse_tree(&lines, first_line)?,
+        };
+        rule.name = name;
+        Ok(rule)
+    }
+
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    pub fn states(&self) -> u32 {
+        self.states as u32
+    }
+}
+
+impl RuleTable {
+    // Lines with comments removed, paired with their line numbers
+    fn content<'a>(lines: &'a [&'a str], first_line: usize) -> impl Iterator<Item = (usize, &'a str)> + 'a {
+        lines.iter().enumerate().filter_map(move |(i, line)| {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() { None } else { Some((first_line + i, line)) }
+        })
+    }
+
+    fn parse_states(value: &str, line: usize) -> Result<usize, RuleTableError> {
+        match value.trim().parse::<usize>() {
+            Ok(states) if (2..=MAX_STATES).contains(&states) => Ok(states),
+            _ => Err(RuleTableError::new(line, format!("number of states must be from 2 to {}", MAX_STATES))),
+        }
+    }
+
+    fn parse_table(lines: &[&str], first_line: usize) -> Result<RuleTable, RuleTableError> {
+        let mut states = None;
+        let mut neighbourhood = None;
+        let mut symmetries = Some(vec![]);
+        let mut symmetries_name = "none";
+        let mut vars: HashMap<String, States> = HashMap::new();
+        let mut transitions = Vec::new();
+        let mut seen = HashSet::new();
+
+        for (line, content) in Self::content(lines, first_line) {
+            if let Some((key, value)) = content.split_once(':') {
+                let value = value.trim();
+                match key.trim() {
+                    "n_states" | "num_states" => states = Some(Self::parse_states(value, line)?),
+                    "neighborhood" => {
+                        neighbourhood = Some(TableNeighbourhood::parse(value)
+                            .ok_or_else(|| RuleTableError::new(line, format!("unknown neighborhood '{}'", value)))?);
+                    },
+                    "symmetries" => symmetries_name = value,
+                    key => return Err(RuleTableError::new(line, format!("unknown setting '{}'", key))),
+                }
+                continue;
+            }
+
+            let (states, neighbourhood) = match (states, neighbourhood) {
+                (Some(states), Some(neighbourhood)) => (states, neighbourhood),
+                _ => return Err(RuleTableError::new(line, "n_states and neighborhood must come before variables and transitions")),
+            };
+            if symmetries.as_ref().map_or(false, |permutations| permutations.is_empty()) {
+                symmetries = neighbourhood.symmetries(symmetries_name).ok_or_else(|| RuleTableError::new(line,
+                    format!("symmetries '{}' don't exist for this neighborhood", symmetries_name)))?;
+            }
+
+            if let Some(declaration) = content.strip_prefix("var ") {
+                let (name, values) = declaration.split_once('=')
+                    .ok_or_else(|| RuleTableError::new(line, "variable declarations look like var a={0,1}"))?;
+                let values = Self::parse_set(values.trim(), &vars, states, line)?;
+                vars.insert(name.trim().to_string(), values);
+                continue;
+            }
+
+            let tokens = Self::tokenize(content, &vars, states, line)?;
+            let positions = neighbourhood.offsets().len() + 2;
+            if tokens.len() != positions {
+                return Err(RuleTableError::new(line, format!("transitions need {} entries, found {}", positions, tokens.len())));
+            }
+            for transition in Self::expand_variables(&tokens, &vars, line)? {
+                for transition in Self::expand_symmetries(transition, symmetries.as_deref()) {
+                    // Later transitions with the same inputs can never match
+                    if seen.insert(transition.inputs.clone()) {
+                        transitions.push(transition);
+                    }
+                }
+                if transitions.len() > MAX_TRANSITIONS {
+                    return Err(RuleTableError::new(line, format!("more than {} transitions after expanding variables and symmetries", MAX_TRANSITIONS)));
+                }
+            }
+        }
+
+        match (states, neighbourhood) {
+            (Some(states), Some(neighbourhood)) => Ok(RuleTable {
+                name: String::new(),
+                states,
+                neighbourhood,
+                kind: Kind::Table(Table::compile(&transitions, states)),
+            }),
+            (None, _) => Err(RuleTableError::new(0, "table is missing n_states")),
+            (_, None) => Err(RuleTableError::new(0, "table is missing neighborhood")),
+        }
+    }
+
+    fn parse_state(token: &str, states: usize, line: usize) -> Result<u8, RuleTableError> {
+        match token.parse::<usize>() {
+            Ok(state) if state < states => Ok(state as u8),
+            Ok(state) => Err(RuleTableError::new(line, format!("state {} is out of range", state))),
+            Err(_) => Err(RuleTableError::new(line, format!("'{}' is not a state", token))),
+        }
+    }
+
+    // A set like {0,1,a}, whose items are states or earlier variables
+    fn parse_set(set: &str, vars: &HashMap<String, States>, states: usize, line: usize) -> Result<States, RuleTableError> {
+        let items = set.strip_prefix('{').and_then(|set| set.strip_suffix('}'))
+            .ok_or_else(|| RuleTableError::new(line, format!("'{}' is not a set of states", set)))?;
+        let mut values = [0; 4];
+        for item in items.split(',').map(str::trim) {
+            let item_values = match vars.get(item) {
+                Some(&values) => values,
+                None => single(Self::parse_state(item, states, line)?),
+            };
+            for (value, item) in values.iter_mut().zip(item_values.iter()) {
+                *value |= item;
+            }
+        }
+        Ok(values)
+    }
+
+    // Split a transition into its entries, either comma separated or, for
+    // tables with at most 10 states, one character each
+    fn tokenize(content: &str, vars: &HashMap<String, States>, states: usize, line: usize) -> Result<Vec<Token>, RuleTableError> {
+        let entries: Vec<String> = if content.contains(',') {
+            let mut entries = vec![String::new()];
+            let mut depth = 0;
+            for c in content.chars() {
+                match c {
+                    ',' if depth == 0 => entries.push(String::new()),
+                    _ => {
+                        depth += (c == '{') as i32 - (c == '}') as i32;
+                        entries.last_mut().unwrap().push(c);
+                    },
+                }
+            }
+            entries.iter().map(|entry| entry.trim().to_string()).collect()
+        }
+        else {
+            content.chars().filter(|c| !c.is_whitespace()).map(String::from).collect()
+        };
+
+        entries.iter().map(|entry| {
+            if entry.starts_with('{') {
+                Ok(Token::Set(Self::parse_set(entry, vars, states, line)?))
+            }
+            else if vars.contains_key(entry) {
+                Ok(Token::Var(entry.clone()))
+            }
+            else if entry.starts_with(|c: char| c.is_ascii_digit()) {
+                Ok(Token::State(Self::parse_state(entry, states, line)?))
+            }
+            else {
+                Err(RuleTableError::new(line, format!("unknown variable '{}'", entry)))
+            }
+        }).collect()
+    }
+
+    // Variables that appear more than once in a transition are bound, so
+    // every occurrence takes the same value. Expand each combination of
+    // values for the bound variables into its own transition.
+    fn expand_variables(tokens: &[Token], vars: &HashMap<String, States>, line: usize) -> Result<Vec<Transition>, RuleTableError> {
+        let (inputs, output) = tokens.split_at(tokens.len() - 1);
+        let mut occurrences: HashMap<&str, usize> = HashMap::new();
+        for token in inputs {
+            if let Token::Var(name) = token {
+                *occurrences.entry(name).or_insert(0) += 1;
+            }
+        }
+        match &output[0] {
+            Token::Var(name) if !occurrences.contains_key(name.as_str()) => {
+                return Err(RuleTableError::new(line, format!("output variable '{}' isn't used by the inputs", name)));
+            },
+            Token::Set(_) => return Err(RuleTableError::new(line, "the output can't be a set of states")),
+            _ => {},
+        }
+
+        let mut bound: Vec<&str> = occurrences.iter()
+            .filter(|&(name, &count)| count > 1 || output[0] == Token::Var(name.to_string()))
+            .map(|(&name, _)| name)
+            .collect();
+        bound.sort_unstable();
+        let values: Vec<Vec<u8>> = bound.iter().map(|name| states_iter(&vars[*name]).collect()).collect();
+        let combinations = values.iter().try_fold(1usize, |total, values| total.checked_mul(values.len()))
+            .filter(|&total| total <= MAX_TRANSITIONS)
+            .ok_or_else(|| RuleTableError::new(line, "too many combinations of bound variables"))?;
+
+        let mut transitions = Vec::with_capacity(combinations);
+        for combination in 0..combinations {
+            let mut assigned = HashMap::new();
+            let mut rest = combination;
+            for (name, values) in bound.iter().zip(values.iter()) {
+                assigned.insert(*name, values[rest % values.len()]);
+                rest /= values.len();
+            }
+            let resolve = |token: &Token| match token {
+                Token::State(state) => single(*state),
+                Token::Set(states) => *states,
+                Token::Var(name) => assigned.get(name.as_str()).map_or(vars[name], |&state| single(state)),
+            };
+            let output = match &output[0] {
+                Token::State(state) => *state,
+                Token::Var(name) => assigned[name.as_str()],
+                Token::Set(_) => unreachable!(),
+            };
+            transitions.push(Transition { inputs: inputs.iter().map(resolve).collect(), output });
+        }
+        Ok(transitions)
+    }
+
+    // Every arrangement of the neighbours the symmetries allow, in order
+    fn expand_symmetries(transition: Transition, permutations: Option<&[Vec<usize>]>) -> Vec<Transition> {
+        let neighbours = &transition.inputs[1..];
+        let arrangements: Vec<Vec<States>> = match permutations {
+            Some(permutations) if !permutations.is_empty() => permutations.iter()
+                .map(|permutation| permutation.iter().map(|&i| neighbours[i]).collect())
+                .collect(),
+            Some(_) => vec![neighbours.to_vec()],
+            None => Self::distinct_permutations(neighbours),
+        };
+
+        let mut seen = HashSet::new();
+        arrangements.into_iter()
+            .filter(|arrangement| seen.insert(arrangement.clone()))
+            .map(|arrangement| {
+                let mut inputs = vec![transition.inputs[0]];
+                inputs.extend(arrangement);
+                Transition { inputs, output: transition.output }
+            })
+            .collect()
+    }
+
+    // Every distinct ordering of the neighbour sets, for "permute"
+    fn distinct_permutations(neighbours: &[States]) -> Vec<Vec<States>> {
+        let mut order: Vec<States> = neighbours.to_vec();
+        order.sort_unstable();
+        let mut permutations = vec![order.clone()];
+        // Standard next permutation in lexicographic order
+        loop {
+            let pivot = match (1..order.len()).rev().find(|&i| order[i - 1] < order[i]) {
+                Some(i) => i - 1,
+                None => return permutations,
+            };
+            let successor = (pivot + 1..order.len()).rev().find(|&i| order[i] > order[pivot]).unwrap();
+            order.swap(pivot, successor);
+            order[pivot + 1..].reverse();
+            permutations.push(order.clone());
+        }
+    }
+
+    fn parse_tree(lines: &[&str], first_line: usize) -> Result<RuleTable, RuleTableError> {
+        let (mut states, mut neighbours, mut node_count) = (None, None, None);
+        let mut children: Vec<u32> = Vec::new();
+        let mut levels: Vec<usize> = Vec::new();
+
+        for (line, content) in Self::content(lines, first_line) {
+            if let Some((key, value)) = content.split_once('=') {
+                let value = value.trim();
+                match key.trim() {
+                    "num_states" => states = Some(Self::parse_states(value, line)?),
+                    "num_neighbors" => match value {
+                        "4" => neighbours = Some(TableNeighbourhood::VonNeumann),
+                        "8" => neighbours = Some(TableNeighbourhood::Moore),
+                        _ => return Err(RuleTableError::new(line, "num_neighbors must be 4 or 8")),
+                    },
+                    "num_nodes" => node_count = Some(value.parse::<usize>()
+                        .map_err(|_| RuleTableError::new(line, "num_nodes must be a number"))?),
+                    key => return Err(RuleTableError::new(line, format!("unknown setting '{}'", key))),
+                }
+                continue;
+            }
+
+            let states = states.ok_or_else(|| RuleTableError::new(line, "num_states must come before the nodes"))?;
+            let numbers = content.split_whitespace()
+                .map(|number| number.parse::<usize>().map_err(|_| RuleTableError::new(line, format!("'{}' is not a number", number))))
+                .collect::<Result<Vec<usize>, RuleTableError>>()?;
+            if numbers.len() != states + 1 {
+                return Err(RuleTableError::new(line, format!("nodes need a level and {} children", states)));
+            }
+
+            let level = numbers[0];
+            for &child in &numbers[1..] {
+                let valid = match level {
+                    0 => false,
+                    1 => child < states,
+                    _ => child < levels.len() && levels[child] == level - 1,
+                };
+                if !valid {
+                    return Err(RuleTableError::new(line, format!("child {} isn't valid for a level {} node", child, level)));
+                }
+            }
+            levels.push(level);
+            children.extend(numbers[1..].iter().map(|&child| child as u32));
+        }
+
+        let (states, neighbourhood) = match (states, neighbours) {
+            (Some(states), Some(neighbourhood)) => (states, neighbourhood),
+            (None, _) => return Err(RuleTableError::new(0, "tree is missing num_states")),
+            (_, None) => return Err(RuleTableError::new(0, "tree is missing num_neighbors")),
+        };
+        if node_count.map_or(false, |count| count != levels.len()) {
+            return Err(RuleTableError::new(0, format!("num_nodes doesn't match the {} nodes given", levels.len())));
+        }
+        let variables = neighbourhood.offsets().len() + 1;
+        if levels.last() != Some(&variables) {
+            return Err(RuleTableError::new(0, format!("the last node must be the root, at level {}", variables)));
+        }
+
+        Ok(RuleTable {
+            name: String::new(),
+            states,
+            neighbourhood,
+            kind: Kind::Tree(Tree { children, root: (levels.len() - 1) as u32 }),
+        })
+    }
+
+    #[inline(always)]
+    pub fn next_state(&self, centre: u8, neighbours: &[u8]) -> u8 {
+        match &self.kind {
+            Kind::Table(table) => table.next_state(self.states, centre, neighbours),
+            Kind::Tree(tree) => tree.next_state(self.states, self.neighbourhood, centre, neighbours),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    State(u8),
+    Var(String),
+    Set(States),
+}
+
+// Universe for rules loaded from .rule files, with a byte per cell like
+// GenerationsUniverse
+#[wasm_bindgen]
+pub struct RuleTableUniverse {
+    width: usize,
+    height: usize,
+    buffers: [Vec<u8>; 2],
+    curr_index: usize,
+    rule: RuleTable,
+}
+
+// Public methods, exposed to JavaScript via bindgen
+#[wasm_bindgen]
+impl RuleTableUniverse {
+    pub fn new(width: u32, height: u32, rule_file: &str) -> Result<RuleTableUniverse, RuleTableError> {
+        let rule = RuleTable::parse(rule_file)?;
+        Ok(RuleTableUniverse::with_rule(width, height, &rule))
+    }
+
+    pub fn with_rule(width: u32, height: u32, rule: &RuleTable) -> RuleTableUniverse {
+        // Enable logging for panics
+        utils::set_panic_hook();
+        let size = width as usize * height as usize;
+
+        RuleTableUniverse {
+            width: width as usize,
+            height: height as usize,
+            buffers: [vec![0; size], vec![0; size]],
+            curr_index: 0,
+            rule: rule.clone(),
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width as u32
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height as u32
+    }
+
+    pub fn cells(&self) -> *const u8 {
+        self.buffers[self.curr_index].as_ptr()
+    }
+
+    pub fn rule(&self) -> RuleTable {
+        self.rule.clone()
+    }
+
+    pub fn set_rule(&mut self, rule: &RuleTable) {
+        self.rule = rule.clone();
+        // States the new rule doesn't have become the highest one it does
+        let last = (rule.states - 1) as u8;
+        for state in self.buffers[self.curr_index].iter_mut() {
+            if *state > last {
+                *state = last;
+            }
+        }
+    }
+
+    pub fn tick(&mut self) {
+        let next_index = 1 - self.curr_index;
+        let (width, height) = (self.width as isize, self.height as isize);
+        let (current, next) = {
+            let (first, second) = self.buffers.split_at_mut(1);
+            if self.curr_index == 0 { (&first[0], &mut second[0]) } else { (&second[0], &mut first[0]) }
+        };
+
+        let offsets = self.rule.neighbourhood.offsets();
+        let mut neighbours = vec![0; offsets.len()];
+        for (idx, &state) in current.iter().enumerate() {
+            let (row, col) = ((idx / self.width) as isize, (idx % self.width) as isize);
+            for (neighbour, &(dr, dc)) in neighbours.iter_mut().zip(offsets.iter()) {
+                let r = (row + dr as isize).rem_euclid(height) as usize;
+                let c = (col + dc as isize).rem_euclid(width) as usize;
+                *neighbour = current[r * self.width + c];
+            }
+            next[idx] = self.rule.next_state(state, &neighbours);
+        }
+        self.curr_index = next_index;
+    }
+
+    pub fn get_state(&self, row: u32, column: u32) -> u8 {
+        self.buffers[self.curr_index][self.get_index(row as usize, column as usize)]
+    }
+
+    pub fn set_state(&mut self, row: u32, column: u32, state: u8) {
+        let idx = self.get_index(row as usize, column as usize);
+        self.buffers[self.curr_index][idx] = min(state as usize, self.rule.states - 1) as u8;
+    }
+
+    // Toggle between states 0 and 1
+    pub fn toggle_cell(&mut self, row: u32, column: u32) {
+        let idx = self.get_index(row as usize, column as usize);
+        let cell = &mut self.buffers[self.curr_index][idx];
+        *cell = (*cell == 0) as u8;
+    }
+
+    // Copy a two-state pattern in, with its live cells in the given state
+    pub fn insert_pattern(&mut self, pattern: &Pattern, row: u32, column: u32, angle: u32, state: u8) {
+        let (row, column) = (row as usize, column as usize);
+        let max_row = min(row + pattern.angle_height(angle), self.height) - row;
+        let max_col = min(column + pattern.angle_width(angle), self.width) - column;
+        let state = min(state as usize, self.rule.states - 1) as u8;
+
+        for r in 0..max_row {
+            for c in 0..max_col {
+                let u_idx = self.get_index(r + row, c + column);
+                let p_idx = pattern.get_angle_index(r, c, angle);
+                self.buffers[self.curr_index][u_idx] = if pattern.get_cells()[p_idx] { state } else { 0 };
+            }
+        }
+    }
+
+    // Number of cells in each state, indexed by state
+    pub fn state_counts(&self) -> Vec<u32> {
+        let mut counts = vec![0; self.rule.states];
+        for &state in self.buffers[self.curr_index].iter() {
+            counts[state as usize] += 1;
+        }
+        counts
+    }
+
+    pub fn render(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl RuleTableUniverse {
+    #[inline(always)]
+    fn get_index(&self, row: usize, column: usize) -> usize {
+        row * self.width + column
+    }
+
+    // Get all the cell states in the universe
+    pub fn get_cells(&self) -> &[u8] {
+        &self.buffers[self.curr_index]
+    }
+}
+
+impl fmt::Display for RuleTableUniverse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in 0..self.height {
+            for col in 0..self.width {
+                let symbol = match self.buffers[self.curr_index][self.get_index(row, col)] {
+                    0 => '◻',
+                    1 => '◼',
+                    _ => '▣',
+                };
+                write!(f, "{}", symbol)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+
+//                      Testing
+// ======================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Universe;
+
+    const WIREWORLD: &str = "\
+@RULE WireWorld
+# 0 empty, 1 electron head, 2 electron tail, 3 conductor
+@TABLE
+n_states:4
+neighborhood:Moore
+symmetries:permute
+var a={0,1,2,3}
+var b={a}
+var c={a}
+var d={a}
+var e={a}
+var f={a}
+var g={a}
+var h={a}
+var i={0,2,3}
+var j={i}
+var k={i}
+var l={i}
+var m={i}
+var n={i}
+var o={i}
+1,a,b,c,d,e,f,g,h,2
+2,a,b,c,d,e,f,g,h,3
+3,1,i,j,k,l,m,n,o,1
+3,1,1,i,j,k,l,m,n,1
+@COLORS
+1 0 128 255
+";
+
+    const LIFE_TABLE: &str = "\
+n_states:2
+neighborhood:Moore
+symmetries:permute
+var a={0,1}
+var b={0,1}
+var c={0,1}
+var d={0,1}
+var e={0,1}
+var f={0,1}
+var g={0,1}
+var h={0,1}
+0,1,1,1,0,0,0,0,0,1
+1,1,1,0,0,0,0,0,0,1
+1,1,1,1,0,0,0,0,0,1
+1,a,b,c,d,e,f,g,h,0
+";
+
+    // Rule tree for Life, with one node per level and neighbour count
+    fn life_tree() -> String {
+        let mut text = String::from("@RULE LifeTree\n@TREE\nnum_states=2\nnum_neighbors=8\nnum_nodes=45\n");
+        let mut ids = HashMap::new();
+        for level in 1..=9 {
+            // Nodes at this level have seen 9 - level of the neighbours
+            let seen = 9 - level;
+            for count in (0..=seen).rev() {
+                let children: Vec<usize> = if level == 1 {
+                    (0..2).map(|alive| (count == 3 || (alive == 1 && count == 2)) as usize).collect()
+                }
+                else {
+                    (0..2).map(|alive| ids[&(level - 1, count + alive)]).collect()
+                };
+                ids.insert((level, count), ids.len());
+                text.push_str(&format!("{} {} {}\n", level, children[0], children[1]));
+            }
+        }
+        text
+    }
+
+    #[test]
+    fn test_wireworld_signal_travels_along_wire() {
+        let mut universe = RuleTableUniverse::new(20, 5, WIREWORLD).unwrap();
+        assert_eq!(universe.rule().name(), "WireWorld");
+        for col in 2..18 {
+            universe.set_state(2, col, 3);
+        }
+        universe.set_state(2, 3, 1);
+        universe.set_state(2, 2, 2);
+
+        for generation in 1..=10 {
+            universe.tick();
+            assert_eq!(universe.get_state(2, 3 + generation), 1);
+            assert_eq!(universe.get_state(2, 2 + generation), 2);
+            assert_eq!(universe.state_counts(), vec![84, 1, 1, 14]);
+        }
+    }
+
+    #[test]
+    fn test_table_and_tree_match_life() {
+        let table = RuleTable::parse(LIFE_TABLE).unwrap();
+        let tree = RuleTable::parse(&life_tree()).unwrap();
+        let mut life = Universe::new(24, 24);
+        life.insert_pattern(&Pattern::r_pentomino(), 9, 9, 0);
+        let mut universes = [RuleTableUniverse::with_rule(24, 24, &table), RuleTableUniverse::with_rule(24, 24, &tree)];
+        for universe in universes.iter_mut() {
+            universe.insert_pattern(&Pattern::r_pentomino(), 9, 9, 0, 1);
+        }
+
+        for generation in 0..40 {
+            life.tick();
+            let states: Vec<u8> = (0..24 * 24).map(|i| life.get_cells()[i] as u8).collect();
+            for universe in universes.iter_mut() {
+                universe.tick();
+                assert_eq!(universe.get_cells(), &states[..], "generation {}", generation);
+            }
+        }
+    }
+
+    #[test]
+    fn test_rotate4_symmetry() {
+        let rule = RuleTable::parse("n_states:3\nneighborhood:Moore\nsymmetries:rotate4\n0,1,0,0,0,0,0,0,0,2\n").unwrap();
+        // A lone north neighbour, rotated to each side, but not a corner
+        for neighbours in [[1, 0, 0, 0, 0, 0, 0, 0], [0, 0, 1, 0, 0, 0, 0, 0], [0, 0, 0, 0, 0, 0, 1, 0]] {
+            assert_eq!(rule.next_state(0, &neighbours), 2);
+        }
+        assert_eq!(rule.next_state(0, &[0, 1, 0, 0, 0, 0, 0, 0]), 0);
+        // Cells matching no transition keep their state
+        assert_eq!(rule.next_state(1, &[0; 8]), 1);
+    }
+
+    #[test]
+    fn test_compact_transitions_and_bound_variables() {
+        let text = "n_states:3\nneighborhood:vonNeumann\nsymmetries:none\nvar a={1,2}\n0aa0a2\n0,a,{1,2},0,0,a\n";
+        let rule = RuleTable::parse(text).unwrap();
+        // Bound: the first transition needs N and E equal
+        assert_eq!(rule.next_state(0, &[1, 1, 0, 1]), 2);
+        assert_eq!(rule.next_state(0, &[2, 2, 0, 2]), 2);
+        // The output takes the value bound from the inputs
+        assert_eq!(rule.next_state(0, &[2, 1, 0, 0]), 2);
+        assert_eq!(rule.next_state(0, &[1, 2, 0, 0]), 1);
+    }
+
+    #[test]
+    fn test_malformed_files() {
+        let error = |text: &str| RuleTable::parse(text).unwrap_err();
+        assert_eq!(error("@RULE Empty\n@COLORS\n").to_string(), "no @TABLE or @TREE section");
+        assert_eq!(error("neighborhood:Moore\n0,0,0,0,0,0,0,0,0,1\n").line(), 2);
+        assert_eq!(error("n_states:2\nneighborhood:Moore\n0,1,0\n").to_string(), "line 3: transitions need 10 entries, found 3");
+        assert_eq!(error("n_states:2\nneighborhood:Moore\n0,x,0,0,0,0,0,0,0,1\n").to_string(), "line 3: unknown variable 'x'");
+        assert_eq!(error("n_states:2\nneighborhood:Moore\n0,2,0,0,0,0,0,0,0,1\n").to_string(), "line 3: state 2 is out of range");
+        assert_eq!(error("n_states:2\nneighborhood:hexagonal\nsymmetries:rotate4\n0,0,0,0,0,0,0,1\n").line(), 4);
+        assert_eq!(error("@TABLE\nn_states:2\nneighborhood:Moore\nvar a={0,1}\n0,1,1,1,0,0,0,0,0,a\n").line(), 5);
+        assert_eq!(error("@TREE\nnum_states=2\nnum_neighbors=4\nnum_nodes=1\n2 0 1\n").line(), 5);
+        assert!(error("@TREE\nnum_states=2\nnum_neighbors=4\nnum_nodes=1\n1 0 1\n").to_string().contains("root"));
+    }
+
+    #[test]
+    fn test_display_empty_rows() {
+        let universe = RuleTableUniverse::new(0, 3, WIREWORLD).unwrap();
+        assert_eq!(universe.to_string(), "\n\n\n");
+        let universe = RuleTableUniverse::new(4, 0, WIREWORLD).unwrap();
+        assert_eq!(universe.to_string(), "");
+    }
+}
@@ -0,0 +1,394 @@
+use std::{
+    cmp::min,
+    collections::{HashMap, HashSet},
+    fmt,
+};
+use wasm_bindgen::prelude::*;
+
+use crate::{kernel, utils, Pattern, Rule, RuleError, Universe};
+
+// Tiles are TILE_SIZE x TILE_SIZE squares, one u64 per row with bit c
+// holding column c, so a tile row is one word of the bit-sliced kernel
+const TILE_SIZE: i64 = 64;
+
+type Tile = [u64; TILE_SIZE as usize];
+
+// Tile row and column, i.e. the cell coordinates divided by TILE_SIZE
+type TileKey = (i64, i64);
+
+#[inline(always)]
+fn tile_key(row: i64, col: i64) -> TileKey {
+    (row.div_euclid(TILE_SIZE), col.div_euclid(TILE_SIZE))
+}
+
+#[inline(always)]
+fn tile_offset(row: i64, col: i64) -> (usize, u32) {
+    (row.rem_euclid(TILE_SIZE) as usize, col.rem_euclid(TILE_SIZE) as u32)
+}
+
+// Universe on an unbounded plane with signed coordinates. Only tiles
+// holding live cells are stored, so patterns can grow without ever
+// wrapping into themselves, and tiles that die out are freed.
+#[wasm_bindgen]
+pub struct SparseUniverse {
+    tiles: HashMap<TileKey, Box<Tile>>,
+    rule: Rule,
+    generation: u64,
+}
+
+impl SparseUniverse {
+    // Whether a sparse universe can run a rule
+    pub(crate) fn check_rule(rule: &Rule) -> Result<(), RuleError> {
+        // A rule with B0 would fill the infinite empty plane in one step
+        if rule.births(0) {
+            return Err(RuleError::Unsupported("B0 rules on an unbounded plane"));
+        }
+        // Tiles only look one cell into their neighbours
+        if rule.neighbourhood().moore_subset().is_none() {
+            return Err(RuleError::Unsupported("neighbourhoods wider than 3x3 in a sparse universe"));
+        }
+        Ok(())
+    }
+
+    fn cell_bit(&self, row: i64, col: i64) -> bool {
+        let (r, c) = tile_offset(row, col);
+        self.tiles.get(&tile_key(row, col)).map_or(false, |tile| tile[r] >> c & 1 != 0)
+    }
+
+    // Tiles that could hold live cells next generation: every live tile,
+    // and the neighbouring tiles across any edge with live cells on it
+    fn candidate_tiles(&self) -> HashSet<TileKey> {
+        let mut candidates = HashSet::with_capacity(self.tiles.len() * 2);
+        for (&(tile_row, tile_col), tile) in self.tiles.iter() {
+            let north = tile[0] != 0;
+            let south = tile[tile.len() - 1] != 0;
+            let west = tile.iter().any(|word| word & 1 != 0);
+            let east = tile.iter().any(|word| word >> 63 != 0);
+
+            for (dr, rows) in [(-1, north), (0, true), (1, south)] {
+                for (dc, cols) in [(-1, west), (0, true), (1, east)] {
+                    if rows && cols {
+                        candidates.insert((tile_row + dr, tile_col + dc));
+                    }
+                }
+            }
+        }
+        candidates
+    }
+
+    // Next generation of one tile, reading the edges of its neighbours
+    fn step_tile(&self, (tile_row, tile_col): TileKey, masks: &[u64; 8]) -> Tile {
+        let mut around: [[Option<&Tile>; 3]; 3] = [[None; 3]; 3];
+        for (dr, row) in around.iter_mut().enumerate() {
+            for (dc, tile) in row.iter_mut().enumerate() {
+                let key = (tile_row + dr as i64 - 1, tile_col + dc as i64 - 1);
+                *tile = self.tiles.get(&key).map(|tile| &**tile);
+            }
+        }
+
+        // Rows -1 to TILE_SIZE, each with its west and east shifted copies
+        let mut rows = [(0, 0, 0); TILE_SIZE as usize + 2];
+        for (i, shifted) in rows.iter_mut().enumerate() {
+            let row = i as i64 - 1;
+            let (dr, r) = ((row.div_euclid(TILE_SIZE) + 1) as usize, row.rem_euclid(TILE_SIZE) as usize);
+            let word = |dc: usize| around[dr][dc].map_or(0, |tile| tile[r]);
+            let centre = word(1);
+            *shifted = ((centre << 1) | (word(0) >> 63), centre, (centre >> 1) | (word(2) << 63));
+        }
+
+        let mut next = [0; TILE_SIZE as usize];
+        for (r, word) in next.iter_mut().enumerate() {
+            let (north, centre, south) = (rows[r], rows[r + 1], rows[r + 2]);
+            let mut neighbours = [north.0, north.1, north.2, centre.0, centre.2, south.0, south.1, south.2];
+            for (neighbour, mask) in neighbours.iter_mut().zip(masks) {
+                *neighbour &= mask;
+            }
+            *word = kernel::next_word(&self.rule, centre.1, neighbours);
+        }
+        next
+    }
+
+    // Row and column of every live cell, in no particular order
+    pub fn live_cells(&self) -> impl Iterator<Item = (i64, i64)> + '_ {
+        self.tiles.iter().flat_map(|(&(tile_row, tile_col), tile)| {
+            tile.iter().enumerate().flat_map(move |(r, &word)| {
+                (0..TILE_SIZE).filter(move |&c| word >> c & 1 != 0)
+                    .map(move |c| (tile_row * TILE_SIZE + r as i64, tile_col * TILE_SIZE + c))
+            })
+        })
+    }
+
+    // Call f with the row and column of every live cell in the window
+    // [top, top + height) x [left, left + width)
+    pub fn live_cells_in<F: FnMut(i64, i64)>(&self, top: i64, left: i64, height: i64, width: i64, mut f: F) {
+        if height <= 0 || width <= 0 {
+            return;
+        }
+        let (first, last) = (tile_key(top, left), tile_key(top + height - 1, left + width - 1));
+        for tile_row in first.0..=last.0 {
+            for tile_col in first.1..=last.1 {
+                let tile = match self.tiles.get(&(tile_row, tile_col)) {
+                    Some(tile) => tile,
+                    None => continue,
+                };
+                for (r, &word) in tile.iter().enumerate() {
+                    let row = tile_row * TILE_SIZE + r as i64;
+                    if word == 0 || row < top || row >= top + height {
+                        continue;
+                    }
+                    for c in 0..TILE_SIZE {
+                        let col = tile_col * TILE_SIZE + c;
+                        if word >> c & 1 != 0 && col >= left && col < left + width {
+                            f(row, col);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Public methods, exposed to JavaScript via bindgen
+#[wasm_bindgen]
+impl SparseUniverse {
+    pub fn new() -> SparseUniverse {
+        // Enable logging for panics
+        utils::set_panic_hook();
+        SparseUniverse { tiles: HashMap::new(), rule: Rule::default(), generation: 0 }
+    }
+
+    // Copy the live cells and rule of a universe, keeping their row and
+    // column
+    pub fn from_universe(universe: &Universe) -> Result<SparseUniverse, RuleError> {
+        let mut sparse = SparseUniverse::new();
+        sparse.set_rule(&universe.rule)?;
+        let width = universe.width;
+        for idx in universe.get_cells().ones() {
+            sparse.set_cell((idx / width) as i64, (idx % width) as i64, true);
+        }
+        Ok(sparse)
+    }
+
+    // Copy the window of cells starting at (top, left) into a new universe
+    pub fn to_universe(&self, top: i64, left: i64, width: u32, height: u32) -> Universe {
+        let mut universe = Universe::new(width, height);
+        self.live_cells_in(top, left, height as i64, width as i64, |row, col| {
+            universe.toggle_cell((row - top) as u32, (col - left) as u32);
+        });
+        universe.set_rule(&self.rule);
+        universe
+    }
+
+    pub fn rule(&self) -> Rule {
+        self.rule
+    }
+
+    pub fn set_rule(&mut self, rule: &Rule) -> Result<(), RuleError> {
+        Self::check_rule(rule)?;
+        self.rule = *rule;
+        Ok(())
+    }
+
+    pub fn set_rulestring(&mut self, rulestring: &str) -> Result<(), RuleError> {
+        let rule = Rule::parse(rulestring)?;
+        self.set_rule(&rule)
+    }
+
+    pub fn tick(&mut self) {
+        let included = self.rule.neighbourhood().moore_subset().unwrap_or([true; 8]);
+        let masks = included.map(|include| if include { u64::MAX } else { 0 });
+
+        let mut tiles = HashMap::with_capacity(self.tiles.len());
+        for key in self.candidate_tiles() {
+            let tile = self.step_tile(key, &masks);
+            // Tiles with no live cells left are dropped
+            if tile.iter().any(|&word| word != 0) {
+                tiles.insert(key, Box::new(tile));
+            }
+        }
+        self.tiles = tiles;
+        self.generation += 1;
+    }
+
+    pub fn get_cell(&self, row: i64, col: i64) -> bool {
+        self.cell_bit(row, col)
+    }
+
+    pub fn set_cell(&mut self, row: i64, col: i64, alive: bool) {
+        let key = tile_key(row, col);
+        let (r, c) = tile_offset(row, col);
+        if alive {
+            self.tiles.entry(key).or_insert_with(|| Box::new([0; TILE_SIZE as usize]))[r] |= 1 << c;
+        }
+        else if let Some(tile) = self.tiles.get_mut(&key) {
+            tile[r] &= !(1 << c);
+            if tile.iter().all(|&word| word == 0) {
+                self.tiles.remove(&key);
+            }
+        }
+    }
+
+    pub fn toggle_cell(&mut self, row: i64, col: i64) {
+        let alive = self.cell_bit(row, col);
+        self.set_cell(row, col, !alive);
+    }
+
+    pub fn insert_pattern(&mut self, pattern: &Pattern, row: i64, column: i64, angle: u32) {
+        for r in 0..pattern.angle_height(angle) {
+            for c in 0..pattern.angle_width(angle) {
+                let p_idx = pattern.get_angle_index(r, c, angle);
+                self.set_cell(row + r as i64, column + c as i64, pattern.get_cells()[p_idx]);
+            }
+        }
+    }
+
+    // Render the window of cells starting at (top, left) as text
+    pub fn render_region(&self, top: i64, left: i64, width: u32, height: u32) -> String {
+        let mut text = String::new();
+        for row in top..top + height as i64 {
+            for col in left..left + width as i64 {
+                text.push(if self.cell_bit(row, col) { '◼' } else { '◻' });
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    // Smallest box around every live cell, as [top, left, bottom, right]
+    // with bottom and right inclusive, or empty if nothing is alive
+    pub fn bounds(&self) -> Vec<i64> {
+        let mut bounds: Option<[i64; 4]> = None;
+        for (&(tile_row, tile_col), tile) in self.tiles.iter() {
+            for (r, &word) in tile.iter().enumerate().filter(|(_, &word)| word != 0) {
+                let row = tile_row * TILE_SIZE + r as i64;
+                let first = tile_col * TILE_SIZE + word.trailing_zeros() as i64;
+                let last = tile_col * TILE_SIZE + 63 - word.leading_zeros() as i64;
+                bounds = Some(match bounds {
+                    Some([top, left, bottom, right]) => [min(top, row), min(left, first), bottom.max(row), right.max(last)],
+                    None => [row, first, row, last],
+                });
+            }
+        }
+        bounds.map_or(Vec::new(), |bounds| bounds.to_vec())
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn population(&self) -> u64 {
+        self.tiles.values()
+            .map(|tile| tile.iter().map(|word| word.count_ones() as u64).sum::<u64>())
+            .sum()
+    }
+
+    // Number of tiles currently allocated
+    pub fn tile_count(&self) -> u32 {
+        self.tiles.len() as u32
+    }
+}
+
+impl Default for SparseUniverse {
+    fn default() -> SparseUniverse {
+        SparseUniverse::new()
+    }
+}
+
+impl fmt::Display for SparseUniverse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let [top, left, bottom, right] = self.bounds()[..] {
+            let (width, height) = ((right - left + 1) as u32, (bottom - top + 1) as u32);
+            write!(f, "{}", self.render_region(top, left, width, height))?;
+        }
+        Ok(())
+    }
+}
+
+
+//                      Testing
+// ======================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HashLife;
+
+    #[test]
+    fn test_set_and_get_cell() {
+        let mut sparse = SparseUniverse::new();
+        for (row, col) in [(0, 0), (-1, -1), (63, 64), (-1000, 2000)] {
+            sparse.set_cell(row, col, true);
+            assert!(sparse.get_cell(row, col));
+        }
+        assert_eq!(sparse.population(), 4);
+        assert_eq!(sparse.tile_count(), 4);
+        assert_eq!(sparse.bounds(), vec![-1000, -1, 63, 2000]);
+
+        sparse.toggle_cell(-1000, 2000);
+        assert!(!sparse.get_cell(-1000, 2000));
+        assert_eq!(sparse.tile_count(), 3);
+    }
+
+    #[test]
+    fn test_matches_universe() {
+        let mut universe = Universe::new(256, 256);
+        universe.insert_pattern(&Pattern::r_pentomino(), 126, 126, 0);
+        let mut sparse = SparseUniverse::from_universe(&universe).unwrap();
+
+        for generation in 0..150 {
+            universe.tick();
+            sparse.tick();
+            assert_eq!(sparse.to_universe(0, 0, 256, 256).get_cells(), universe.get_cells(), "generation {}", generation);
+        }
+    }
+
+    #[test]
+    fn test_glider_crosses_tiles_and_frees_them() {
+        let mut sparse = SparseUniverse::new();
+        sparse.insert_pattern(&Pattern::glider(), -3, -3, 0);
+        let mut hashlife = HashLife::new();
+        for (row, col) in sparse.live_cells() {
+            hashlife.set_cell(row, col, true).unwrap();
+        }
+
+        for _ in 0..600 {
+            sparse.tick();
+            // A glider never spans more than a 2x2 block of tiles
+            assert!(sparse.tile_count() <= 4);
+        }
+        hashlife.advance(600).unwrap();
+
+        assert_eq!(sparse.population(), 5);
+        let [top, left, bottom, right] = match sparse.bounds()[..] {
+            [top, left, bottom, right] => [top, left, bottom, right],
+            _ => panic!("glider died"),
+        };
+        let window = hashlife.to_universe(top - 1, left - 1, (right - left + 3) as u32, (bottom - top + 3) as u32);
+        assert_eq!(sparse.to_universe(top - 1, left - 1, (right - left + 3) as u32, (bottom - top + 3) as u32).get_cells(), window.get_cells());
+    }
+
+    #[test]
+    fn test_gun_grows_without_wrapping() {
+        let mut sparse = SparseUniverse::new();
+        sparse.insert_pattern(&Pattern::gosper_glider_gun(), 0, 0, 0);
+        let mut hashlife = HashLife::new();
+        for (row, col) in sparse.live_cells() {
+            hashlife.set_cell(row, col, true).unwrap();
+        }
+
+        for _ in 0..600 {
+            sparse.tick();
+        }
+        hashlife.advance(600).unwrap();
+        assert_eq!(sparse.population(), hashlife.population());
+        assert!(sparse.tile_count() > 1);
+    }
+
+    #[test]
+    fn test_rejects_unsupported_rules() {
+        let mut sparse = SparseUniverse::new();
+        assert!(sparse.set_rulestring("B03/S23").is_err());
+        assert!(sparse.set_rulestring("B2/S34H").is_ok());
+        let wide = Rule::conway().with_neighbourhood(&crate::Neighbourhood::custom(&[-2, 0]).unwrap());
+        assert!(sparse.set_rule(&wide).is_err());
+    }
+}
@@ -0,0 +1,57 @@
+use std::collections::VecDeque;
+use fixedbitset::FixedBitSet;
+
+// Default number of generations kept in the population history
+pub(crate) const DEFAULT_HISTORY_LENGTH: usize = 1000;
+
+// Population statistics, updated every tick
+#[derive(Clone, Debug)]
+pub(crate) struct Stats {
+    pub births: u32,
+    pub deaths: u32,
+    // Population after each of the most recent ticks, with the generation
+    // each tick reached
+    history: VecDeque<(u64, u32)>,
+    history_length: usize,
+}
+
+impl Stats {
+    pub fn new() -> Stats {
+        Stats { births: 0, deaths: 0, history: VecDeque::new(), history_length: DEFAULT_HISTORY_LENGTH }
+    }
+
+    // Record a tick from one generation's cells to the next
+    pub fn record(&mut self, previous: &FixedBitSet, current: &FixedBitSet, generation: u64) {
+        self.births = current.difference_count(previous) as u32;
+        self.deaths = previous.difference_count(current) as u32;
+        // After going back, the later generations no longer happened
+        self.truncate(generation.saturating_sub(1));
+        self.history.push_back((generation, current.count_ones(..) as u32));
+        while self.history.len() > self.history_length {
+            self.history.pop_front();
+        }
+    }
+
+    // Forget the populations of generations after the given one
+    pub fn truncate(&mut self, generation: u64) {
+        while self.history.back().map_or(false, |&(g, _)| g > generation) {
+            self.history.pop_back();
+        }
+    }
+
+    pub fn set_history_length(&mut self, length: usize) {
+        self.history_length = length;
+        while self.history.len() > length {
+            self.history.pop_front();
+        }
+    }
+
+    pub fn history(&self) -> Vec<u32> {
+        self.history.iter().map(|&(_, population)| population).collect()
+    }
+
+    // Generation of the first population in the history
+    pub fn history_start(&self) -> Option<u64> {
+        self.history.front().map(|&(generation, _)| generation)
+    }
+}
@@ -0,0 +1,334 @@
+use std::{
+    fmt,
+    str::FromStr,
+};
+use wasm_bindgen::prelude::*;
+
+use crate::RuleError;
+
+// How the edges of a bounded grid are joined, following Golly's bounded
+// grid modes
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Boundary {
+    // Opposite edges joined, optionally with a shift
+    Torus,
+    // Cells beyond the edges are always dead
+    Plane,
+    // Opposite edges joined, one pair of them with a twist
+    KleinBottle,
+    // Opposite edges joined, both pairs with a twist
+    CrossSurface,
+    // Top edge joined to the left edge and bottom edge to the right edge,
+    // so the grid must be square
+    Sphere,
+}
+
+// Boundary together with the size and edge details a rulestring suffix
+// like ":T100,100", ":P" or ":K10*,20" can give. A width or height of 0
+// means it wasn't given, and the universe keeps its own size.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Topology {
+    boundary: Boundary,
+    width: u32,
+    height: u32,
+    // Columns moved when crossing the top or bottom edge of a torus
+    horizontal_shift: i32,
+    // Rows moved when crossing the left or right edge of a torus
+    vertical_shift: i32,
+    // Whether a Klein bottle's top and bottom edges are the twisted pair,
+    // rather than its left and right edges
+    twisted_horizontal: bool,
+}
+
+#[wasm_bindgen]
+impl Topology {
+    pub fn torus() -> Topology {
+        Self::with_boundary(Boundary::Torus)
+    }
+
+    pub fn plane() -> Topology {
+        Self::with_boundary(Boundary::Plane)
+    }
+
+    pub fn klein_bottle(twisted_horizontal: bool) -> Topology {
+        Topology { twisted_horizontal, ..Self::with_boundary(Boundary::KleinBottle) }
+    }
+
+    pub fn cross_surface() -> Topology {
+        Self::with_boundary(Boundary::CrossSurface)
+    }
+
+    pub fn sphere() -> Topology {
+        Self::with_boundary(Boundary::Sphere)
+    }
+
+    // Torus whose top and bottom edges are joined with a horizontal shift,
+    // or whose left and right edges are joined with a vertical one. Like
+    // Golly, only one of the two may be shifted.
+    pub fn shifted_torus(horizontal_shift: i32, vertical_shift: i32) -> Result<Topology, RuleError> {
+        if horizontal_shift != 0 && vertical_shift != 0 {
+            return Err(RuleError::Unsupported("shifts on both pairs of edges"));
+        }
+        Ok(Topology { horizontal_shift, vertical_shift, ..Self::torus() })
+    }
+
+    // Parse a bounded grid suffix, with or without its leading ':'
+    pub fn parse(suffix: &str) -> Result<Topology, RuleError> {
+        let suffix = suffix.trim();
+        let suffix = suffix.strip_prefix(':').unwrap_or(suffix);
+        let mut chars = suffix.chars();
+        let mut topology = match chars.next().map(|c| c.to_ascii_uppercase()) {
+            Some('T') => Self::torus(),
+            Some('P') => Self::plane(),
+            Some('K') => Self::klein_bottle(true),
+            Some('C') => Self::cross_surface(),
+            Some('S') => Self::sphere(),
+            Some(c) => return Err(RuleError::UnexpectedChar(c)),
+            None => return Err(RuleError::Empty),
+        };
+
+        let dimensions = chars.as_str();
+        if dimensions.is_empty() {
+            return Ok(topology);
+        }
+        let (width, height) = dimensions.split_once(',').ok_or(RuleError::MissingSeparator)?;
+        let (width, width_twisted, width_shift) = Self::parse_edge(width)?;
+        let (height, height_twisted, height_shift) = Self::parse_edge(height)?;
+        topology.width = width;
+        topology.height = height;
+
+        match topology.boundary {
+            Boundary::Torus if !width_twisted && !height_twisted => {
+                let shifted = Self::shifted_torus(width_shift, height_shift)?;
+                topology.horizontal_shift = shifted.horizontal_shift;
+                topology.vertical_shift = shifted.vertical_shift;
+            },
+            Boundary::KleinBottle if width_shift == 0 && height_shift == 0 && width_twisted != height_twisted => {
+                topology.twisted_horizontal = width_twisted;
+            },
+            Boundary::KleinBottle if width_twisted == height_twisted => {
+                return Err(RuleError::Unsupported("Klein bottles without exactly one twisted pair of edges"));
+            },
+            Boundary::Sphere if width != height => {
+                return Err(RuleError::Unsupported("spheres that aren't square"));
+            },
+            Boundary::Plane | Boundary::CrossSurface | Boundary::Sphere if !width_twisted && !height_twisted && width_shift == 0 && height_shift == 0 => {},
+            _ => return Err(RuleError::Unsupported("twists or shifts on these edges")),
+        }
+        Ok(topology)
+    }
+
+    pub fn boundary(&self) -> Boundary {
+        self.boundary
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    #[wasm_bindgen(js_name = toString)]
+    pub fn suffix(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl Topology {
+    fn with_boundary(boundary: Boundary) -> Topology {
+        Topology {
+            boundary,
+            width: 0,
+            height: 0,
+            horizontal_shift: 0,
+            vertical_shift: 0,
+            twisted_horizontal: false,
+        }
+    }
+
+    // Size, twist marker and shift of one edge, e.g. "100", "10*" or "30+5"
+    fn parse_edge(edge: &str) -> Result<(u32, bool, i32), RuleError> {
+        let digits = edge.find(|c: char| !c.is_ascii_digit()).unwrap_or(edge.len());
+        let size = edge[..digits].parse::<u32>().map_err(|_| RuleError::MissingSeparator)?;
+        if size == 0 {
+            return Err(RuleError::Unsupported("unbounded edges"));
+        }
+
+        let mut rest = &edge[digits..];
+        let twisted = rest.starts_with('*');
+        if twisted {
+            rest = &rest[1..];
+        }
+        let shift = match rest.chars().next() {
+            None => 0,
+            Some('+') | Some('-') => rest.parse::<i32>().map_err(|_| RuleError::UnexpectedChar(rest.chars().next().unwrap()))?,
+            Some(c) => return Err(RuleError::UnexpectedChar(c)),
+        };
+        Ok((size, twisted, shift))
+    }
+
+    // The same topology with its size filled in
+    pub(crate) fn sized(&self, width: usize, height: usize) -> Topology {
+        Topology { width: width as u32, height: height as u32, ..*self }
+    }
+
+    // A torus with no shift, which the fast neighbour paths assume
+    pub fn is_plain_torus(&self) -> bool {
+        self.boundary == Boundary::Torus && self.horizontal_shift == 0 && self.vertical_shift == 0
+    }
+
+    // Whether crossing an edge only ever translates a neighbourhood, so
+    // the cells that see a given cell are found at the negated offsets.
+    // Twisted and spherical edges can reflect or transpose it as well.
+    pub fn is_translational(&self) -> bool {
+        matches!(self.boundary, Boundary::Torus | Boundary::Plane)
+    }
+
+    // Map a position a few cells outside the grid back onto it, or None
+    // if the position is off the edge of a plane or a sphere's corner
+    pub fn wrap(&self, row: isize, col: isize, width: usize, height: usize) -> Option<(usize, usize)> {
+        let (w, h) = (width as isize, height as isize);
+        let (row, col) = match self.boundary {
+            Boundary::Torus if self.vertical_shift != 0 => {
+                let crossings = col.div_euclid(w);
+                (row + crossings * self.vertical_shift as isize, col)
+            },
+            Boundary::Torus => {
+                let crossings = row.div_euclid(h);
+                (row, col + crossings * self.horizontal_shift as isize)
+            },
+            Boundary::Plane => (row, col),
+            Boundary::KleinBottle | Boundary::CrossSurface => {
+                // Each crossing of a twisted pair of edges reflects the
+                // position along that edge
+                let cross = self.boundary == Boundary::CrossSurface;
+                let flip_cols = (cross || self.twisted_horizontal) && row.div_euclid(h) % 2 != 0;
+                let flip_rows = (cross || !self.twisted_horizontal) && col.div_euclid(w) % 2 != 0;
+                (if flip_rows { h - 1 - row } else { row }, if flip_cols { w - 1 - col } else { col })
+            },
+            Boundary::Sphere => {
+                let outside_rows = row < 0 || row >= h;
+                let outside_cols = col < 0 || col >= w;
+                match (outside_rows, outside_cols) {
+                    (true, true) => return None,
+                    (true, false) if row < 0 => (col, -row - 1),
+                    (true, false) => (col, 2 * w - 1 - row),
+                    (false, true) if col < 0 => (-col - 1, row),
+                    (false, true) => (2 * h - 1 - col, row),
+                    (false, false) => (row, col),
+                }
+            },
+        };
+
+        let (row, col) = match self.boundary {
+            Boundary::Torus | Boundary::KleinBottle | Boundary::CrossSurface => (row.rem_euclid(h), col.rem_euclid(w)),
+            Boundary::Plane | Boundary::Sphere => (row, col),
+        };
+        if (0..h).contains(&row) && (0..w).contains(&col) {
+            Some((row as usize, col as usize))
+        }
+        else {
+            None
+        }
+    }
+}
+
+impl Default for Topology {
+    fn default() -> Topology {
+        Topology::torus()
+    }
+}
+
+impl FromStr for Topology {
+    type Err = RuleError;
+
+    fn from_str(s: &str) -> Result<Topology, RuleError> {
+        Topology::parse(s)
+    }
+}
+
+impl fmt::Display for Topology {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let letter = match self.boundary {
+            Boundary::Torus => 'T',
+            Boundary::Plane => 'P',
+            Boundary::KleinBottle => 'K',
+            Boundary::CrossSurface => 'C',
+            Boundary::Sphere => 'S',
+        };
+        write!(f, ":{}", letter)?;
+        if self.width == 0 || self.height == 0 {
+            return Ok(());
+        }
+
+        let klein = self.boundary == Boundary::KleinBottle;
+        let edge = |f: &mut fmt::Formatter<'_>, size: u32, twisted: bool, shift: i32| {
+            write!(f, "{}", size)?;
+            if twisted {
+                write!(f, "*")?;
+            }
+            if shift != 0 {
+                write!(f, "{:+}", shift)?;
+            }
+            Ok(())
+        };
+        edge(f, self.width, klein && self.twisted_horizontal, self.horizontal_shift)?;
+        write!(f, ",")?;
+        edge(f, self.height, klein && !self.twisted_horizontal, self.vertical_shift)
+    }
+}
+
+
+//                      Testing
+// ======================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_round_trip() {
+        for suffix in [":T100,100", ":P", ":P30,20", ":K10*,20", ":K10,20*", ":C8,6", ":S12,12", ":T30+5,20", ":T30,20-3"] {
+            let topology = Topology::parse(suffix).unwrap();
+            assert_eq!(topology.to_string(), suffix);
+        }
+        assert_eq!(Topology::parse("t100,100").unwrap(), Topology::parse(":T100,100").unwrap());
+        assert_eq!(Topology::parse(":K").unwrap().boundary(), Boundary::KleinBottle);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(Topology::parse(":X"), Err(RuleError::UnexpectedChar('X')));
+        assert_eq!(Topology::parse(":T100"), Err(RuleError::MissingSeparator));
+        assert!(Topology::parse(":T10+1,10+1").is_err());
+        assert!(Topology::parse(":K10,20").is_err());
+        assert!(Topology::parse(":S10,20").is_err());
+        assert!(Topology::parse(":P10*,20").is_err());
+        assert!(Topology::parse(":T0,20").is_err());
+    }
+
+    #[test]
+    fn test_wrap() {
+        let (w, h) = (10, 8);
+        assert_eq!(Topology::torus().wrap(-1, -1, w, h), Some((7, 9)));
+        assert_eq!(Topology::plane().wrap(-1, 3, w, h), None);
+        assert_eq!(Topology::plane().wrap(2, 3, w, h), Some((2, 3)));
+        assert_eq!(Topology::shifted_torus(2, 0).unwrap().wrap(8, 9, w, h), Some((0, 1)));
+        assert_eq!(Topology::shifted_torus(0, -1).unwrap().wrap(0, 10, w, h), Some((7, 0)));
+        assert_eq!(Topology::klein_bottle(true).wrap(-1, 2, w, h), Some((7, 7)));
+        assert_eq!(Topology::klein_bottle(true).wrap(2, -1, w, h), Some((2, 9)));
+        assert_eq!(Topology::klein_bottle(false).wrap(2, -1, w, h), Some((5, 9)));
+        assert_eq!(Topology::cross_surface().wrap(-1, -1, w, h), Some((0, 0)));
+
+        // The top edge meets the left edge, the bottom edge the right edge
+        let sphere = Topology::sphere();
+        assert_eq!(sphere.wrap(-1, 3, 8, 8), Some((3, 0)));
+        assert_eq!(sphere.wrap(3, -1, 8, 8), Some((0, 3)));
+        assert_eq!(sphere.wrap(8, 3, 8, 8), Some((3, 7)));
+        assert_eq!(sphere.wrap(3, 8, 8, 8), Some((7, 3)));
+        assert_eq!(sphere.wrap(-1, -1, 8, 8), None);
+    }
+}
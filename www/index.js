@@ -1,4 +1,4 @@
-import { Universe, Pattern } from "game-of-life";
+import { Universe, Pattern, Anchor, PatternKind, analyse_pattern } from "game-of-life";
 // Import the WebAssembly memory
 import { memory } from "game-of-life/game_of_life_bg";
 import { startup, onFrame, drawCellsFrame, clearCellsCanvas, setSquareSize, onGridSizeChanged } from "./modules/webgl.js";
@@ -27,6 +27,9 @@ const borderCheckbox = document.getElementById("cell_border");
 const cellSizeSelect = document.getElementById("cell_size");
 const patternSelect = document.getElementById("pattern");
 const rotation = document.getElementById("rotation");
+const speedSelect = document.getElementById("speed");
+const autoStopCheckbox = document.getElementById("auto_stop");
+const patternInfo = document.getElementById("pattern-info");
 // Get various canvases by ID
 const gameCanvas = document.getElementById("game-layer");
 //const ctx = gameCanvas.getContext("2d");
@@ -108,11 +111,33 @@ window.addEventListener("load", event => {
     startup();
     // Setup cell size and start rendering
     setCellSize();
-    setCanvasSizeFull();
+    setCanvasSizeFull(false);
     play();
 });
 // ================================================
 
+// Start of the cycle auto stop last paused for, so playing on from there
+// doesn't pause again straight away
+let stoppedForCycle = null;
+
+// Watch for cycles only while auto stop is on
+const setCycleDetection = () => {
+    if (autoStopCheckbox.checked) {
+        universe.enable_cycle_detection(1000);
+    }
+    else {
+        universe.disable_cycle_detection();
+    }
+    stoppedForCycle = null;
+};
+
+// Replace the universe, carrying over the speed and cycle detection
+const setUniverse = (newUniverse) => {
+    universe = newUniverse;
+    universe.set_step_exponent(parseInt(speedSelect.value));
+    setCycleDetection();
+};
+
 // Method to set cell size and cell border size
 const setCellSize = () => {
     CELL_SIZE = parseInt(cellSizeSelect.value);
@@ -125,8 +150,9 @@ const setCellSize = () => {
     setSquareSize(CELL_SIZE);
 };
 
-// Method to set grid size based on cell size 
-const setCanvasSizeFull = () => {
+// Method to set grid size based on cell size. Unless keepCells is false
+// the current cells are kept, centred in the new grid.
+const setCanvasSizeFull = (keepCells = true) => {
     let gridSize = Math.floor(0.9 * window.innerHeight / CELL_BORDER);
     width = gridSize;
     height = gridSize;
@@ -140,18 +166,32 @@ const setCanvasSizeFull = () => {
     foreCanvas.height = canvasSize;
     foreCanvas.width = canvasSize;
     drawGrid();
-    universe = Universe.new_rand(width, height);
+    if (keepCells) {
+        universe.resize(width, height, Anchor.Centre);
+    }
+    else {
+        setUniverse(Universe.new_rand(width, height));
+    }
     onGridSizeChanged(width, height);
 }
 
 // Render loop, runs each frame
 const renderLoop = () => {
     fps.render();
-    universe.tick();
+    // Run 2^k generations per frame, or one every 2^-k frames
+    universe.step();
 
     //onFrame();
     drawCells();
 
+    // Stop once the universe has settled into a cycle
+    const report = universe.cycle_report();
+    if (report && report.start_generation() !== stoppedForCycle) {
+        stoppedForCycle = report.start_generation();
+        pause();
+        return;
+    }
+
     animationId = requestAnimationFrame(renderLoop);
 };
 
@@ -240,19 +280,29 @@ stepButton.addEventListener("click", event => {
 
 // Event listener for reset button
 resetButton.addEventListener("click", event => {
-    universe = Universe.new_rand(width, height);
+    setUniverse(Universe.new_rand(width, height));
 
     drawCells();
 });
 
 // Event listener for clear button
 clearButton.addEventListener("click", event => {
-    universe = Universe.new(width, height);
+    setUniverse(Universe.new(width, height));
 
     // Redraw the scene, in case we're currently paused
     drawCells();
 });
 
+// Event listener for the auto stop checkbox
+autoStopCheckbox.addEventListener("change", event => {
+    setCycleDetection();
+});
+
+// Event listener for the speed select dropdown
+speedSelect.addEventListener("change", event => {
+    universe.set_step_exponent(parseInt(speedSelect.value));
+});
+
 // Event listener for grid button 
 gridButton.addEventListener("click", event => {
     showGrid = !showGrid;
@@ -337,11 +387,65 @@ patternSelect.addEventListener("change", event => {
         case "eater1":
             pattern = Pattern.eater_one();
             break;
+        case "paste":
+            pattern = readPastedPattern();
+            break;
         default:
             pattern = null;
     }
+    showPatternInfo();
 });
 
+// Ask for a pattern in RLE, plaintext or Life 1.05/1.06 format, e.g.
+// copied from LifeWiki
+const readPastedPattern = () => {
+    const text = prompt("Paste a pattern in RLE, plaintext or Life 1.05/1.06 format");
+    if (text == null) {
+        return null;
+    }
+    try {
+        return Pattern.from_text(text);
+    } catch (error) {
+        alert(`Couldn't read the pattern: ${error.message}`);
+        return null;
+    }
+};
+
+// Describe the selected pattern: what it is, its size, population and heat
+const showPatternInfo = () => {
+    if (pattern == null) {
+        patternInfo.textContent = "";
+        return;
+    }
+    let analysis;
+    try {
+        analysis = analyse_pattern(pattern, 1000);
+    } catch (error) {
+        // Patterns under rules like B0 ones can't be run on their own
+        patternInfo.textContent = "";
+        return;
+    }
+    let kind;
+    switch (analysis.kind()) {
+        case PatternKind.StillLife:
+            kind = "Still life";
+            break;
+        case PatternKind.Oscillator:
+            kind = `Oscillator, period ${analysis.period()}`;
+            break;
+        case PatternKind.Spaceship:
+            kind = `Spaceship, ${analysis.speed()}, period ${analysis.period()}`;
+            break;
+        default:
+            kind = "Other";
+    }
+    patternInfo.textContent = `
+${kind}
+bounding box = ${analysis.width()}x${analysis.height()}
+population = ${analysis.min_population()}-${analysis.max_population()}
+heat = ${analysis.heat().toFixed(1)}`.trim();
+};
+
 // Event listener for canvas, to toggle cells
 foreCanvas.addEventListener("click", event => {
     const boundingRect = foreCanvas.getBoundingClientRect();